@@ -0,0 +1,23 @@
+-- Add migration script here
+CREATE TABLE newsletter_issues(
+  id uuid NOT NULL,
+  title TEXT NOT NULL,
+  text_content TEXT NOT NULL,
+  html_content TEXT NOT NULL,
+  track_opens BOOLEAN NOT NULL,
+  published_at timestamptz NOT NULL,
+  PRIMARY KEY (id)
+);
+
+CREATE TABLE issue_opens(
+  newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+  -- Only set by opens which look like they came from the recipient
+  first_opened_at timestamptz NULL,
+  last_opened_at timestamptz NULL,
+  open_count INTEGER NOT NULL DEFAULT 0,
+  machine_open_count INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
@@ -0,0 +1,24 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE issue_links(
+  newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  link_id INTEGER NOT NULL,
+  url TEXT NOT NULL,
+  PRIMARY KEY (newsletter_issue_id, link_id)
+);
+
+CREATE TABLE issue_clicks(
+  id BIGSERIAL NOT NULL,
+  newsletter_issue_id uuid NOT NULL,
+  link_id INTEGER NOT NULL,
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+  clicked_at timestamptz NOT NULL,
+  PRIMARY KEY (id),
+  FOREIGN KEY (newsletter_issue_id, link_id)
+    REFERENCES issue_links (newsletter_issue_id, link_id) ON DELETE CASCADE
+);
+
+CREATE INDEX issue_clicks_issue_link_idx ON issue_clicks (newsletter_issue_id, link_id);
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_log(
+  newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+  -- The address the issue was sent to, subscribers may change it later
+  subscriber_email TEXT NOT NULL,
+  -- 'delivered' once accepted by the email provider, 'failed' otherwise
+  status TEXT NOT NULL,
+  message_id TEXT NULL,
+  error TEXT NULL,
+  sent_at timestamptz NOT NULL,
+  bounced_at timestamptz NULL,
+  complained_at timestamptz NULL,
+  PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+
+CREATE INDEX issue_delivery_log_message_id_idx ON issue_delivery_log (message_id);
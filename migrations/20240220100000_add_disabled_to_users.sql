@@ -0,0 +1,2 @@
+-- Disabled users keep their history but can no longer authenticate
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,5 @@
+-- The seeded `admin` account has a publicly known password. Remove it wherever the password was
+-- never changed, the owner account is created through `/setup` on first run instead.
+DELETE FROM users
+WHERE user_id = '2d8c861e-b2e6-4a00-bb44-7dfe4cd3bc3b'
+  AND password_hash = '$argon2id$v=19$m=15000,t=2,p=1$ZegXdWjxHc6jQE+2L+CtDw$84vf+M9sDkwsWLMegUsdwezd9qrt9OeGT1/ybKfxIYY';
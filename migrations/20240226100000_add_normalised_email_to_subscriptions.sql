@@ -0,0 +1,15 @@
+-- The form of the address used to tell whether two signups are for the same mailbox, see
+-- `SubscriberEmail::normalised`
+ALTER TABLE subscriptions ADD COLUMN normalised_email TEXT;
+
+-- Existing addresses only differing by case are left as they are, the oldest one claims the
+-- normalised address and the rest stay NULL, which the unique index ignores
+UPDATE subscriptions
+SET normalised_email = lower(email)
+WHERE id IN (
+  SELECT DISTINCT ON (lower(email)) id
+  FROM subscriptions
+  ORDER BY lower(email), subscribed_at
+);
+
+CREATE UNIQUE INDEX subscriptions_normalised_email_idx ON subscriptions (normalised_email);
@@ -0,0 +1,38 @@
+-- Endpoints our CRM and other integrations register to hear about subscription changes
+CREATE TABLE webhook_endpoints(
+  id uuid NOT NULL,
+  url TEXT NOT NULL,
+  -- Key for the HMAC-SHA256 signature on every request, shown to admins so receivers can verify
+  secret TEXT NOT NULL,
+  -- Event types to send, e.g. 'subscriber.confirmed'
+  events TEXT[] NOT NULL,
+  enabled BOOLEAN NOT NULL DEFAULT true,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (id)
+);
+
+-- One row per event per endpoint, written in the same transaction as the change it describes and
+-- worked through by the delivery worker
+CREATE TABLE webhook_deliveries(
+  id uuid NOT NULL,
+  endpoint_id uuid NOT NULL
+    REFERENCES webhook_endpoints (id) ON DELETE CASCADE,
+  -- Shared by the deliveries of one event to every endpoint
+  event_id uuid NOT NULL,
+  event_type TEXT NOT NULL,
+  payload JSONB NOT NULL,
+  -- 'pending' until accepted by the endpoint ('delivered') or out of attempts ('failed')
+  status TEXT NOT NULL,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  next_attempt_at timestamptz NOT NULL,
+  last_attempt_at timestamptz NULL,
+  last_response_status SMALLINT NULL,
+  last_error TEXT NULL,
+  created_at timestamptz NOT NULL,
+  delivered_at timestamptz NULL,
+  PRIMARY KEY (id)
+);
+
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries (next_attempt_at)
+  WHERE status = 'pending';
+CREATE INDEX webhook_deliveries_endpoint_idx ON webhook_deliveries (endpoint_id, created_at);
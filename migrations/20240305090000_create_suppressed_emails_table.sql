@@ -0,0 +1,11 @@
+-- Addresses of erased subscribers, kept so they are not added back by the API. Only the SHA-256 of
+-- the normalised address is stored, see `personal_data::suppression_hash`
+CREATE TABLE suppressed_emails(
+  email_hash TEXT NOT NULL,
+  suppressed_at timestamptz NOT NULL,
+  PRIMARY KEY (email_hash)
+);
+
+-- Webhook payloads carry the subscriber's details, erasure finds them by id
+CREATE INDEX webhook_deliveries_subscriber_idx
+  ON webhook_deliveries ((payload #>> '{data,subscriber,id}'));
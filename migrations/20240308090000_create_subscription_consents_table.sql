@@ -0,0 +1,42 @@
+-- Proof of consent, one row per signup through the form. Rows are never changed once written,
+-- apart from stamping the confirmation, they only go when the subscriber is erased.
+CREATE TABLE subscription_consents(
+  id uuid NOT NULL,
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+  -- The wording next to the subscribe button, and the version it was configured under
+  consent_text TEXT NOT NULL,
+  consent_version TEXT NOT NULL,
+  -- Which form the signup came from, e.g. 'home'
+  source TEXT NOT NULL,
+  -- As worked out from the trusted proxies, NULL if it couldn't be
+  ip_address TEXT NULL,
+  user_agent TEXT NULL,
+  given_at timestamptz NOT NULL,
+  -- Set once, when the subscriber follows the confirmation link
+  confirmed_at timestamptz NULL,
+  PRIMARY KEY (id)
+);
+
+CREATE INDEX subscription_consents_subscriber_idx ON subscription_consents (subscriber_id, given_at);
+
+CREATE FUNCTION forbid_consent_changes() RETURNS trigger AS $$
+BEGIN
+  IF OLD.confirmed_at IS NULL
+    AND NEW.confirmed_at IS NOT NULL
+    AND (NEW.id, NEW.subscriber_id, NEW.consent_text, NEW.consent_version, NEW.source,
+         NEW.ip_address, NEW.user_agent, NEW.given_at)
+      IS NOT DISTINCT FROM
+        (OLD.id, OLD.subscriber_id, OLD.consent_text, OLD.consent_version, OLD.source,
+         OLD.ip_address, OLD.user_agent, OLD.given_at)
+  THEN
+    RETURN NEW;
+  END IF;
+
+  RAISE EXCEPTION 'Consent records cannot be changed, only confirmed once';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER subscription_consents_immutable
+  BEFORE UPDATE ON subscription_consents
+  FOR EACH ROW EXECUTE FUNCTION forbid_consent_changes();
@@ -0,0 +1,34 @@
+-- Who did what through the admin pages, the API and newsletter-admin. Rows are only ever
+-- added, the retention purge is the one thing allowed to remove them.
+CREATE TABLE audit_log(
+  id uuid NOT NULL,
+  occurred_at timestamptz NOT NULL,
+  -- NULL for failed logins and newsletter-admin, which runs with direct database access.
+  -- Not a foreign key, entries outlive the users they mention.
+  user_id uuid NULL,
+  -- e.g. 'login.failed', 'issue.published', 'subscriber.updated'
+  action TEXT NOT NULL,
+  -- What the action was applied to: a username, an issue or subscriber id
+  target TEXT NULL,
+  ip_address TEXT NULL,
+  -- {"field": {"from": ..., "to": ...}} for every field the action changed
+  changes JSONB NOT NULL,
+  PRIMARY KEY (id)
+);
+
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+CREATE INDEX audit_log_action_idx ON audit_log (action, occurred_at);
+
+CREATE FUNCTION forbid_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'DELETE' AND current_setting('audit_log.purging', true) = 'on' THEN
+    RETURN OLD;
+  END IF;
+
+  RAISE EXCEPTION 'The audit log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+  BEFORE UPDATE OR DELETE ON audit_log
+  FOR EACH ROW EXECUTE FUNCTION forbid_audit_log_changes();
@@ -0,0 +1,8 @@
+-- Records how the `normalised_email`s in `subscriptions` were worked out. The app fills it in the
+-- first time it starts, redoing the plain `lower(email)` backfill with `SubscriberEmail::normalised`
+CREATE TABLE email_normalisation (
+  -- There is only ever one row
+  id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+  fold_provider_aliases BOOLEAN NOT NULL,
+  normalised_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,236 @@
+use std::net::IpAddr;
+
+use serde_json::{Map, Value};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::configuration::AuditSettings;
+use crate::shutdown::Shutdown;
+
+///
+/// The administrative actions written to the audit log
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    LoginSucceeded,
+    LoginFailed,
+    Logout,
+    PasswordChanged,
+    UserCreated,
+    UserDisabled,
+    UserEnabled,
+    IssuePublished,
+    SubscriberCreated,
+    SubscriberUpdated,
+    SubscriberDeleted,
+    SubscriberErased,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 12] = [
+        AuditAction::LoginSucceeded,
+        AuditAction::LoginFailed,
+        AuditAction::Logout,
+        AuditAction::PasswordChanged,
+        AuditAction::UserCreated,
+        AuditAction::UserDisabled,
+        AuditAction::UserEnabled,
+        AuditAction::IssuePublished,
+        AuditAction::SubscriberCreated,
+        AuditAction::SubscriberUpdated,
+        AuditAction::SubscriberDeleted,
+        AuditAction::SubscriberErased,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::LoginSucceeded => "login.succeeded",
+            AuditAction::LoginFailed => "login.failed",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChanged => "password.changed",
+            AuditAction::UserCreated => "user.created",
+            AuditAction::UserDisabled => "user.disabled",
+            AuditAction::UserEnabled => "user.enabled",
+            AuditAction::IssuePublished => "issue.published",
+            AuditAction::SubscriberCreated => "subscriber.created",
+            AuditAction::SubscriberUpdated => "subscriber.updated",
+            AuditAction::SubscriberDeleted => "subscriber.deleted",
+            AuditAction::SubscriberErased => "subscriber.erased",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.as_str() == action)
+    }
+}
+
+///
+/// Who performed an action. Both are `None` for `newsletter-admin`, which has direct access to
+/// the database rather than logging in.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Actor {
+    pub user_id: Option<Uuid>,
+    pub ip_address: Option<IpAddr>,
+}
+
+impl Actor {
+    pub fn new(user_id: Option<Uuid>, ip_address: Option<IpAddr>) -> Self {
+        Self {
+            user_id,
+            ip_address,
+        }
+    }
+
+    ///
+    /// `newsletter-admin`
+    ///
+    pub fn cli() -> Self {
+        Self::default()
+    }
+}
+
+#[tracing::instrument(name = "Record audit event", skip(executor, changes))]
+///
+/// Append an entry to the audit log. Pass the transaction making the change where there is one,
+/// so the entry is written if and only if the change is committed.
+///
+pub async fn record_audit_event(
+    executor: impl PgExecutor<'_>,
+    actor: Actor,
+    action: AuditAction,
+    target: Option<&str>,
+    changes: Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+      INSERT INTO audit_log (id, occurred_at, user_id, action, target, ip_address, changes)
+      VALUES ($1, now(), $2, $3, $4, $5, $6)
+    "#,
+        Uuid::new_v4(),
+        actor.user_id,
+        action.as_str(),
+        target,
+        actor.ip_address.map(|ip| ip.to_string()),
+        changes
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+///
+/// `{"field": {"from": ..., "to": ...}}` for every field whose value differs between two JSON
+/// objects. A field missing on one side is `null` there.
+///
+pub fn diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let from = before.get(key).unwrap_or(&Value::Null);
+        let to = after.get(key).unwrap_or(&Value::Null);
+        if from != to && !changes.contains_key(key) {
+            changes.insert(key.clone(), serde_json::json!({ "from": from, "to": to }));
+        }
+    }
+
+    Value::Object(changes)
+}
+
+#[tracing::instrument(name = "Purge expired audit log entries", skip(pool))]
+///
+/// Delete the entries older than `retention`, returning how many went
+///
+pub async fn purge_expired_audit_events(
+    pool: &PgPool,
+    retention: chrono::Duration,
+) -> Result<u64, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    // The append-only trigger lets deletes through in this transaction only
+    sqlx::query("SET LOCAL audit_log.purging = 'on'")
+        .execute(&mut transaction)
+        .await?;
+    let purged = sqlx::query!(
+        "DELETE FROM audit_log WHERE occurred_at < $1",
+        chrono::Utc::now() - retention
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+
+    transaction.commit().await?;
+
+    Ok(purged)
+}
+
+///
+/// Purge expired entries every `audit.purge_interval_seconds` until shutdown is triggered
+///
+pub async fn run_purge_until_stopped(pool: PgPool, settings: AuditSettings, shutdown: Shutdown) {
+    while !shutdown.is_triggered() {
+        match purge_expired_audit_events(&pool, settings.retention()).await {
+            Ok(purged) if purged > 0 => {
+                tracing::info!(purged, "Purged expired audit log entries")
+            }
+            Ok(_) => {}
+            Err(error) => tracing::error!(
+              error.cause_chain = ?error,
+              "Failed to purge expired audit log entries"
+            ),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(settings.purge_interval()) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, AuditAction};
+    use serde_json::json;
+
+    #[test]
+    fn actions_round_trip_through_their_names() {
+        for action in AuditAction::ALL {
+            assert_eq!(AuditAction::parse(action.as_str()), Some(action));
+        }
+        assert_eq!(AuditAction::parse("subscriber.exploded"), None);
+    }
+
+    #[test]
+    fn only_changed_fields_are_in_the_diff() {
+        let before = json!({ "status": "pending_confirmation", "attributes": { "plan": "free" } });
+        let after = json!({ "status": "confirmed", "attributes": { "plan": "free" } });
+
+        assert_eq!(
+            diff(&before, &after),
+            json!({ "status": { "from": "pending_confirmation", "to": "confirmed" } })
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_null() {
+        assert_eq!(
+            diff(&json!(null), &json!({ "status": "confirmed" })),
+            json!({ "status": { "from": null, "to": "confirmed" } })
+        );
+        assert_eq!(
+            diff(&json!({ "status": "confirmed" }), &json!({})),
+            json!({ "status": { "from": "confirmed", "to": null } })
+        );
+    }
+
+    #[test]
+    fn identical_objects_have_an_empty_diff() {
+        let value = json!({ "status": "confirmed" });
+
+        assert_eq!(diff(&value, &value), json!({}));
+    }
+}
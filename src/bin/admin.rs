@@ -0,0 +1,538 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use rand::seq::SliceRandom;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+use zero_to_production::{
+    audit_log::{record_audit_event, Actor, AuditAction},
+    authentication::{change_password, compute_password_hash},
+    bootstrap::normalise_stored_emails,
+    configuration::{get_configuration, Environment, Settings},
+    domain::{EmailPolicy, NewSubscriber, SubscriberEmail, SubscriberName},
+    personal_data::{data_export_link, erase_subscriber, export_subscriber_data},
+    routes::{
+        audited_changes, confirm_subscriber, generate_subscription_token, insert_subscriber,
+        publish_issue, store_token, BodyData, Content,
+    },
+    startup::get_connection_pool,
+};
+
+/// Environment variable checked for a password before prompting, so passwords stay out of
+/// shell history and `ps`
+const PASSWORD_ENV: &str = "NEWSLETTER_ADMIN_PASSWORD";
+
+const MIN_PASSWORD_LENGTH: usize = 12;
+
+///
+/// Operations tooling for the newsletter. Reads the same configuration as the server, so run it
+/// from the project root with `APP_ENVIRONMENT` set as you would for the server.
+///
+#[derive(Parser)]
+#[command(name = "newsletter-admin", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage the users who can log in and publish
+    #[command(subcommand)]
+    Users(UsersCommand),
+    /// Manage subscribers
+    #[command(subcommand)]
+    Subscribers(SubscribersCommand),
+    /// Run any pending database migrations
+    Migrate,
+    /// Publish an issue to every confirmed subscriber
+    Publish {
+        /// A Markdown file, or a JSON file in the same shape as the `/newsletters` request body
+        file: PathBuf,
+        /// Required for Markdown files
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        track_opens: bool,
+        #[arg(long)]
+        track_clicks: bool,
+    },
+    /// Fill the database with made up subscribers, for local development only
+    Seed {
+        #[arg(long, default_value_t = 25)]
+        subscribers: usize,
+        /// Seed even though APP_ENVIRONMENT is not `local`
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum UsersCommand {
+    /// Create a user, the password is read from NEWSLETTER_ADMIN_PASSWORD or prompted for
+    Create { username: String },
+    /// Stop a user from logging in or publishing
+    Disable { username: String },
+    /// Allow a disabled user to log in again
+    Enable { username: String },
+    /// Set a new password, read from NEWSLETTER_ADMIN_PASSWORD or prompted for
+    ResetPassword { username: String },
+}
+
+#[derive(Subcommand)]
+enum SubscribersCommand {
+    /// Print every subscriber as tab separated email, name, status and subscription date
+    List {
+        /// Only list subscribers with this status, e.g. `confirmed`
+        #[arg(long)]
+        status: Option<String>,
+    },
+    /// Confirm a subscriber without them following the confirmation link
+    Confirm { email: String },
+    /// Delete a subscriber, along with their tokens and delivery history
+    Remove { email: String },
+    /// Print everything held on a subscriber as JSON, for a subject access request
+    Export { email: String },
+    /// Print a link the subscriber can use to download their data themselves
+    ExportLink { email: String },
+    /// Delete everything held on a subscriber and stop the address being added back by the API
+    Erase { email: String },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    let config = get_configuration().context("Failed to read configuration")?;
+    let pool = get_connection_pool(&config.database);
+
+    match cli.command {
+        Command::Users(command) => users(command, &pool).await,
+        Command::Subscribers(command) => subscribers(command, &config, &pool).await,
+        Command::Migrate => {
+            sqlx::migrate!("./migrations")
+                .run(&pool)
+                .await
+                .context("Failed to migrate the database")?;
+            normalise_stored_emails(&pool, config.email_policy.fold_provider_aliases).await?;
+            println!("Database is up to date");
+            Ok(())
+        }
+        Command::Publish {
+            file,
+            title,
+            track_opens,
+            track_clicks,
+        } => {
+            let body = read_issue(&file, title, track_opens, track_clicks)?;
+            publish(body, config, &pool).await
+        }
+        Command::Seed { subscribers, force } => {
+            if config.environment != Environment::Local && !force {
+                anyhow::bail!(
+                    "Refusing to add made up subscribers to the {} database, pass --force if you really mean to",
+                    config.environment.as_str()
+                );
+            }
+            let email_policy = config.email_policy.policy()?;
+            seed(subscribers, &email_policy, &pool).await
+        }
+    }
+}
+
+async fn users(command: UsersCommand, pool: &PgPool) -> Result<(), anyhow::Error> {
+    match command {
+        UsersCommand::Create { username } => {
+            let password = read_password()?;
+            let password_hash = compute_password_hash(password)?;
+            let mut transaction = pool.begin().await?;
+
+            sqlx::query!(
+                r#"
+                INSERT INTO users (user_id, username, password_hash)
+                VALUES ($1, $2, $3)
+              "#,
+                Uuid::new_v4(),
+                username,
+                password_hash.expose_secret()
+            )
+            .execute(&mut transaction)
+            .await
+            .context("Failed to create user, the username may already be taken")?;
+            record_audit_event(
+                &mut transaction,
+                Actor::cli(),
+                AuditAction::UserCreated,
+                Some(&username),
+                serde_json::json!({}),
+            )
+            .await?;
+
+            transaction.commit().await?;
+
+            println!("Created user {}", username);
+        }
+        UsersCommand::Disable { username } => {
+            set_disabled(&username, true, pool).await?;
+            println!("Disabled user {}", username);
+        }
+        UsersCommand::Enable { username } => {
+            set_disabled(&username, false, pool).await?;
+            println!("Enabled user {}", username);
+        }
+        UsersCommand::ResetPassword { username } => {
+            let user_id = get_user_id(&username, pool).await?;
+            let password = read_password()?;
+
+            change_password(user_id, password, pool).await?;
+            record_audit_event(
+                pool,
+                Actor::cli(),
+                AuditAction::PasswordChanged,
+                Some(&username),
+                serde_json::json!({}),
+            )
+            .await?;
+            println!("Reset password for {}", username);
+        }
+    }
+
+    Ok(())
+}
+
+async fn get_user_id(username: &str, pool: &PgPool) -> Result<Uuid, anyhow::Error> {
+    sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_optional(pool)
+        .await?
+        .map(|r| r.user_id)
+        .with_context(|| format!("No user called {}", username))
+}
+
+async fn set_disabled(username: &str, disabled: bool, pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let result = sqlx::query!(
+        "UPDATE users SET disabled = $1 WHERE username = $2",
+        disabled,
+        username
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        anyhow::bail!("No user called {}", username);
+    }
+
+    let action = if disabled {
+        AuditAction::UserDisabled
+    } else {
+        AuditAction::UserEnabled
+    };
+    record_audit_event(
+        &mut transaction,
+        Actor::cli(),
+        action,
+        Some(username),
+        serde_json::json!({}),
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+fn read_password() -> Result<Secret<String>, anyhow::Error> {
+    let password = match std::env::var(PASSWORD_ENV) {
+        Ok(password) => password,
+        Err(_) => {
+            let password = rpassword::prompt_password("Password: ")?;
+            if password != rpassword::prompt_password("Repeat password: ")? {
+                anyhow::bail!("The passwords do not match");
+            }
+            password
+        }
+    };
+
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        anyhow::bail!(
+            "Passwords must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        );
+    }
+
+    Ok(Secret::new(password))
+}
+
+async fn subscribers(
+    command: SubscribersCommand,
+    config: &Settings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    match command {
+        SubscribersCommand::List { status } => {
+            let rows = sqlx::query!(
+                r#"
+                SELECT email, name, status, subscribed_at
+                FROM subscriptions
+                WHERE $1::TEXT IS NULL OR status = $1
+                ORDER BY subscribed_at
+              "#,
+                status
+            )
+            .fetch_all(pool)
+            .await?;
+
+            for row in rows {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    row.email,
+                    row.name,
+                    row.status,
+                    row.subscribed_at.to_rfc3339()
+                );
+            }
+        }
+        SubscribersCommand::Confirm { email } => {
+            let subscriber_id = get_subscriber_id(&email, config, pool).await?;
+            let mut transaction = pool.begin().await?;
+            let previous = sqlx::query!(
+                "SELECT status, attributes FROM subscriptions WHERE id = $1 FOR UPDATE",
+                subscriber_id
+            )
+            .fetch_one(&mut transaction)
+            .await?;
+            confirm_subscriber(&mut transaction, subscriber_id).await?;
+            if previous.status != "confirmed" {
+                record_audit_event(
+                    &mut transaction,
+                    Actor::cli(),
+                    AuditAction::SubscriberUpdated,
+                    Some(&subscriber_id.to_string()),
+                    audited_changes(
+                        Some((&previous.status, &previous.attributes)),
+                        Some(("confirmed", &previous.attributes)),
+                    ),
+                )
+                .await?;
+            }
+            transaction.commit().await?;
+            println!("Confirmed {}", email);
+        }
+        SubscribersCommand::Remove { email } => {
+            let subscriber_id = get_subscriber_id(&email, config, pool).await?;
+            let mut transaction = pool.begin().await?;
+
+            // Tokens predate the cascading foreign keys, everything else goes with the subscriber
+            sqlx::query!(
+                "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+                subscriber_id
+            )
+            .execute(&mut transaction)
+            .await?;
+            let deleted = sqlx::query!(
+                "DELETE FROM subscriptions WHERE id = $1 RETURNING status, attributes",
+                subscriber_id
+            )
+            .fetch_one(&mut transaction)
+            .await?;
+            record_audit_event(
+                &mut transaction,
+                Actor::cli(),
+                AuditAction::SubscriberDeleted,
+                Some(&subscriber_id.to_string()),
+                audited_changes(Some((&deleted.status, &deleted.attributes)), None),
+            )
+            .await?;
+
+            transaction.commit().await?;
+            println!("Removed {}", email);
+        }
+        SubscribersCommand::Export { email } => {
+            let subscriber_id = get_subscriber_id(&email, config, pool).await?;
+            let export = export_subscriber_data(pool, subscriber_id)
+                .await?
+                .with_context(|| format!("No subscriber with the email {}", email))?;
+            println!("{}", serde_json::to_string_pretty(&export)?);
+        }
+        SubscribersCommand::ExportLink { email } => {
+            let subscriber_id = get_subscriber_id(&email, config, pool).await?;
+            let app_base_url = config.application.base_url().map_err(anyhow::Error::msg)?;
+            println!(
+                "{}",
+                data_export_link(
+                    &app_base_url,
+                    subscriber_id,
+                    &config.application.hmac_secret
+                )
+            );
+        }
+        SubscribersCommand::Erase { email } => {
+            let subscriber_id = get_subscriber_id(&email, config, pool).await?;
+            let mut transaction = pool.begin().await?;
+            erase_subscriber(&mut transaction, subscriber_id).await?;
+            record_audit_event(
+                &mut transaction,
+                Actor::cli(),
+                AuditAction::SubscriberErased,
+                Some(&subscriber_id.to_string()),
+                serde_json::json!({}),
+            )
+            .await?;
+            transaction.commit().await?;
+            println!("Erased {}", email);
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// Look the subscriber up the way signups are matched, so any spelling of their address which
+/// the email policy treats as the same mailbox finds them
+///
+async fn get_subscriber_id(
+    email: &str,
+    config: &Settings,
+    pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    let parsed = SubscriberEmail::parse(email.to_string()).map_err(anyhow::Error::msg)?;
+    let normalised_email = config.email_policy.policy()?.normalise(&parsed);
+
+    sqlx::query!(
+        "SELECT id FROM subscriptions WHERE normalised_email = $1",
+        normalised_email
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| r.id)
+    .with_context(|| format!("No subscriber with the email {}", email))
+}
+
+fn read_issue(
+    file: &Path,
+    title: Option<String>,
+    track_opens: bool,
+    track_clicks: bool,
+) -> Result<BodyData, anyhow::Error> {
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+
+    if file.extension().is_some_and(|e| e == "json") {
+        let mut body: BodyData =
+            serde_json::from_str(&contents).context("Failed to parse the issue")?;
+        body.title = title.unwrap_or(body.title);
+        body.track_opens |= track_opens;
+        body.track_clicks |= track_clicks;
+
+        return Ok(body);
+    }
+
+    Ok(BodyData {
+        title: title.context("--title is required when publishing Markdown")?,
+        content: Content {
+            html: None,
+            text: None,
+            markdown: Some(contents),
+        },
+        track_opens,
+        track_clicks,
+    })
+}
+
+async fn publish(body: BodyData, config: Settings, pool: &PgPool) -> Result<(), anyhow::Error> {
+    let app_base_url = config.application.base_url().map_err(anyhow::Error::msg)?;
+    let email_client = config.email_client.client();
+
+    let response = publish_issue(
+        pool,
+        &email_client,
+        &app_base_url,
+        &config.application.hmac_secret,
+        &config.tracking,
+        Actor::cli(),
+        body,
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+
+    println!("Published issue {}", response.issue_id);
+    for warning in response.warnings {
+        println!("warning: {}", warning);
+    }
+
+    Ok(())
+}
+
+const FIRST_NAMES: [&str; 10] = [
+    "Ada",
+    "Alan",
+    "Barbara",
+    "Donald",
+    "Edsger",
+    "Frances",
+    "Grace",
+    "Katherine",
+    "Ken",
+    "Radia",
+];
+const LAST_NAMES: [&str; 10] = [
+    "Allen",
+    "Hopper",
+    "Johnson",
+    "Kernighan",
+    "Knuth",
+    "Liskov",
+    "Lovelace",
+    "Perlman",
+    "Ritchie",
+    "Turing",
+];
+
+async fn seed(
+    count: usize,
+    email_policy: &EmailPolicy,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let mut rng = rand::thread_rng();
+    let mut transaction = pool.begin().await?;
+    let mut subscriber_ids = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let first = FIRST_NAMES.choose(&mut rng).unwrap();
+        let last = LAST_NAMES.choose(&mut rng).unwrap();
+        // The suffix keeps addresses unique across repeated runs
+        let suffix = &generate_subscription_token()[..6];
+        let subscriber = NewSubscriber {
+            email: SubscriberEmail::parse(format!(
+                "{}.{}.{}@example.com",
+                first.to_lowercase(),
+                last.to_lowercase(),
+                suffix.to_lowercase()
+            ))
+            .map_err(anyhow::Error::msg)?,
+            name: SubscriberName::parse(format!("{} {}", first, last))
+                .map_err(anyhow::Error::msg)?,
+        };
+
+        let normalised_email = email_policy.normalise(&subscriber.email);
+        let subscriber_id =
+            insert_subscriber(&mut transaction, &subscriber, &normalised_email).await?;
+        store_token(
+            &mut transaction,
+            subscriber_id,
+            &generate_subscription_token(),
+        )
+        .await?;
+        subscriber_ids.push(subscriber_id);
+    }
+
+    // Leave roughly a third pending so both states show up in the dashboard
+    for subscriber_id in subscriber_ids.into_iter().skip(count / 3) {
+        confirm_subscriber(&mut transaction, subscriber_id).await?;
+    }
+
+    transaction.commit().await?;
+
+    println!("Added {} subscribers", count);
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::configuration::Environment;
+use crate::domain::SubscriberEmail;
+
+/// The account inserted by `20240131112100_seed_user.sql`, its password is in the git history
+const LEGACY_ADMIN_USERNAME: &str = "admin";
+const LEGACY_ADMIN_PASSWORD: &str = "everythinghastostartsomewhere";
+
+///
+/// One-time token allowing the owner account to be created through `/setup`. It only exists
+/// while there are no users, and is consumed by the first successful use.
+///
+pub struct SetupToken(Mutex<Option<Secret<String>>>);
+
+impl SetupToken {
+    fn generate() -> Self {
+        let mut rng = thread_rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(32)
+            .collect();
+
+        Self(Mutex::new(Some(Secret::new(token))))
+    }
+
+    fn none() -> Self {
+        Self(Mutex::new(None))
+    }
+
+    pub fn expose(&self) -> Option<String> {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|token| token.expose_secret().clone())
+    }
+
+    pub fn matches(&self, candidate: &str) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|token| digest_eq(token.expose_secret(), candidate))
+    }
+
+    ///
+    /// Consume the token if `candidate` matches it, so that only one request can use it
+    ///
+    pub fn take(&self, candidate: &str) -> Option<Secret<String>> {
+        let mut token = self.0.lock().unwrap();
+
+        match token.as_ref() {
+            Some(t) if digest_eq(t.expose_secret(), candidate) => token.take(),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Put back a token taken by a request which then failed
+    ///
+    pub fn restore(&self, token: Secret<String>) {
+        *self.0.lock().unwrap() = Some(token);
+    }
+}
+
+// Compare digests rather than the tokens themselves, so the time taken says nothing about how
+// much of the candidate was right
+fn digest_eq(a: &str, b: &str) -> bool {
+    Sha256::digest(a.as_bytes()) == Sha256::digest(b.as_bytes())
+}
+
+#[tracing::instrument(name = "Check if any users exist", skip(pool))]
+pub async fn users_exist(pool: &PgPool) -> Result<bool, sqlx::Error> {
+    let exists = sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM users) AS "exists!""#)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(exists)
+}
+
+///
+/// Run before the server starts. Refuses to start in production while the legacy seeded account
+/// can still log in, and issues a setup token when there are no users yet.
+///
+pub async fn bootstrap(
+    pool: &PgPool,
+    environment: Environment,
+    app_base_url: &Url,
+) -> Result<SetupToken, anyhow::Error> {
+    if environment == Environment::Production && legacy_admin_can_log_in(pool).await? {
+        anyhow::bail!(
+            "The `{}` user still has the publicly known password it was seeded with. \
+            Change its password or disable it before starting in production.",
+            LEGACY_ADMIN_USERNAME
+        );
+    }
+
+    if users_exist(pool)
+        .await
+        .context("Failed to check for existing users")?
+    {
+        return Ok(SetupToken::none());
+    }
+
+    let setup_token = SetupToken::generate();
+    tracing::warn!(
+        "No users exist yet, create the owner account at {}setup?token={}",
+        app_base_url,
+        setup_token.expose().unwrap()
+    );
+
+    Ok(setup_token)
+}
+
+///
+/// Make sure every stored `normalised_email` was worked out the way signups work it out now. The
+/// first run recomputes them all, as the migration adding the column could only lowercase them,
+/// and later runs refuse to start if `email_policy.fold_provider_aliases` has changed since.
+///
+#[tracing::instrument(name = "Normalise stored email addresses", skip(pool))]
+pub async fn normalise_stored_emails(
+    pool: &PgPool,
+    fold_provider_aliases: bool,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // Only one instance does the backfill, any others starting alongside wait for it
+    sqlx::query!("LOCK TABLE email_normalisation IN EXCLUSIVE MODE")
+        .execute(&mut transaction)
+        .await
+        .context("Failed to lock the email normalisation settings")?;
+
+    let normalised_with =
+        sqlx::query_scalar!("SELECT fold_provider_aliases FROM email_normalisation")
+            .fetch_optional(&mut transaction)
+            .await
+            .context("Failed to read the email normalisation settings")?;
+
+    match normalised_with {
+        Some(stored) if stored == fold_provider_aliases => return Ok(()),
+        Some(stored) => anyhow::bail!(
+            "The stored subscriber addresses were normalised with \
+            `email_policy.fold_provider_aliases` set to {}, it can't be changed to {} without \
+            letting existing subscribers sign up again",
+            stored,
+            fold_provider_aliases
+        ),
+        None => {}
+    }
+
+    // Confirmed subscribers, then the oldest, keep the address when several share a mailbox
+    let subscribers = sqlx::query!(
+        r#"
+        SELECT id, email
+        FROM subscriptions
+        ORDER BY status = 'confirmed' DESC, subscribed_at, id
+        "#
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to read the stored subscriber addresses")?;
+
+    let mut claimed = HashSet::new();
+    let mut ids = Vec::new();
+    let mut normalised_emails = Vec::new();
+    for subscriber in subscribers {
+        let normalised = match SubscriberEmail::parse(subscriber.email.clone()) {
+            Ok(email) => email.normalised(fold_provider_aliases),
+            // Accepted under older validation rules, leave it as the migration had it
+            Err(_) => subscriber.email.to_lowercase(),
+        };
+
+        if claimed.insert(normalised.clone()) {
+            ids.push(subscriber.id);
+            normalised_emails.push(normalised);
+        } else {
+            tracing::warn!(
+                subscriber_id = %subscriber.id,
+                "Subscriber shares a mailbox with another one, it is left without a normalised \
+                address and can only be reached by id"
+            );
+        }
+    }
+
+    // Cleared first, so addresses moving between rows never trip the unique index
+    sqlx::query!("UPDATE subscriptions SET normalised_email = NULL")
+        .execute(&mut transaction)
+        .await
+        .context("Failed to clear the normalised addresses")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET normalised_email = normalised.email
+        FROM UNNEST($1::uuid[], $2::text[]) AS normalised(id, email)
+        WHERE subscriptions.id = normalised.id
+        "#,
+        &ids[..] as &[Uuid],
+        &normalised_emails[..],
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the normalised addresses")?;
+
+    sqlx::query!(
+        "INSERT INTO email_normalisation (fold_provider_aliases) VALUES ($1)",
+        fold_provider_aliases
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record the email normalisation settings")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the normalised addresses")?;
+
+    Ok(())
+}
+
+async fn legacy_admin_can_log_in(pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let credentials = Credentials {
+        username: LEGACY_ADMIN_USERNAME.to_string(),
+        password: Secret::new(LEGACY_ADMIN_PASSWORD.to_string()),
+    };
+
+    match validate_credentials(credentials, pool).await {
+        Ok(_) => Ok(true),
+        Err(AuthError::InvalidCredentials(_)) => Ok(false),
+        Err(e @ AuthError::UnexpectedError(_)) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SetupToken;
+
+    #[test]
+    fn a_setup_token_can_only_be_taken_once() {
+        // Arrange
+        let setup_token = SetupToken::generate();
+        let token = setup_token.expose().unwrap();
+
+        // Act
+        let first = setup_token.take(&token);
+        let second = setup_token.take(&token);
+
+        // Assert
+        assert!(first.is_some());
+        assert!(second.is_none());
+        assert!(!setup_token.matches(&token));
+    }
+
+    #[test]
+    fn a_wrong_token_is_not_consumed() {
+        // Arrange
+        let setup_token = SetupToken::generate();
+        let token = setup_token.expose().unwrap();
+
+        // Act
+        let taken = setup_token.take("not-the-token");
+
+        // Assert
+        assert!(taken.is_none());
+        assert!(setup_token.matches(&token));
+    }
+}
@@ -0,0 +1,126 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, FromRequest};
+use base64::Engine;
+use futures_util::future::LocalBoxFuture;
+use rand::RngCore;
+
+use crate::helpers::constant_time_eq;
+use crate::session_state::TypedSession;
+
+/// Name of the hidden input carrying the token, rendered by `partials/csrf_input.html`
+pub const CSRF_FIELD: &str = "csrf_token";
+
+///
+/// The session's CSRF token, created on first use. Forms posting to a protected path render it
+/// with `partials/csrf_input.html`.
+///
+pub fn csrf_token(session: &TypedSession) -> Result<String, actix_web::Error> {
+    if let Some(token) = session
+        .get_csrf_token()
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        return Ok(token);
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+    session
+        .insert_csrf_token(&token)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(token)
+}
+
+///
+/// Paths whose POSTs are authenticated by the session cookie alone. Everything else is public,
+/// or authenticated by credentials a cross-site form can't attach.
+///
+fn is_protected(request: &ServiceRequest) -> bool {
+    let path = request.path();
+    !request.method().is_safe() && (path == "/login" || path.starts_with("/admin/"))
+}
+
+///
+/// Synchronizer token check: form posts to cookie-authenticated paths must carry the token stored
+/// in the session, or they are turned away with a 403. Wrap it inside `SessionMiddleware`.
+///
+pub struct CsrfProtection;
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfProtection
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = CsrfProtectionMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfProtectionMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct CsrfProtectionMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfProtectionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            if !is_protected(&request) {
+                return service.call(request).await;
+            }
+
+            let expected = TypedSession::extract(request.request())
+                .await?
+                .get_csrf_token()
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            // The handler still needs the body, so hand it back once the token is read out
+            let body = request.extract::<web::Bytes>().await?;
+            let submitted = serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body)
+                .unwrap_or_default()
+                .into_iter()
+                .find(|(name, _)| name == CSRF_FIELD)
+                .map(|(_, value)| value);
+            request.set_payload(body.into());
+
+            match (expected, submitted) {
+                (Some(expected), Some(submitted)) if constant_time_eq(&expected, &submitted) => {
+                    service.call(request).await
+                }
+                _ => {
+                    tracing::warn!(
+                        path = request.path(),
+                        "Rejected a form post without a valid CSRF token"
+                    );
+                    Err(actix_web::error::ErrorForbidden(
+                        "The form has expired, please reload the page and try again",
+                    ))
+                }
+            }
+        })
+    }
+}
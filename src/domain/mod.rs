@@ -1,3 +1,6 @@
+mod newsletter;
+mod signed_token;
 mod subscriptions;
 
+pub use newsletter::*;
 pub use subscriptions::*;
@@ -0,0 +1,421 @@
+use kuchikiki::traits::TendrilSink;
+use kuchikiki::NodeRef;
+
+use super::ProtectedTags;
+
+///
+/// Issue HTML which has been made safe and compatible for email clients:
+/// `<style>` rules are inlined into `style` attributes, anything outside the allowlist
+/// (scripts, iframes, event handlers, ...) is stripped, and size limits are checked.
+///
+#[derive(Debug)]
+pub struct EmailHtml {
+    pub html: String,
+    pub warnings: Vec<String>,
+}
+
+struct CssRule {
+    selector: String,
+    declarations: Vec<(String, String)>,
+    specificity: (usize, usize, usize),
+    position: usize,
+}
+
+impl EmailHtml {
+    /// Gmail clips messages above this size behind a "View entire message" link
+    const CLIPPING_THRESHOLD_BYTES: usize = 102 * 1024;
+    /// Postmark rejects HTML bodies above this size
+    const MAX_SIZE_BYTES: usize = 5 * 1024 * 1024;
+    const ORIGINAL_STYLE_ATTRIBUTE: &'static str = "data-original-style";
+    /// Selectors which depend on user interaction or pseudo-elements, and so can never be inlined
+    const INTERACTIVE_PSEUDO_CLASSES: [&'static str; 5] =
+        [":hover", ":focus", ":active", ":visited", "::"];
+    const DISALLOWED_ELEMENTS: [&'static str; 7] = [
+        "script", "iframe", "object", "embed", "form", "frame", "frameset",
+    ];
+
+    ///
+    /// Run the HTML through inlining and sanitisation, failing only when it is too large to send
+    ///
+    pub fn process(html: &str) -> Result<EmailHtml, String> {
+        if html.len() > Self::MAX_SIZE_BYTES {
+            return Err(format!(
+                "The HTML content is {} bytes, the maximum allowed is {} bytes",
+                html.len(),
+                Self::MAX_SIZE_BYTES
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        let (protected, tags) = ProtectedTags::protect(html);
+
+        let document = kuchikiki::parse_html().one(protected);
+        warnings.extend(report_disallowed_markup(&document));
+        warnings.extend(inline_styles(&document));
+
+        let sanitised = sanitiser().clean(&document.to_string()).to_string();
+        let html = tags.restore(&sanitised);
+
+        if html.len() > Self::CLIPPING_THRESHOLD_BYTES {
+            warnings.push(format!(
+                "The HTML content is {} bytes, Gmail clips messages larger than {} bytes",
+                html.len(),
+                Self::CLIPPING_THRESHOLD_BYTES
+            ));
+        }
+
+        Ok(EmailHtml { html, warnings })
+    }
+}
+
+fn sanitiser() -> ammonia::Builder<'static> {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tags(["center", "font"])
+        .add_clean_content_tags(["title"])
+        .add_generic_attributes([
+            "style",
+            "class",
+            "id",
+            "align",
+            "valign",
+            "width",
+            "height",
+            "bgcolor",
+            "border",
+            "cellpadding",
+            "cellspacing",
+        ]);
+    builder
+}
+
+fn report_disallowed_markup(document: &NodeRef) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for element in EmailHtml::DISALLOWED_ELEMENTS {
+        let count = document
+            .select(element)
+            .map(|elements| elements.count())
+            .unwrap_or(0);
+        if count > 0 {
+            warnings.push(format!("Removed {} <{}> element(s)", count, element));
+        }
+    }
+
+    let event_handlers = document
+        .select("*")
+        .map(|elements| {
+            elements
+                .map(|e| {
+                    e.attributes
+                        .borrow()
+                        .map
+                        .keys()
+                        .filter(|name| name.local.starts_with("on"))
+                        .count()
+                })
+                .sum::<usize>()
+        })
+        .unwrap_or(0);
+    if event_handlers > 0 {
+        warnings.push(format!(
+            "Removed {} event handler attribute(s)",
+            event_handlers
+        ));
+    }
+
+    warnings
+}
+
+///
+/// Move every `<style>` rule onto the elements it matches. Existing `style` attributes win over
+/// stylesheet rules, and rules are applied by ascending specificity then source order.
+///
+fn inline_styles(document: &NodeRef) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut stylesheet = String::new();
+
+    if let Ok(style_elements) = document.select("style") {
+        let style_elements: Vec<_> = style_elements.collect();
+        for style in style_elements {
+            stylesheet.push_str(&style.text_contents());
+            stylesheet.push('\n');
+            style.as_node().detach();
+        }
+    }
+
+    let (mut rules, skipped_at_rules) = parse_stylesheet(&stylesheet);
+    if skipped_at_rules > 0 {
+        warnings.push(format!(
+            "Dropped {} CSS at-rule(s) (e.g. @media) which cannot be inlined",
+            skipped_at_rules
+        ));
+    }
+
+    if let Ok(elements) = document.select("[style]") {
+        for element in elements {
+            let mut attributes = element.attributes.borrow_mut();
+            if let Some(style) = attributes.remove("style") {
+                attributes.insert(EmailHtml::ORIGINAL_STYLE_ATTRIBUTE, style.value);
+            }
+        }
+    }
+
+    rules.sort_by_key(|r| (r.specificity, r.position));
+    let mut unsupported_selectors = 0;
+
+    for rule in &rules {
+        let is_interactive = EmailHtml::INTERACTIVE_PSEUDO_CLASSES
+            .iter()
+            .any(|p| rule.selector.contains(p));
+        let elements = match document.select(&rule.selector) {
+            Ok(elements) if !is_interactive => elements,
+            _ => {
+                unsupported_selectors += 1;
+                continue;
+            }
+        };
+
+        for element in elements {
+            let mut attributes = element.attributes.borrow_mut();
+            let style = attributes.get("style").unwrap_or_default().to_string();
+            let style = merge_declarations(&style, &rule.declarations);
+            attributes.insert("style", style);
+        }
+    }
+
+    if unsupported_selectors > 0 {
+        warnings.push(format!(
+            "Dropped {} CSS rule(s) with selectors which cannot be inlined (e.g. :hover)",
+            unsupported_selectors
+        ));
+    }
+
+    if let Ok(elements) = document.select("[style], [data-original-style]") {
+        for element in elements {
+            let mut attributes = element.attributes.borrow_mut();
+            let original = attributes
+                .remove(EmailHtml::ORIGINAL_STYLE_ATTRIBUTE)
+                .map(|a| parse_declarations(&a.value))
+                .unwrap_or_default();
+            let style = attributes.get("style").unwrap_or_default().to_string();
+            let style = merge_declarations(&style, &original);
+
+            if style.is_empty() {
+                attributes.remove("style");
+            } else {
+                attributes.insert("style", style);
+            }
+        }
+    }
+
+    warnings
+}
+
+///
+/// A deliberately small CSS parser: plain rule sets are returned, at-rules are skipped and counted
+///
+fn parse_stylesheet(stylesheet: &str) -> (Vec<CssRule>, usize) {
+    let css = strip_comments(stylesheet);
+    let mut rules = Vec::new();
+    let mut skipped_at_rules = 0;
+    let mut rest = css.as_str();
+
+    while let Some(open) = rest.find('{') {
+        let prelude = rest[..open].trim();
+
+        if prelude.starts_with('@') {
+            // Skip the whole (possibly nested) block
+            let mut depth = 0;
+            let mut end = rest.len();
+            for (i, c) in rest[open..].char_indices() {
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = open + i + 1;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            skipped_at_rules += 1;
+            rest = &rest[end..];
+            continue;
+        }
+
+        let Some(close) = rest[open..].find('}') else {
+            break;
+        };
+        let declarations = parse_declarations(&rest[open + 1..open + close]);
+
+        for selector in prelude.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            rules.push(CssRule {
+                selector: selector.to_string(),
+                declarations: declarations.clone(),
+                specificity: specificity(selector),
+                position: rules.len(),
+            });
+        }
+
+        rest = &rest[open + close + 1..];
+    }
+
+    (rules, skipped_at_rules)
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("/*") {
+        output.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    output.push_str(rest);
+
+    output
+}
+
+fn parse_declarations(block: &str) -> Vec<(String, String)> {
+    block
+        .split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let property = property.trim().to_lowercase();
+            let value = value.trim();
+
+            let is_dangerous = value.to_lowercase().contains("expression(")
+                || value.to_lowercase().contains("javascript:");
+            if property.is_empty() || value.is_empty() || is_dangerous {
+                return None;
+            }
+
+            Some((property, value.to_string()))
+        })
+        .collect()
+}
+
+fn merge_declarations(style: &str, declarations: &[(String, String)]) -> String {
+    let mut merged = parse_declarations(style);
+
+    for (property, value) in declarations {
+        match merged.iter_mut().find(|(p, _)| p == property) {
+            Some(existing) => existing.1 = value.clone(),
+            None => merged.push((property.clone(), value.clone())),
+        }
+    }
+
+    merged
+        .iter()
+        .map(|(property, value)| format!("{}: {};", property, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+///
+/// (ids, classes/attributes/pseudo-classes, element types) as per the CSS specificity rules
+///
+fn specificity(selector: &str) -> (usize, usize, usize) {
+    let mut ids = 0;
+    let mut classes = 0;
+    let mut types = 0;
+    let mut previous = ' ';
+
+    for c in selector.chars() {
+        match c {
+            '#' => ids += 1,
+            '.' | '[' => classes += 1,
+            ':' if previous != ':' => classes += 1,
+            c if c.is_ascii_alphabetic() && " >+~(".contains(previous) => types += 1,
+            _ => {}
+        }
+        previous = c;
+    }
+
+    (ids, classes, types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailHtml;
+    use claims::assert_err;
+
+    #[test]
+    fn scripts_iframes_and_event_handlers_are_stripped() {
+        let processed = EmailHtml::process(
+            r#"<p onclick="steal()">Hi</p><script>alert(1)</script><iframe src="https://evil.com"></iframe>"#,
+        )
+        .unwrap();
+
+        assert_eq!(processed.html, "<p>Hi</p>");
+        assert_eq!(processed.warnings.len(), 3);
+    }
+
+    #[test]
+    fn style_rules_are_inlined() {
+        let processed = EmailHtml::process(
+            "<html><head><title>Issue</title><style>p { color: red; } .big { font-size: 20px; }</style></head>\
+<body><p class=\"big\">Hi</p></body></html>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            processed.html,
+            r#"<p class="big" style="color: red; font-size: 20px;">Hi</p>"#
+        );
+        assert!(processed.warnings.is_empty());
+    }
+
+    #[test]
+    fn inline_styles_win_over_stylesheet_rules() {
+        let processed = EmailHtml::process(
+            r#"<style>#intro { color: red; } p { color: blue; margin: 0; }</style><p id="intro" style="color: green">Hi</p>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            processed.html,
+            r#"<p id="intro" style="color: green; margin: 0;">Hi</p>"#
+        );
+    }
+
+    #[test]
+    fn rules_which_cannot_be_inlined_are_reported() {
+        let processed = EmailHtml::process(
+            "<style>@media (max-width: 600px) { p { color: red; } } a:hover { color: red; }</style><p>Hi</p>",
+        )
+        .unwrap();
+
+        assert_eq!(processed.html, "<p>Hi</p>");
+        assert_eq!(processed.warnings.len(), 2);
+    }
+
+    #[test]
+    fn personalisation_tags_are_preserved() {
+        let processed = EmailHtml::process(
+            r#"<a href="{{ unsubscribe_url }}">{{ attributes.company | default: "friend" }}</a>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            processed.html,
+            r#"<a href="{{ unsubscribe_url }}" rel="noopener noreferrer">{{ attributes.company | default: "friend" }}</a>"#
+        );
+    }
+
+    #[test]
+    fn large_content_is_reported_and_oversized_content_is_rejected() {
+        let large = format!("<p>{}</p>", "a".repeat(200 * 1024));
+        let processed = EmailHtml::process(&large).unwrap();
+        assert_eq!(processed.warnings.len(), 1);
+
+        let oversized = format!("<p>{}</p>", "a".repeat(6 * 1024 * 1024));
+        assert_err!(EmailHtml::process(&oversized));
+    }
+}
@@ -0,0 +1,437 @@
+///
+/// A newsletter issue body which has been checked for valid personalisation tags.
+/// Tags use the `{{ variable }}` or `{{ variable | default: "fallback" }}` syntax.
+///
+#[derive(Debug, Clone)]
+pub struct IssueTemplate {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Tag {
+        variable: Variable,
+        default: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Variable {
+    SubscriberName,
+    SubscriberEmail,
+    UnsubscribeUrl,
+    DataExportUrl,
+    Attribute(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateEscape {
+    Html,
+    None,
+}
+
+///
+/// Values available to a template when it is rendered for a single recipient
+///
+pub struct PersonalisationContext<'a> {
+    pub subscriber_name: &'a str,
+    pub subscriber_email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub data_export_url: &'a str,
+    pub attributes: &'a serde_json::Value,
+}
+
+impl IssueTemplate {
+    const OPEN_TAG: &'static str = "{{";
+    const CLOSE_TAG: &'static str = "}}";
+    /// Attributes whose value is loaded or followed as a URL by email clients
+    const URL_ATTRIBUTES: [&'static str; 3] = ["href", "src", "background"];
+    /// Schemes a value may start with when it opens a URL attribute
+    const SAFE_URL_SCHEMES: [&'static str; 3] = ["http://", "https://", "mailto:"];
+
+    ///
+    /// Parse the issue content, rejecting unknown variables, unknown filters and unclosed tags
+    ///
+    pub fn parse(s: &str) -> Result<IssueTemplate, String> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find(Self::OPEN_TAG) {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+
+            let after_open = &rest[start + Self::OPEN_TAG.len()..];
+            let end = after_open
+                .find(Self::CLOSE_TAG)
+                .ok_or_else(|| format!("Unclosed template tag near '{}'", &rest[start..]))?;
+
+            segments.push(parse_tag(&after_open[..end])?);
+            rest = &after_open[end + Self::CLOSE_TAG.len()..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        Ok(Self { segments })
+    }
+
+    ///
+    /// Render the template for a single recipient, escaping substituted values when targeting HTML.
+    /// The HTML is sanitised before rendering, so a value opening a URL attribute (e.g.
+    /// `href="{{ attributes.website }}"`) must be an http(s) or mailto URL: anything else, like
+    /// `javascript:`, is treated as missing.
+    ///
+    pub fn render(&self, context: &PersonalisationContext, escape: TemplateEscape) -> String {
+        let mut output = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => output.push_str(literal),
+                Segment::Tag { variable, default } => {
+                    let opens_url = escape == TemplateEscape::Html && opens_url_attribute(&output);
+                    let value = variable
+                        .resolve(context)
+                        .into_iter()
+                        .chain(default.clone())
+                        .find(|v| !v.is_empty() && (!opens_url || is_safe_url(v)))
+                        .unwrap_or_default();
+
+                    match escape {
+                        TemplateEscape::Html => {
+                            output.push_str(&htmlescape::encode_minimal(&value))
+                        }
+                        TemplateEscape::None => output.push_str(&value),
+                    }
+                }
+            }
+        }
+
+        output
+    }
+}
+
+///
+/// Template tags swapped out for opaque markers, so that content transformations (e.g. Markdown
+/// rendering) cannot escape or mangle them. Markers survive HTML escaping and are restored from
+/// either their raw or percent-encoded form.
+///
+pub struct ProtectedTags {
+    tags: Vec<String>,
+}
+
+impl ProtectedTags {
+    const MARKER_START: char = '\u{E000}';
+    const MARKER_END: char = '\u{E001}';
+    const ENCODED_MARKER_START: &'static str = "%EE%80%80";
+    const ENCODED_MARKER_END: &'static str = "%EE%80%81";
+
+    pub fn protect(s: &str) -> (String, ProtectedTags) {
+        let mut tags = Vec::new();
+        let mut output = String::with_capacity(s.len());
+        let mut rest = s;
+
+        while let Some(start) = rest.find(IssueTemplate::OPEN_TAG) {
+            let Some(end) = rest[start..].find(IssueTemplate::CLOSE_TAG) else {
+                break;
+            };
+            let end = start + end + IssueTemplate::CLOSE_TAG.len();
+
+            output.push_str(&rest[..start]);
+            output.push(Self::MARKER_START);
+            output.push_str(&tags.len().to_string());
+            output.push(Self::MARKER_END);
+
+            tags.push(rest[start..end].to_string());
+            rest = &rest[end..];
+        }
+        output.push_str(rest);
+
+        (output, ProtectedTags { tags })
+    }
+
+    pub fn restore(&self, s: &str) -> String {
+        let mut output = s.to_string();
+
+        for (index, tag) in self.tags.iter().enumerate().rev() {
+            let raw = format!("{}{}{}", Self::MARKER_START, index, Self::MARKER_END);
+            let encoded = format!(
+                "{}{}{}",
+                Self::ENCODED_MARKER_START,
+                index,
+                Self::ENCODED_MARKER_END
+            );
+            output = output.replace(&raw, tag).replace(&encoded, tag);
+        }
+
+        output
+    }
+}
+
+impl Variable {
+    fn parse(s: &str) -> Result<Variable, String> {
+        match s {
+            "subscriber.name" => Ok(Self::SubscriberName),
+            "subscriber.email" => Ok(Self::SubscriberEmail),
+            "unsubscribe_url" => Ok(Self::UnsubscribeUrl),
+            "data_export_url" => Ok(Self::DataExportUrl),
+            other => match other.strip_prefix("attributes.") {
+                Some(key)
+                    if !key.is_empty()
+                        && key
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+                {
+                    Ok(Self::Attribute(key.to_string()))
+                }
+                _ => Err(format!("Unknown template variable '{}'", other)),
+            },
+        }
+    }
+
+    fn resolve(&self, context: &PersonalisationContext) -> Option<String> {
+        match self {
+            Variable::SubscriberName => Some(context.subscriber_name.to_string()),
+            Variable::SubscriberEmail => Some(context.subscriber_email.to_string()),
+            Variable::UnsubscribeUrl => Some(context.unsubscribe_url.to_string()),
+            Variable::DataExportUrl => Some(context.data_export_url.to_string()),
+            Variable::Attribute(key) => match context.attributes.get(key)? {
+                serde_json::Value::String(s) => Some(s.clone()),
+                serde_json::Value::Number(n) => Some(n.to_string()),
+                serde_json::Value::Bool(b) => Some(b.to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+///
+/// Whether the HTML rendered so far ends inside the start of a URL attribute's value
+///
+fn opens_url_attribute(html: &str) -> bool {
+    let html = html.strip_suffix(['"', '\'']).unwrap_or(html);
+    let Some(html) = html.trim_end().strip_suffix('=') else {
+        return false;
+    };
+    let html = html.trim_end();
+
+    IssueTemplate::URL_ATTRIBUTES.iter().any(|attribute| {
+        html.len() > attribute.len()
+            && html
+                .get(html.len() - attribute.len()..)
+                .is_some_and(|name| name.eq_ignore_ascii_case(attribute))
+            && html[..html.len() - attribute.len()].ends_with(|c: char| c.is_ascii_whitespace())
+    })
+}
+
+fn is_safe_url(value: &str) -> bool {
+    let value = value.trim_start();
+    IssueTemplate::SAFE_URL_SCHEMES.iter().any(|scheme| {
+        value
+            .get(..scheme.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    })
+}
+
+fn parse_tag(tag: &str) -> Result<Segment, String> {
+    let mut parts = tag.splitn(2, '|');
+    let variable = parts.next().unwrap_or_default().trim();
+
+    if variable.is_empty() {
+        return Err("Template tags must name a variable".to_string());
+    }
+
+    let variable = Variable::parse(variable)?;
+    let default = match parts.next() {
+        Some(filter) => Some(parse_default_filter(filter.trim())?),
+        None => None,
+    };
+
+    Ok(Segment::Tag { variable, default })
+}
+
+fn parse_default_filter(filter: &str) -> Result<String, String> {
+    let argument = filter
+        .strip_prefix("default")
+        .and_then(|f| f.trim_start().strip_prefix(':'))
+        .ok_or_else(|| {
+            format!(
+                "Unsupported template filter '{}', only 'default' is available",
+                filter
+            )
+        })?
+        .trim();
+
+    argument
+        .strip_prefix('"')
+        .and_then(|a| a.strip_suffix('"'))
+        .filter(|a| !a.contains('"'))
+        .map(str::to_string)
+        .ok_or_else(|| format!("The default value {} must be a quoted string", argument))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueTemplate, PersonalisationContext, ProtectedTags, TemplateEscape};
+    use claims::{assert_err, assert_ok};
+
+    fn render(template: &str, attributes: serde_json::Value, escape: TemplateEscape) -> String {
+        let context = PersonalisationContext {
+            subscriber_name: "Ursula <Le Guin>",
+            subscriber_email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=abc&x=1",
+            data_export_url: "https://example.com/export?token=def",
+            attributes: &attributes,
+        };
+
+        IssueTemplate::parse(template)
+            .unwrap()
+            .render(&context, escape)
+    }
+
+    #[test]
+    fn protected_tags_are_restored_verbatim() {
+        let source =
+            r#"<a href="{{ unsubscribe_url }}">{{ attributes.company | default: "friend" }}</a>"#;
+        let (protected, tags) = ProtectedTags::protect(source);
+
+        assert!(!protected.contains("{{"));
+        assert_eq!(tags.restore(&protected), source);
+        assert_eq!(
+            tags.restore(
+                &protected
+                    .replace('\u{E000}', "%EE%80%80")
+                    .replace('\u{E001}', "%EE%80%81")
+            ),
+            source
+        );
+    }
+
+    #[test]
+    fn content_without_tags_is_left_untouched() {
+        let output = render("<p>Hello</p>", serde_json::json!({}), TemplateEscape::Html);
+        assert_eq!(output, "<p>Hello</p>");
+    }
+
+    #[test]
+    fn known_variables_are_parsed_successfully() {
+        assert_ok!(IssueTemplate::parse(
+            "{{ subscriber.name }} {{subscriber.email}} {{ unsubscribe_url }} {{ data_export_url }} {{ attributes.company }}"
+        ));
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hello {{ subscriber.password }}"));
+        assert_err!(IssueTemplate::parse("Hello {{ attributes. }}"));
+        assert_err!(IssueTemplate::parse("Hello {{ }}"));
+    }
+
+    #[test]
+    fn unclosed_tags_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hello {{ subscriber.name"));
+    }
+
+    #[test]
+    fn unknown_filters_are_rejected() {
+        assert_err!(IssueTemplate::parse("{{ subscriber.name | upcase }}"));
+        assert_err!(IssueTemplate::parse(
+            "{{ subscriber.name | default: friend }}"
+        ));
+    }
+
+    #[test]
+    fn values_are_html_escaped_in_the_html_part() {
+        let output = render(
+            "Hi {{ subscriber.name }}",
+            serde_json::json!({}),
+            TemplateEscape::Html,
+        );
+        assert_eq!(output, "Hi Ursula &lt;Le Guin&gt;");
+    }
+
+    #[test]
+    fn values_are_not_escaped_in_the_text_part() {
+        let output = render(
+            "Hi {{ subscriber.name }} {{ unsubscribe_url }}",
+            serde_json::json!({}),
+            TemplateEscape::None,
+        );
+        assert_eq!(
+            output,
+            "Hi Ursula <Le Guin> https://example.com/unsubscribe?token=abc&x=1"
+        );
+    }
+
+    #[test]
+    fn missing_attributes_fall_back_to_the_default() {
+        let template = r#"Hello {{ attributes.company | default: "friend" }}"#;
+
+        let output = render(template, serde_json::json!({}), TemplateEscape::None);
+        assert_eq!(output, "Hello friend");
+
+        let output = render(
+            template,
+            serde_json::json!({ "company": "Earthsea" }),
+            TemplateEscape::None,
+        );
+        assert_eq!(output, "Hello Earthsea");
+    }
+
+    #[test]
+    fn only_web_and_mailto_urls_can_open_a_url_attribute() {
+        let template = r#"<a href="{{ attributes.website }}">Site</a><img src='{{ attributes.logo | default: "https://example.com/logo.png" }}'>"#;
+
+        let output = render(
+            template,
+            serde_json::json!({ "website": "javascript:alert(1)", "logo": "data:image/png;base64,AAAA" }),
+            TemplateEscape::Html,
+        );
+        assert_eq!(
+            output,
+            r#"<a href="">Site</a><img src='https://example.com/logo.png'>"#
+        );
+
+        let output = render(
+            template,
+            serde_json::json!({ "website": "HTTPS://earthsea.example", "logo": "mailto:ged@earthsea.example" }),
+            TemplateEscape::Html,
+        );
+        assert_eq!(
+            output,
+            r#"<a href="HTTPS://earthsea.example">Site</a><img src='mailto:ged@earthsea.example'>"#
+        );
+    }
+
+    #[test]
+    fn values_later_in_a_url_or_outside_attributes_are_not_restricted() {
+        let output = render(
+            r#"<a href="https://example.com/?ref={{ attributes.ref }}" title="{{ attributes.ref }}">{{ attributes.ref }}</a>"#,
+            serde_json::json!({ "ref": "javascript:x" }),
+            TemplateEscape::Html,
+        );
+        assert_eq!(
+            output,
+            r#"<a href="https://example.com/?ref=javascript:x" title="javascript:x">javascript:x</a>"#
+        );
+
+        let output = render(
+            "href={{ attributes.ref }}",
+            serde_json::json!({ "ref": "javascript:x" }),
+            TemplateEscape::None,
+        );
+        assert_eq!(output, "href=javascript:x");
+    }
+
+    #[test]
+    fn missing_attributes_without_a_default_render_empty() {
+        let output = render(
+            "[{{ attributes.company }}]",
+            serde_json::json!({}),
+            TemplateEscape::None,
+        );
+        assert_eq!(output, "[]");
+    }
+}
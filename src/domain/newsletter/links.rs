@@ -0,0 +1,189 @@
+use super::TemplateEscape;
+
+///
+/// The http(s) links of an issue, swapped out for opaque markers so each recipient can be sent
+/// their own tracking URL. The same URL appearing in both parts (or several times) shares a link.
+///
+#[derive(Debug, Default)]
+pub struct TrackedLinks {
+    urls: Vec<String>,
+}
+
+impl TrackedLinks {
+    const MARKER_START: char = '\u{E002}';
+    const MARKER_END: char = '\u{E003}';
+    const HREF: &'static str = "href=\"";
+    const SCHEMES: [&'static str; 2] = ["https://", "http://"];
+
+    ///
+    /// Replace the `href` targets of the (sanitised) HTML part and the bare URLs of the text part
+    ///
+    pub fn extract(html: &str, text: &str) -> (String, String, TrackedLinks) {
+        let mut links = TrackedLinks::default();
+        let html = links.replace_hrefs(html);
+        let text = links.replace_bare_urls(text);
+
+        (html, text, links)
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    ///
+    /// Swap every marker for the URL `link_url` returns for the link at that index
+    ///
+    pub fn apply(
+        &self,
+        s: &str,
+        escape: TemplateEscape,
+        link_url: impl Fn(usize) -> String,
+    ) -> String {
+        let mut output = s.to_string();
+
+        for index in (0..self.urls.len()).rev() {
+            let url = link_url(index);
+            let url = match escape {
+                TemplateEscape::Html => htmlescape::encode_minimal(&url),
+                TemplateEscape::None => url,
+            };
+            output = output.replace(&Self::marker(index), &url);
+        }
+
+        output
+    }
+
+    fn marker(index: usize) -> String {
+        format!("{}{}{}", Self::MARKER_START, index, Self::MARKER_END)
+    }
+
+    fn marker_for(&mut self, url: String) -> String {
+        let index = match self.urls.iter().position(|u| *u == url) {
+            Some(index) => index,
+            None => {
+                self.urls.push(url);
+                self.urls.len() - 1
+            }
+        };
+
+        Self::marker(index)
+    }
+
+    fn replace_hrefs(&mut self, html: &str) -> String {
+        let mut output = String::with_capacity(html.len());
+        let mut rest = html;
+
+        while let Some(start) = rest.find(Self::HREF) {
+            let value_start = start + Self::HREF.len();
+            let Some(value_length) = rest[value_start..].find('"') else {
+                break;
+            };
+            let value = &rest[value_start..value_start + value_length];
+            output.push_str(&rest[..value_start]);
+
+            match htmlescape::decode_html(value) {
+                Ok(url) if Self::is_trackable(&url) => output.push_str(&self.marker_for(url)),
+                _ => output.push_str(value),
+            }
+
+            rest = &rest[value_start + value_length..];
+        }
+        output.push_str(rest);
+
+        output
+    }
+
+    fn replace_bare_urls(&mut self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = Self::SCHEMES.iter().filter_map(|s| rest.find(s)).min() {
+            let length = rest[start..]
+                .find(|c: char| c.is_whitespace() || "<>\"'".contains(c))
+                .unwrap_or(rest.len() - start);
+            // Punctuation closing a sentence is not part of the link
+            let url = rest[start..start + length].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+
+            output.push_str(&rest[..start]);
+            if Self::is_trackable(url) {
+                output.push_str(&self.marker_for(url.to_string()));
+            } else {
+                output.push_str(url);
+            }
+            rest = &rest[start + url.len()..];
+        }
+        output.push_str(rest);
+
+        output
+    }
+
+    ///
+    /// Personalised links are left alone, a single stored URL cannot serve every recipient
+    ///
+    fn is_trackable(url: &str) -> bool {
+        Self::SCHEMES.iter().any(|scheme| url.starts_with(scheme)) && !url.contains("{{")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrackedLinks;
+    use crate::domain::TemplateEscape;
+
+    fn apply(links: &TrackedLinks, s: &str, escape: TemplateEscape) -> String {
+        links.apply(s, escape, |index| {
+            format!("https://t.example/{}?a=1&b=2", index)
+        })
+    }
+
+    #[test]
+    fn links_are_shared_between_the_html_and_text_parts() {
+        let (html, text, links) = TrackedLinks::extract(
+            r#"<a href="https://example.com/post">post</a> <a href="https://example.com/other">other</a>"#,
+            "Read the post [1]\n\nLinks:\n[1] https://example.com/post",
+        );
+
+        assert_eq!(
+            links.urls(),
+            ["https://example.com/post", "https://example.com/other"]
+        );
+        assert_eq!(
+            apply(&links, &html, TemplateEscape::Html),
+            r#"<a href="https://t.example/0?a=1&amp;b=2">post</a> <a href="https://t.example/1?a=1&amp;b=2">other</a>"#
+        );
+        assert_eq!(
+            apply(&links, &text, TemplateEscape::None),
+            "Read the post [1]\n\nLinks:\n[1] https://t.example/0?a=1&b=2"
+        );
+    }
+
+    #[test]
+    fn escaped_hrefs_are_decoded() {
+        let (_, _, links) =
+            TrackedLinks::extract(r#"<a href="https://example.com/?a=1&amp;b=2">x</a>"#, "");
+        assert_eq!(links.urls(), ["https://example.com/?a=1&b=2"]);
+    }
+
+    #[test]
+    fn non_http_and_personalised_links_are_left_alone() {
+        let html = r##"<a href="mailto:me@example.com">me</a><a href="#top">top</a><a href="{{ unsubscribe_url }}">unsubscribe</a>"##;
+        let text = "Share https://example.com/?ref={{ subscriber.email }} with a friend";
+        let (rewritten_html, rewritten_text, links) = TrackedLinks::extract(html, text);
+
+        assert!(links.urls().is_empty());
+        assert_eq!(rewritten_html, html);
+        assert_eq!(rewritten_text, text);
+    }
+
+    #[test]
+    fn trailing_punctuation_is_not_part_of_a_bare_url() {
+        let (_, text, links) =
+            TrackedLinks::extract("", "See https://example.com/post. Or http://example.com!");
+
+        assert_eq!(
+            links.urls(),
+            ["https://example.com/post", "http://example.com"]
+        );
+        assert!(text.starts_with("See \u{E002}0\u{E003}. Or \u{E002}1\u{E003}!"));
+    }
+}
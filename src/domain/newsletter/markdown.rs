@@ -0,0 +1,517 @@
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+use textwrap::WordSeparator;
+
+use super::ProtectedTags;
+
+///
+/// A newsletter issue authored in Markdown (CommonMark with tables and footnotes),
+/// from which both the HTML and the plain text parts of the email are derived.
+/// Personalisation tags are carried through both parts untouched.
+///
+pub struct MarkdownIssue {
+    source: String,
+}
+
+impl MarkdownIssue {
+    const TEXT_WIDTH: usize = 72;
+
+    pub fn new(source: String) -> Self {
+        Self { source }
+    }
+
+    fn options() -> Options {
+        Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH
+    }
+
+    ///
+    /// Render the issue as a complete, styled HTML document
+    ///
+    pub fn to_html(&self, title: &str) -> String {
+        let (source, tags) = ProtectedTags::protect(&self.source);
+
+        let mut body = String::new();
+        html::push_html(&mut body, Parser::new_ext(&source, Self::options()));
+
+        tags.restore(&format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>{title}</title>
+<style>
+body {{ margin: 0; padding: 0; background-color: #f4f4f4; }}
+.issue {{ max-width: 600px; margin: 0 auto; padding: 24px; background-color: #ffffff; font-family: Helvetica, Arial, sans-serif; font-size: 16px; line-height: 1.5; color: #222222; }}
+h1, h2, h3 {{ line-height: 1.25; color: #111111; }}
+a {{ color: #1a73e8; }}
+blockquote {{ margin: 0; padding-left: 16px; border-left: 4px solid #dddddd; color: #555555; }}
+pre {{ padding: 12px; background-color: #f6f8fa; overflow-x: auto; }}
+code {{ font-family: Menlo, Consolas, monospace; font-size: 14px; }}
+table {{ border-collapse: collapse; }}
+th, td {{ padding: 6px 12px; border: 1px solid #dddddd; }}
+.footnote-definition {{ font-size: 14px; color: #555555; }}
+</style>
+</head>
+<body>
+<div class="issue">
+{body}</div>
+</body>
+</html>
+"#,
+            title = htmlescape::encode_minimal(title),
+        ))
+    }
+
+    ///
+    /// Render the issue as wrapped plain text, with links listed as numbered references at the end
+    ///
+    pub fn to_text(&self) -> String {
+        let (source, tags) = ProtectedTags::protect(&self.source);
+
+        let mut renderer = TextRenderer::new(Self::TEXT_WIDTH);
+        for event in Parser::new_ext(&source, Self::options()) {
+            renderer.handle(event);
+        }
+
+        tags.restore(&renderer.finish())
+    }
+}
+
+enum Container {
+    BlockQuote,
+    Item { marker: String, first_line: bool },
+}
+
+#[derive(Default)]
+struct Table {
+    rows: Vec<Vec<String>>,
+    header_rows: usize,
+}
+
+struct TextRenderer {
+    width: usize,
+    output: String,
+    inline: String,
+    containers: Vec<Container>,
+    lists: Vec<Option<u64>>,
+    links: Vec<String>,
+    open_links: Vec<String>,
+    code_block: Option<String>,
+    table: Option<Table>,
+}
+
+impl TextRenderer {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            output: String::new(),
+            inline: String::new(),
+            containers: Vec::new(),
+            lists: Vec::new(),
+            links: Vec::new(),
+            open_links: Vec::new(),
+            code_block: None,
+            table: None,
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match self.code_block.as_mut() {
+                Some(code) => code.push_str(&text),
+                None => self.push_inline(&text),
+            },
+            Event::Code(code) => self.push_inline(&format!("`{}`", code)),
+            Event::FootnoteReference(label) => self.push_inline(&format!("[^{}]", label)),
+            Event::SoftBreak => self.push_inline(" "),
+            Event::HardBreak => self.push_inline("\n"),
+            Event::Rule => {
+                self.flush_block();
+                let rule = "-".repeat(self.width.min(40));
+                self.push_lines(&rule);
+                self.output.push('\n');
+            }
+            Event::TaskListMarker(checked) => {
+                self.push_inline(if checked { "[x] " } else { "[ ] " })
+            }
+            Event::Html(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::Heading(..) => self.flush_block(),
+            Tag::BlockQuote => {
+                self.flush_block();
+                self.containers.push(Container::BlockQuote);
+            }
+            Tag::CodeBlock(_) => {
+                self.flush_block();
+                self.code_block = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.flush_block();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush_block();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        let marker = format!("{}. ", number);
+                        *number += 1;
+                        marker
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.containers.push(Container::Item {
+                    marker,
+                    first_line: true,
+                });
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush_block();
+                self.push_inline(&format!("[^{}]: ", label));
+            }
+            Tag::Table(_) => {
+                self.flush_block();
+                self.table = Some(Table::default());
+            }
+            Tag::TableHead => {
+                if let Some(table) = self.table.as_mut() {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableRow => {
+                if let Some(table) = self.table.as_mut() {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => {
+                if let Some(row) = self.table.as_mut().and_then(|t| t.rows.last_mut()) {
+                    row.push(String::new());
+                }
+            }
+            Tag::Emphasis => self.push_inline("_"),
+            Tag::Strong => self.push_inline("*"),
+            Tag::Strikethrough => self.push_inline("~"),
+            Tag::Link(_, destination, _) => self.open_links.push(destination.to_string()),
+            Tag::Image(_, destination, _) => {
+                self.open_links.push(destination.to_string());
+                self.push_inline("[image: ");
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                self.flush_block();
+                self.blank_line();
+            }
+            Tag::Heading(level, ..) => {
+                // Underlines can't be used as the heading length is only known once personalised
+                let heading = std::mem::take(&mut self.inline);
+                self.push_lines(&format!(
+                    "{} {}",
+                    "#".repeat(level as usize),
+                    heading.trim()
+                ));
+                self.blank_line();
+            }
+            Tag::BlockQuote => {
+                self.flush_block();
+                self.containers.pop();
+                self.blank_line();
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code_block.take().unwrap_or_default();
+                for line in code.trim_end_matches('\n').lines() {
+                    self.push_lines(&format!("    {}", line));
+                }
+                self.blank_line();
+            }
+            Tag::List(_) => {
+                self.flush_block();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank_line();
+                }
+            }
+            Tag::Item => {
+                self.flush_block();
+                self.containers.pop();
+            }
+            Tag::FootnoteDefinition(_) => {
+                self.flush_block();
+                self.blank_line();
+            }
+            Tag::Table(_) => {
+                if let Some(table) = self.table.take() {
+                    self.render_table(table);
+                }
+                self.blank_line();
+            }
+            Tag::TableHead => {
+                if let Some(table) = self.table.as_mut() {
+                    table.header_rows = table.rows.len();
+                }
+            }
+            Tag::TableRow | Tag::TableCell => {}
+            Tag::Emphasis => self.push_inline("_"),
+            Tag::Strong => self.push_inline("*"),
+            Tag::Strikethrough => self.push_inline("~"),
+            Tag::Link(..) => {
+                if let Some(destination) = self.open_links.pop() {
+                    // Autolinks already show their URL, there is no need to repeat it
+                    if !self.current_inline().ends_with(destination.as_str()) {
+                        let reference = self.add_link(destination);
+                        self.push_inline(&format!(" [{}]", reference));
+                    }
+                }
+            }
+            Tag::Image(..) => {
+                if let Some(destination) = self.open_links.pop() {
+                    let reference = self.add_link(destination);
+                    self.push_inline(&format!("] [{}]", reference));
+                }
+            }
+        }
+    }
+
+    fn add_link(&mut self, destination: String) -> usize {
+        match self.links.iter().position(|l| *l == destination) {
+            Some(index) => index + 1,
+            None => {
+                self.links.push(destination);
+                self.links.len()
+            }
+        }
+    }
+
+    fn current_inline(&self) -> &str {
+        match self
+            .table
+            .as_ref()
+            .and_then(|t| t.rows.last())
+            .and_then(|r| r.last())
+        {
+            Some(cell) => cell,
+            None => &self.inline,
+        }
+    }
+
+    fn push_inline(&mut self, s: &str) {
+        match self
+            .table
+            .as_mut()
+            .and_then(|t| t.rows.last_mut())
+            .and_then(|r| r.last_mut())
+        {
+            Some(cell) => cell.push_str(s),
+            None => self.inline.push_str(s),
+        }
+    }
+
+    fn prefix(&mut self) -> (String, String) {
+        let mut initial = String::new();
+        let mut subsequent = String::new();
+
+        for container in self.containers.iter_mut() {
+            match container {
+                Container::BlockQuote => {
+                    initial.push_str("> ");
+                    subsequent.push_str("> ");
+                }
+                Container::Item { marker, first_line } => {
+                    if *first_line {
+                        initial.push_str(marker);
+                        *first_line = false;
+                    } else {
+                        initial.push_str(&" ".repeat(marker.len()));
+                    }
+                    subsequent.push_str(&" ".repeat(marker.len()));
+                }
+            }
+        }
+
+        (initial, subsequent)
+    }
+
+    fn flush_block(&mut self) {
+        let block = std::mem::take(&mut self.inline);
+        if block.trim().is_empty() {
+            return;
+        }
+
+        let (initial, subsequent) = self.prefix();
+        let options = textwrap::Options::new(self.width)
+            .word_separator(WordSeparator::AsciiSpace)
+            .break_words(false)
+            .initial_indent(&initial)
+            .subsequent_indent(&subsequent);
+
+        for (i, line) in block.trim().split('\n').enumerate() {
+            let options = if i == 0 {
+                options.clone()
+            } else {
+                options.clone().initial_indent(&subsequent)
+            };
+            self.output.push_str(&textwrap::fill(line.trim(), options));
+            self.output.push('\n');
+        }
+    }
+
+    fn push_lines(&mut self, s: &str) {
+        let (initial, subsequent) = self.prefix();
+        for (i, line) in s.lines().enumerate() {
+            self.output
+                .push_str(if i == 0 { &initial } else { &subsequent });
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+    }
+
+    fn blank_line(&mut self) {
+        if !self.output.is_empty() && !self.output.ends_with("\n\n") {
+            self.output.push('\n');
+        }
+    }
+
+    fn render_table(&mut self, table: Table) {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in &table.rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.trim().chars().count());
+            }
+        }
+
+        let format_row = |row: &Vec<String>| {
+            (0..columns)
+                .map(|i| {
+                    let cell = row.get(i).map(|c| c.trim()).unwrap_or_default();
+                    format!("{:width$}", cell, width = widths[i])
+                })
+                .collect::<Vec<_>>()
+                .join(" | ")
+                .trim_end()
+                .to_string()
+        };
+
+        for (i, row) in table.rows.iter().enumerate() {
+            let line = format_row(row);
+            self.push_lines(&line);
+            if i + 1 == table.header_rows {
+                let separator = widths
+                    .iter()
+                    .map(|w| "-".repeat(*w))
+                    .collect::<Vec<_>>()
+                    .join("-+-");
+                self.push_lines(&separator);
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush_block();
+        let mut output = self.output.trim_end().to_string();
+
+        if !self.links.is_empty() {
+            output.push_str("\n\nLinks:\n");
+            for (i, link) in self.links.iter().enumerate() {
+                output.push_str(&format!("[{}] {}\n", i + 1, link));
+            }
+        } else {
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MarkdownIssue;
+
+    #[test]
+    fn html_part_is_a_styled_document() {
+        let html = MarkdownIssue::new("# Hello\n\nSome *text*".to_string()).to_html("Issue <1>");
+
+        assert!(html.contains("<title>Issue &lt;1&gt;</title>"));
+        assert!(html.contains("<style>"));
+        assert!(html.contains("<h1>Hello</h1>"));
+        assert!(html.contains("<em>text</em>"));
+    }
+
+    #[test]
+    fn tables_and_footnotes_are_rendered_to_html() {
+        let markdown = "| a | b |\n|---|---|\n| 1 | 2 |\n\nNote[^1]\n\n[^1]: The footnote";
+        let html = MarkdownIssue::new(markdown.to_string()).to_html("Issue");
+
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<td>2</td>"));
+        assert!(html.contains("footnote-definition"));
+    }
+
+    #[test]
+    fn links_are_rendered_as_numbered_references_in_text() {
+        let markdown = "Read [the post](https://example.com/post) and \
+[the other post](https://example.com/other), or [the post](https://example.com/post) again.";
+        let text = MarkdownIssue::new(markdown.to_string()).to_text();
+
+        assert_eq!(
+            text,
+            "Read the post [1] and the other post [2], or the post [1] again.\n\
+\n\
+Links:\n\
+[1] https://example.com/post\n\
+[2] https://example.com/other\n"
+        );
+    }
+
+    #[test]
+    fn long_paragraphs_are_wrapped_in_text() {
+        let markdown = "word ".repeat(40);
+        let text = MarkdownIssue::new(markdown).to_text();
+
+        assert!(text.lines().count() > 1);
+        assert!(text.lines().all(|l| l.chars().count() <= 72));
+    }
+
+    #[test]
+    fn lists_headings_and_quotes_are_rendered_in_text() {
+        let markdown = "Title\n=====\n\n- one\n- two\n\n1. first\n2. second\n\n> quoted";
+        let text = MarkdownIssue::new(markdown.to_string()).to_text();
+
+        assert_eq!(
+            text,
+            "# Title\n\n- one\n- two\n\n1. first\n2. second\n\n> quoted\n"
+        );
+    }
+
+    #[test]
+    fn tables_are_aligned_in_text() {
+        let markdown = "| name | count |\n|---|---|\n| apples | 2 |";
+        let text = MarkdownIssue::new(markdown.to_string()).to_text();
+
+        assert_eq!(text, "name   | count\n-------+------\napples | 2\n");
+    }
+
+    #[test]
+    fn personalisation_tags_survive_both_parts() {
+        let markdown =
+            r#"Hi {{ attributes.first_name | default: "friend" }}, [leave]({{ unsubscribe_url }})"#;
+        let issue = MarkdownIssue::new(markdown.to_string());
+
+        let html = issue.to_html("Issue");
+        assert!(html.contains(r#"Hi {{ attributes.first_name | default: "friend" }}"#));
+        assert!(html.contains(r#"<a href="{{ unsubscribe_url }}">leave</a>"#));
+
+        let text = issue.to_text();
+        assert!(
+            text.starts_with(r#"Hi {{ attributes.first_name | default: "friend" }}, leave [1]"#)
+        );
+        assert!(text.ends_with("[1] {{ unsubscribe_url }}\n"));
+    }
+}
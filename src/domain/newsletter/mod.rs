@@ -0,0 +1,11 @@
+mod email_html;
+mod issue_template;
+mod links;
+mod markdown;
+mod tracking;
+
+pub use email_html::EmailHtml;
+pub use issue_template::{IssueTemplate, PersonalisationContext, ProtectedTags, TemplateEscape};
+pub use links::TrackedLinks;
+pub use markdown::MarkdownIssue;
+pub use tracking::{is_machine_open, ClickToken, TrackingToken};
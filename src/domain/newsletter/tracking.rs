@@ -0,0 +1,214 @@
+use secrecy::Secret;
+use uuid::Uuid;
+
+use crate::domain::signed_token::{decode_signed, encode_signed};
+
+///
+/// Identifies a single recipient of a single issue inside a tracking URL.
+/// The ids are signed with the application HMAC secret so that tokens cannot be forged or
+/// enumerated, the signature is truncated to keep URLs short.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackingToken {
+    pub issue_id: Uuid,
+    pub subscriber_id: Uuid,
+}
+
+impl TrackingToken {
+    const PURPOSE: &'static str = "open";
+    const PAYLOAD_LENGTH: usize = 32;
+
+    pub fn new(issue_id: Uuid, subscriber_id: Uuid) -> Self {
+        Self {
+            issue_id,
+            subscriber_id,
+        }
+    }
+
+    pub fn encode(&self, secret: &Secret<String>) -> String {
+        let mut payload = [0; Self::PAYLOAD_LENGTH];
+        payload[..16].copy_from_slice(self.issue_id.as_bytes());
+        payload[16..].copy_from_slice(self.subscriber_id.as_bytes());
+
+        encode_signed(Self::PURPOSE, &payload, secret)
+    }
+
+    ///
+    /// Decode the token, rejecting anything which was not signed with `secret`
+    ///
+    pub fn decode(token: &str, secret: &Secret<String>) -> Result<Self, String> {
+        let payload = decode_signed(Self::PURPOSE, token, Self::PAYLOAD_LENGTH, secret)?;
+
+        Ok(Self::new(
+            Uuid::from_slice(&payload[..16]).map_err(|e| e.to_string())?,
+            Uuid::from_slice(&payload[16..]).map_err(|e| e.to_string())?,
+        ))
+    }
+}
+
+///
+/// Identifies a single link clicked by a single recipient of a single issue.
+/// Signed with a different purpose to [`TrackingToken`], so one can never be passed off as the other.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClickToken {
+    pub issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    pub link_id: i32,
+}
+
+impl ClickToken {
+    const PURPOSE: &'static str = "click";
+    const PAYLOAD_LENGTH: usize = 36;
+
+    pub fn new(issue_id: Uuid, subscriber_id: Uuid, link_id: i32) -> Self {
+        Self {
+            issue_id,
+            subscriber_id,
+            link_id,
+        }
+    }
+
+    pub fn encode(&self, secret: &Secret<String>) -> String {
+        let mut payload = [0; Self::PAYLOAD_LENGTH];
+        payload[..16].copy_from_slice(self.issue_id.as_bytes());
+        payload[16..32].copy_from_slice(self.subscriber_id.as_bytes());
+        payload[32..].copy_from_slice(&self.link_id.to_be_bytes());
+
+        encode_signed(Self::PURPOSE, &payload, secret)
+    }
+
+    ///
+    /// Decode the token, rejecting anything which was not signed with `secret`
+    ///
+    pub fn decode(token: &str, secret: &Secret<String>) -> Result<Self, String> {
+        let payload = decode_signed(Self::PURPOSE, token, Self::PAYLOAD_LENGTH, secret)?;
+        let link_id: [u8; 4] = payload[32..].try_into().map_err(|_| "Invalid link id")?;
+
+        Ok(Self::new(
+            Uuid::from_slice(&payload[..16]).map_err(|e| e.to_string())?,
+            Uuid::from_slice(&payload[16..32]).map_err(|e| e.to_string())?,
+            i32::from_be_bytes(link_id),
+        ))
+    }
+}
+
+///
+/// Whether an open was most likely triggered by a machine rather than the recipient.
+/// Privacy proxies (e.g. Apple Mail Privacy Protection, Gmail's image proxy) and security
+/// scanners fetch every image as soon as the email arrives, so their opens say nothing about
+/// whether the issue was read.
+///
+pub fn is_machine_open(user_agent: Option<&str>) -> bool {
+    const MACHINE_USER_AGENTS: [&str; 9] = [
+        "googleimageproxy",
+        "yahoomailproxy",
+        "mimecast",
+        "barracuda",
+        "proofpoint",
+        "bot",
+        "crawler",
+        "spider",
+        "preview",
+    ];
+
+    let Some(user_agent) = user_agent.map(str::trim).filter(|ua| !ua.is_empty()) else {
+        return true;
+    };
+
+    // Apple's Mail Privacy Protection proxy sends a bare user agent with no platform details
+    if user_agent == "Mozilla/5.0" {
+        return true;
+    }
+
+    let user_agent = user_agent.to_lowercase();
+    MACHINE_USER_AGENTS
+        .iter()
+        .any(|machine| user_agent.contains(machine))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_machine_open, ClickToken, TrackingToken};
+    use claims::assert_err;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret(s: &str) -> Secret<String> {
+        Secret::new(s.to_string())
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let token = TrackingToken::new(Uuid::new_v4(), Uuid::new_v4());
+        let encoded = token.encode(&secret("secret"));
+
+        assert_eq!(
+            TrackingToken::decode(&encoded, &secret("secret")).unwrap(),
+            token
+        );
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let encoded = TrackingToken::new(Uuid::new_v4(), Uuid::new_v4()).encode(&secret("other"));
+        assert_err!(TrackingToken::decode(&encoded, &secret("secret")));
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let encoded = TrackingToken::new(Uuid::new_v4(), Uuid::new_v4()).encode(&secret("secret"));
+        // Swap the first character for a different one, whatever it happened to be
+        let replacement = if encoded.starts_with('A') { 'B' } else { 'A' };
+        let tampered = format!("{}{}", replacement, &encoded[1..]);
+
+        assert_err!(TrackingToken::decode(&tampered, &secret("secret")));
+        assert_err!(TrackingToken::decode(&encoded[1..], &secret("secret")));
+        assert_err!(TrackingToken::decode("not a token!", &secret("secret")));
+    }
+
+    #[test]
+    fn click_tokens_round_trip() {
+        let token = ClickToken::new(Uuid::new_v4(), Uuid::new_v4(), 7);
+        let encoded = token.encode(&secret("secret"));
+
+        assert_eq!(
+            ClickToken::decode(&encoded, &secret("secret")).unwrap(),
+            token
+        );
+    }
+
+    #[test]
+    fn open_and_click_tokens_are_not_interchangeable() {
+        let issue_id = Uuid::new_v4();
+        let subscriber_id = Uuid::new_v4();
+        let open = TrackingToken::new(issue_id, subscriber_id).encode(&secret("secret"));
+        let click = ClickToken::new(issue_id, subscriber_id, 0).encode(&secret("secret"));
+
+        assert_err!(ClickToken::decode(&open, &secret("secret")));
+        assert_err!(TrackingToken::decode(&click, &secret("secret")));
+    }
+
+    #[test]
+    fn mail_clients_are_counted_as_human_opens() {
+        assert!(!is_machine_open(Some(
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko)"
+        )));
+        assert!(!is_machine_open(Some(
+            "Microsoft Office/16.0 (Outlook 16.0)"
+        )));
+    }
+
+    #[test]
+    fn proxies_and_scanners_are_counted_as_machine_opens() {
+        assert!(is_machine_open(None));
+        assert!(is_machine_open(Some("")));
+        assert!(is_machine_open(Some("Mozilla/5.0")));
+        assert!(is_machine_open(Some(
+            "Mozilla/5.0 (Windows NT 5.1; rv:11.0) Gecko Firefox/11.0 (via ggpht.com GoogleImageProxy)"
+        )));
+        assert!(is_machine_open(Some(
+            "YahooMailProxy; https://help.yahoo.com"
+        )));
+    }
+}
@@ -0,0 +1,93 @@
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+/// Signatures are truncated to keep URLs short
+const SIGNATURE_LENGTH: usize = 16;
+
+///
+/// Sign `payload` with the application HMAC secret for `purpose` and encode it for use in a URL.
+/// Every token type has its own purpose, so one kind of token is never accepted as another.
+///
+pub(crate) fn encode_signed(purpose: &str, payload: &[u8], secret: &Secret<String>) -> String {
+    let signature = mac(purpose, payload, secret).finalize().into_bytes();
+
+    let mut token = payload.to_vec();
+    token.extend_from_slice(&signature[..SIGNATURE_LENGTH]);
+
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(token)
+}
+
+///
+/// Decode a token made by [`encode_signed`], returning its payload if it was signed with `secret`
+/// for `purpose`
+///
+pub(crate) fn decode_signed(
+    purpose: &str,
+    token: &str,
+    payload_length: usize,
+    secret: &Secret<String>,
+) -> Result<Vec<u8>, String> {
+    let mut bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|_| format!("The {} token is not valid base64", purpose))?;
+
+    if bytes.len() != payload_length + SIGNATURE_LENGTH {
+        return Err(format!("The {} token has an invalid length", purpose));
+    }
+
+    let signature = bytes.split_off(payload_length);
+    mac(purpose, &bytes, secret)
+        .verify_truncated_left(&signature)
+        .map_err(|_| format!("The {} token has an invalid signature", purpose))?;
+
+    Ok(bytes)
+}
+
+fn mac(purpose: &str, payload: &[u8], secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    // Length prefixed, so no purpose and payload can be read as another purpose and payload
+    mac.update(&(purpose.len() as u64).to_be_bytes());
+    mac.update(purpose.as_bytes());
+    mac.update(payload);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_signed, encode_signed};
+    use claims::assert_err;
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("secret".to_string())
+    }
+
+    #[test]
+    fn payloads_round_trip() {
+        let token = encode_signed("test", b"payload", &secret());
+
+        assert_eq!(
+            decode_signed("test", &token, 7, &secret()).unwrap(),
+            b"payload"
+        );
+    }
+
+    #[test]
+    fn tokens_are_only_accepted_for_their_own_purpose() {
+        let token = encode_signed("exp", b"ort-payload", &secret());
+
+        assert_err!(decode_signed("export", &token, 11, &secret()));
+        assert_err!(decode_signed("expo", &token, 11, &secret()));
+    }
+
+    #[test]
+    fn tokens_of_the_wrong_length_are_rejected() {
+        let token = encode_signed("test", b"payload", &secret());
+
+        assert_err!(decode_signed("test", &token, 8, &secret()));
+        assert_err!(decode_signed("test", "not a token!", 7, &secret()));
+    }
+}
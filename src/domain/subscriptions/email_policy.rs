@@ -0,0 +1,296 @@
+use std::collections::HashSet;
+
+use super::SubscriberEmail;
+
+/// Shared mailboxes for running a domain, nobody reads a newsletter sent to them
+const ROLE_ACCOUNTS: [&str; 10] = [
+    "abuse",
+    "donotreply",
+    "do-not-reply",
+    "hostmaster",
+    "mailer-daemon",
+    "noreply",
+    "no-reply",
+    "nobody",
+    "postmaster",
+    "webmaster",
+];
+
+/// Domains people commonly sign up with, typos are suggested against these
+const POPULAR_DOMAINS: [&str; 32] = [
+    "aol.com",
+    "btinternet.com",
+    "comcast.net",
+    "email.com",
+    "gmail.com",
+    "gmx.com",
+    "gmx.de",
+    "googlemail.com",
+    "hotmail.co.jp",
+    "hotmail.co.uk",
+    "hotmail.com",
+    "hotmail.de",
+    "hotmail.fr",
+    "hotmail.it",
+    "icloud.com",
+    "live.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "yahoo.co.id",
+    "yahoo.co.in",
+    "yahoo.co.jp",
+    "yahoo.co.nz",
+    "yahoo.co.uk",
+    "yahoo.com",
+    "yahoo.com.au",
+    "ymail.com",
+    "web.de",
+    "zoho.com",
+];
+
+///
+/// Rules on top of syntactic validity, deciding which addresses we are willing to send a
+/// confirmation email to
+///
+#[derive(Debug, Default)]
+pub struct EmailPolicy {
+    disposable_domains: HashSet<String>,
+    reject_role_accounts: bool,
+    suggest_corrections: bool,
+    fold_provider_aliases: bool,
+}
+
+impl EmailPolicy {
+    pub fn new(
+        disposable_domains: HashSet<String>,
+        reject_role_accounts: bool,
+        suggest_corrections: bool,
+        fold_provider_aliases: bool,
+    ) -> Self {
+        Self {
+            disposable_domains,
+            reject_role_accounts,
+            suggest_corrections,
+            fold_provider_aliases,
+        }
+    }
+
+    ///
+    /// The form of `email` used to recognise a repeat signup for the same mailbox
+    ///
+    pub fn normalise(&self, email: &SubscriberEmail) -> String {
+        email.normalised(self.fold_provider_aliases)
+    }
+
+    ///
+    /// Parse a blocklist with one domain per line, ignoring blank lines and `#` comments
+    ///
+    pub fn parse_domain_list(contents: &str) -> HashSet<String> {
+        contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap().trim().to_lowercase())
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    ///
+    /// Check the address against the policy, the error is shown to the subscriber as is. Likely
+    /// typos aren't errors, see [`EmailPolicy::suggest_correction`].
+    ///
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        let (local_part, domain) = email.as_ref().rsplit_once('@').unwrap();
+        let domain = domain.to_lowercase();
+
+        if self.is_disposable(&domain) {
+            return Err(format!(
+                "Addresses at {} are disposable, please use a permanent address.",
+                domain
+            ));
+        }
+
+        // `abuse+newsletter@` still lands in the abuse mailbox
+        let mailbox = local_part.split('+').next().unwrap().to_lowercase();
+        if self.reject_role_accounts && ROLE_ACCOUNTS.contains(&mailbox.as_str()) {
+            return Err(format!(
+                "{} is a role account, please use a personal address.",
+                email
+            ));
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// The address `email` was most likely meant to be, when its domain looks like a typo of a
+    /// popular one. Only a guess, so the subscriber has to be able to keep what they typed.
+    ///
+    pub fn suggest_correction(&self, email: &SubscriberEmail) -> Option<String> {
+        if !self.suggest_corrections {
+            return None;
+        }
+
+        let (local_part, domain) = email.as_ref().rsplit_once('@').unwrap();
+        suggest_domain(&domain.to_lowercase())
+            .map(|suggestion| format!("{}@{}", local_part, suggestion))
+    }
+
+    fn is_disposable(&self, domain: &str) -> bool {
+        // Match the domain and every parent, so `x.mailinator.com` is caught by `mailinator.com`
+        std::iter::successors(Some(domain), |d| {
+            d.split_once('.').map(|(_, parent)| parent)
+        })
+        .any(|d| self.disposable_domains.contains(d))
+    }
+}
+
+///
+/// The popular domain `domain` was most likely meant to be, if it isn't one already
+///
+fn suggest_domain(domain: &str) -> Option<&'static str> {
+    if POPULAR_DOMAINS.contains(&domain) {
+        return None;
+    }
+
+    // Allow one slip for short domains and two for longer ones, where a mistake is less unusual
+    let max_distance = if domain.len() >= 10 { 2 } else { 1 };
+
+    POPULAR_DOMAINS
+        .iter()
+        .map(|candidate| (candidate, edit_distance(domain, candidate)))
+        .filter(|(_, distance)| *distance <= max_distance)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(candidate, _)| *candidate)
+}
+
+///
+/// Edits needed to turn `a` into `b`, counting insertions, deletions, substitutions and swapping
+/// two neighbouring characters as one edit each
+///
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distances[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, suggest_domain, EmailPolicy};
+    use crate::domain::SubscriberEmail;
+    use claims::{assert_err, assert_ok};
+
+    fn policy() -> EmailPolicy {
+        EmailPolicy::new(
+            EmailPolicy::parse_domain_list("# comment\nmailinator.com\n\nYopmail.com # inline\n"),
+            true,
+            true,
+            false,
+        )
+    }
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn domain_lists_skip_comments_and_blank_lines() {
+        let domains =
+            EmailPolicy::parse_domain_list("# comment\nmailinator.com\n\nYopmail.com # inline\n");
+
+        assert_eq!(domains.len(), 2);
+        assert!(domains.contains("yopmail.com"));
+    }
+
+    #[test]
+    fn ordinary_addresses_are_accepted() {
+        assert_ok!(policy().check(&email("ursula_le_guin@gmail.com")));
+        assert_ok!(policy().check(&email("ursula@earthsea.org")));
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        assert_err!(policy().check(&email("ursula@mailinator.com")));
+        assert_err!(policy().check(&email("ursula@YOPMAIL.com")));
+        assert_err!(policy().check(&email("ursula@inbox.mailinator.com")));
+    }
+
+    #[test]
+    fn role_accounts_are_rejected_when_configured() {
+        assert_err!(policy().check(&email("postmaster@earthsea.org")));
+        assert_err!(policy().check(&email("No-Reply@earthsea.org")));
+        assert_err!(policy().check(&email("abuse+newsletter@earthsea.org")));
+
+        let lenient = EmailPolicy::new(Default::default(), false, false, false);
+        assert_ok!(lenient.check(&email("postmaster@earthsea.org")));
+    }
+
+    #[test]
+    fn typos_of_popular_domains_suggest_the_correction() {
+        assert_ok!(policy().check(&email("ursula@gmial.com")));
+        assert_eq!(
+            policy().suggest_correction(&email("ursula@GMIAL.com")),
+            Some("ursula@gmail.com".to_string())
+        );
+
+        let lenient = EmailPolicy::new(Default::default(), false, false, false);
+        assert_eq!(lenient.suggest_correction(&email("ursula@gmial.com")), None);
+    }
+
+    #[test]
+    fn regional_domains_of_popular_providers_are_not_typos() {
+        for domain in [
+            "yahoo.co.in",
+            "yahoo.co.jp",
+            "yahoo.co.id",
+            "yahoo.co.nz",
+            "hotmail.co.jp",
+        ] {
+            let address = email(&format!("ursula@{}", domain));
+            assert_eq!(policy().suggest_correction(&address), None, "{}", domain);
+        }
+    }
+
+    #[test]
+    fn popular_domains_close_to_each_other_are_not_typos() {
+        assert_eq!(suggest_domain("ymail.com"), None);
+        assert_eq!(suggest_domain("mail.com"), None);
+        assert_eq!(suggest_domain("email.com"), None);
+        assert_eq!(suggest_domain("hotmial.com"), Some("hotmail.com"));
+        assert_eq!(suggest_domain("outlok.com"), Some("outlook.com"));
+        assert_eq!(suggest_domain("earthsea.org"), None);
+    }
+
+    #[test]
+    fn swapped_characters_count_as_one_edit() {
+        assert_eq!(edit_distance("gmial.com", "gmail.com"), 1);
+        assert_eq!(edit_distance("gmail.com", "gmail.com"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -1,7 +1,15 @@
+mod data_export_token;
+mod email_policy;
 mod new_subscriber;
+mod signup_form_token;
 mod subscriber_email;
 mod subscriber_name;
+mod unsubscribe_token;
 
+pub use data_export_token::DataExportToken;
+pub use email_policy::EmailPolicy;
 pub use new_subscriber::NewSubscriber;
+pub use signup_form_token::{verify_proof_of_work, SignupFormToken};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use unsubscribe_token::UnsubscribeToken;
@@ -0,0 +1,144 @@
+use base64::Engine;
+use rand::Rng;
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+
+use crate::domain::signed_token::{decode_signed, encode_signed};
+
+///
+/// Embedded in the signup form when it is rendered. It records when the form was handed out, so
+/// submissions made faster than a person could fill the form in can be rejected, and doubles as
+/// the challenge for the optional proof of work.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignupFormToken {
+    /// Unix timestamp, in seconds
+    pub issued_at: i64,
+    nonce: [u8; 16],
+}
+
+impl SignupFormToken {
+    const PURPOSE: &'static str = "signup";
+    const PAYLOAD_LENGTH: usize = 24;
+
+    pub fn new(issued_at: i64) -> Self {
+        Self {
+            issued_at,
+            nonce: rand::thread_rng().gen(),
+        }
+    }
+
+    ///
+    /// Identifies this particular form, so a submission can only be made with it once
+    ///
+    pub fn id(&self) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(self.nonce)
+    }
+
+    pub fn encode(&self, secret: &Secret<String>) -> String {
+        let mut payload = [0; Self::PAYLOAD_LENGTH];
+        payload[..8].copy_from_slice(&self.issued_at.to_be_bytes());
+        payload[8..].copy_from_slice(&self.nonce);
+
+        encode_signed(Self::PURPOSE, &payload, secret)
+    }
+
+    ///
+    /// Decode the token, rejecting anything which was not signed with `secret`
+    ///
+    pub fn decode(token: &str, secret: &Secret<String>) -> Result<Self, String> {
+        let payload = decode_signed(Self::PURPOSE, token, Self::PAYLOAD_LENGTH, secret)?;
+
+        Ok(Self {
+            issued_at: i64::from_be_bytes(payload[..8].try_into().unwrap()),
+            nonce: payload[8..].try_into().unwrap(),
+        })
+    }
+}
+
+///
+/// Check `solution` solves the proof of work for `token`: the SHA-256 of `{token}:{solution}` must
+/// start with at least `difficulty` zero bits. This mirrors the script on the signup form.
+///
+pub fn verify_proof_of_work(token: &str, solution: &str, difficulty: u8) -> bool {
+    let digest = Sha256::digest(format!("{}:{}", token, solution));
+
+    leading_zero_bits(&digest) >= u32::from(difficulty)
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in bytes {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{leading_zero_bits, verify_proof_of_work, SignupFormToken};
+    use claims::assert_err;
+    use secrecy::Secret;
+
+    fn secret(s: &str) -> Secret<String> {
+        Secret::new(s.to_string())
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let token = SignupFormToken::new(1_700_000_000);
+        let encoded = token.encode(&secret("secret"));
+
+        assert_eq!(
+            SignupFormToken::decode(&encoded, &secret("secret")).unwrap(),
+            token
+        );
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let encoded = SignupFormToken::new(1_700_000_000).encode(&secret("other"));
+
+        assert_err!(SignupFormToken::decode(&encoded, &secret("secret")));
+    }
+
+    #[test]
+    fn tokens_with_a_backdated_timestamp_are_rejected() {
+        let encoded = SignupFormToken::new(1_700_000_000).encode(&secret("secret"));
+        let mut bytes =
+            base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, &encoded)
+                .unwrap();
+        bytes[7] ^= 0xff;
+        let tampered =
+            base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, bytes);
+
+        assert_err!(SignupFormToken::decode(&tampered, &secret("secret")));
+        assert_err!(SignupFormToken::decode("not a token!", &secret("secret")));
+    }
+
+    #[test]
+    fn leading_zero_bits_are_counted_across_bytes() {
+        assert_eq!(leading_zero_bits(&[0xff]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x10]), 11);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn a_solved_proof_of_work_is_accepted() {
+        let solution = (0..)
+            .map(|n: u32| n.to_string())
+            .find(|n| verify_proof_of_work("token", n, 8))
+            .unwrap();
+
+        assert!(verify_proof_of_work("token", &solution, 8));
+        assert!(!verify_proof_of_work("another-token", &solution, 16));
+    }
+
+    #[test]
+    fn any_solution_is_accepted_when_the_difficulty_is_zero() {
+        assert!(verify_proof_of_work("token", "", 0));
+    }
+}
@@ -1,5 +1,11 @@
 use validator::validate_email;
 
+/// Providers which ignore dots in the local part and deliver `user+tag@` to `user@`
+const ALIAS_FOLDING_DOMAINS: [&str; 2] = ["gmail.com", "googlemail.com"];
+
+/// Longest local part allowed by RFC 5321, in bytes
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+
 #[derive(Debug)]
 pub struct SubscriberEmail(String);
 
@@ -11,17 +17,66 @@ impl AsRef<str> for SubscriberEmail {
 
 impl SubscriberEmail {
     ///
-    /// Parse sting to check if its a valid email
+    /// Parse sting to check if its a valid email.
+    /// The domain is lowercased and converted to its ASCII (punycode) form, the local part is kept
+    /// as given, and may contain non-ASCII characters (RFC 6531).
     ///
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if !validate_email(&s) {
-            return Err(format!("{} is not a valid a subscriber email.", s));
+        let invalid = || format!("{} is not a valid a subscriber email.", s);
+
+        let (local_part, domain) = s.rsplit_once('@').ok_or_else(invalid)?;
+        let domain = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+
+        let is_valid = if local_part.is_ascii() {
+            validate_email(format!("{}@{}", local_part, domain))
+        } else {
+            is_valid_international_local_part(local_part)
+                && validate_email(format!("postmaster@{}", domain))
+        };
+        if !is_valid {
+            return Err(invalid());
+        }
+
+        Ok(Self(format!("{}@{}", local_part, domain)))
+    }
+
+    ///
+    /// The form used to decide whether two addresses reach the same mailbox. Local parts are
+    /// compared case-insensitively, and with `fold_aliases` the dots and `+tag`s ignored by some
+    /// providers are dropped.
+    ///
+    pub fn normalised(&self, fold_aliases: bool) -> String {
+        let (local_part, domain) = self.0.rsplit_once('@').unwrap();
+        let local_part = local_part.to_lowercase();
+
+        if fold_aliases && ALIAS_FOLDING_DOMAINS.contains(&domain) {
+            let mailbox = local_part.split('+').next().unwrap().replace('.', "");
+            return format!("{}@gmail.com", mailbox);
         }
 
-        Ok(Self(s))
+        format!("{}@{}", local_part, domain)
     }
 }
 
+///
+/// `validator` only accepts ASCII local parts, SMTPUTF8 allows any printable character on top
+///
+fn is_valid_international_local_part(local_part: &str) -> bool {
+    const SPECIALS: &str = "!#$%&'*+/=?^_`{|}~-";
+
+    !local_part.is_empty()
+        && local_part.len() <= MAX_LOCAL_PART_LENGTH
+        && !local_part.starts_with('.')
+        && !local_part.ends_with('.')
+        && !local_part.contains("..")
+        && local_part.chars().all(|c| {
+            c.is_ascii_alphanumeric()
+                || c == '.'
+                || SPECIALS.contains(c)
+                || (!c.is_ascii() && !c.is_control() && !c.is_whitespace())
+        })
+}
+
 impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.0.fmt(f)
@@ -31,7 +86,7 @@ impl std::fmt::Display for SubscriberEmail {
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
-    use claims::assert_err;
+    use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
     use quickcheck::Arbitrary;
@@ -68,4 +123,54 @@ mod tests {
         let email = "@test.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn domains_are_lowercased_and_converted_to_punycode() {
+        let email = SubscriberEmail::parse("Ursula@Bücher.Example".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula@xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn international_local_parts_are_valid() {
+        let email = SubscriberEmail::parse("用户@例子.广告".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "用户@xn--fsqu00a.xn--4rr70v");
+
+        assert_ok!(SubscriberEmail::parse(
+            "josé.garcía@example.com".to_string()
+        ));
+    }
+
+    #[test]
+    fn international_local_parts_with_spaces_or_stray_dots_are_invalid() {
+        assert_err!(SubscriberEmail::parse("jos é@example.com".to_string()));
+        assert_err!(SubscriberEmail::parse(".josé@example.com".to_string()));
+        assert_err!(SubscriberEmail::parse("jo..sé@example.com".to_string()));
+    }
+
+    #[test]
+    fn normalised_addresses_ignore_case() {
+        let a = SubscriberEmail::parse("Alice@Example.com".to_string()).unwrap();
+        let b = SubscriberEmail::parse("alice@example.COM".to_string()).unwrap();
+
+        assert_eq!(a.normalised(false), b.normalised(false));
+        assert_eq!(a.normalised(false), "alice@example.com");
+    }
+
+    #[test]
+    fn gmail_aliases_are_only_folded_when_asked_to() {
+        let email =
+            SubscriberEmail::parse("Ursula.Le.Guin+news@googlemail.com".to_string()).unwrap();
+
+        assert_eq!(email.normalised(true), "ursulaleguin@gmail.com");
+        assert_eq!(
+            email.normalised(false),
+            "ursula.le.guin+news@googlemail.com"
+        );
+    }
+
+    #[test]
+    fn plus_tags_are_kept_for_other_providers() {
+        let email = SubscriberEmail::parse("ursula+news@example.com".to_string()).unwrap();
+        assert_eq!(email.normalised(true), "ursula+news@example.com");
+    }
 }
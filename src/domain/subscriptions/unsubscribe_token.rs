@@ -0,0 +1,86 @@
+use secrecy::Secret;
+use uuid::Uuid;
+
+use crate::domain::signed_token::{decode_signed, encode_signed};
+
+///
+/// Identifies the subscriber behind the unsubscribe link in every issue. Signed with the
+/// application HMAC secret rather than reusing the confirmation token, so the link can't be used to
+/// confirm, and it never expires since readers unsubscribe from old issues too.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsubscribeToken {
+    pub subscriber_id: Uuid,
+}
+
+impl UnsubscribeToken {
+    const PURPOSE: &'static str = "unsubscribe";
+    const PAYLOAD_LENGTH: usize = 16;
+
+    pub fn new(subscriber_id: Uuid) -> Self {
+        Self { subscriber_id }
+    }
+
+    pub fn encode(&self, secret: &Secret<String>) -> String {
+        encode_signed(Self::PURPOSE, self.subscriber_id.as_bytes(), secret)
+    }
+
+    ///
+    /// Decode the token, rejecting anything which was not signed with `secret`
+    ///
+    pub fn decode(token: &str, secret: &Secret<String>) -> Result<Self, String> {
+        let payload = decode_signed(Self::PURPOSE, token, Self::PAYLOAD_LENGTH, secret)?;
+
+        Ok(Self::new(
+            Uuid::from_slice(&payload).map_err(|e| e.to_string())?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use crate::domain::DataExportToken;
+    use claims::assert_err;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret(s: &str) -> Secret<String> {
+        Secret::new(s.to_string())
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let token = UnsubscribeToken::new(Uuid::new_v4());
+        let encoded = token.encode(&secret("secret"));
+
+        assert_eq!(
+            UnsubscribeToken::decode(&encoded, &secret("secret")).unwrap(),
+            token
+        );
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let encoded = UnsubscribeToken::new(Uuid::new_v4()).encode(&secret("other"));
+        assert_err!(UnsubscribeToken::decode(&encoded, &secret("secret")));
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let encoded = UnsubscribeToken::new(Uuid::new_v4()).encode(&secret("secret"));
+        let mut tampered = encoded.into_bytes();
+        tampered[0] = if tampered[0] == b'A' { b'B' } else { b'A' };
+
+        assert_err!(UnsubscribeToken::decode(
+            std::str::from_utf8(&tampered).unwrap(),
+            &secret("secret")
+        ));
+    }
+
+    #[test]
+    fn data_export_tokens_are_not_unsubscribe_tokens() {
+        let encoded = DataExportToken::new(Uuid::new_v4(), i64::MAX).encode(&secret("secret"));
+        assert_err!(UnsubscribeToken::decode(&encoded, &secret("secret")));
+    }
+}
@@ -2,6 +2,8 @@ use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
 
 use crate::domain::SubscriberEmail;
+use crate::metrics;
+use crate::telemetry::trace_context_headers;
 
 pub struct EmailClient {
     base_url: reqwest::Url,
@@ -18,16 +20,54 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader<'a>],
+}
+
+///
+/// An extra header on an outgoing email, e.g. `List-Unsubscribe`
+///
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
 }
 
 impl EmailClient {
+    ///
+    /// Send an email through Postmark, returning the MessageID assigned by the provider (when it
+    /// sends one back) so that later delivery events can be matched to this email
+    ///
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<Option<String>, reqwest::Error> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    ///
+    /// Same as [`EmailClient::send_email`], with extra headers set on the email
+    ///
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<Option<String>, reqwest::Error> {
         let url = self.base_url.join("email").expect("Invalid base url");
 
         let request_body = SendEmailRequest {
@@ -36,15 +76,54 @@ impl EmailClient {
             subject,
             html_body: html_content,
             text_body: text_content,
+            headers,
         };
 
+        let started_at = std::time::Instant::now();
+        let response = self.send_request(url, &request_body).await;
+        metrics::record_email_send(response.is_ok(), started_at);
+
+        // The email has been accepted at this point, a malformed body must not turn it into a failure
+        let message_id = response?
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .and_then(|r| r.message_id);
+
+        Ok(message_id)
+    }
+
+    async fn send_request(
+        &self,
+        url: reqwest::Url,
+        request_body: &SendEmailRequest<'_>,
+    ) -> Result<reqwest::Response, reqwest::Error> {
         self.http_client
             .post(url)
+            .headers(trace_context_headers())
+            .header(
+                "X-Postmark-Server-Token",
+                self.authroisation_token.expose_secret(),
+            )
+            .json(request_body)
+            .send()
+            .await?
+            .error_for_status()
+    }
+
+    ///
+    /// Check the provider is reachable and accepts our token, without sending anything
+    ///
+    pub async fn check_connection(&self) -> Result<(), reqwest::Error> {
+        let url = self.base_url.join("server").expect("Invalid base url");
+
+        self.http_client
+            .get(url)
+            .headers(trace_context_headers())
             .header(
                 "X-Postmark-Server-Token",
                 self.authroisation_token.expose_secret(),
             )
-            .json(&request_body)
             .send()
             .await?
             .error_for_status()?;
@@ -76,14 +155,19 @@ impl EmailClient {
 mod tests {
     use crate::domain::SubscriberEmail;
     use crate::email_client::EmailClient;
+    use crate::telemetry::get_subscriber;
 
     use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
     use reqwest::Url;
     use secrecy::Secret;
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use tracing::Instrument;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use wiremock::matchers::{any, header, header_exists, header_regex, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     struct SendEmailBodyMatcher;
@@ -100,7 +184,7 @@ mod tests {
                     && body.get("TextBody").is_some();
             }
 
-            return false;
+            false
         }
     }
 
@@ -176,6 +260,37 @@ mod tests {
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_message_id_assigned_by_the_server() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        let mock_url = Url::parse(mock_server.uri().as_str()).expect("Invalid mock base url");
+
+        let email_client = email_client(mock_url);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+              "ErrorCode": 0,
+              "Message": "OK",
+              "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(
+            outcome.unwrap().as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         // Arrange
@@ -225,4 +340,37 @@ mod tests {
         // Assert
         assert_err!(outcome);
     }
+
+    #[tokio::test]
+    async fn send_email_propagates_the_trace_context_of_the_current_span() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let mock_url = Url::parse(mock_server.uri().as_str()).expect("Invalid mock base url");
+
+        let email_client = email_client(mock_url);
+
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let _guard = tracing::subscriber::set_default(get_subscriber(
+            "test".into(),
+            "info".into(),
+            std::io::sink,
+        ));
+        let span = tracing::info_span!("Publish newsletter");
+        let trace_id = span.context().span().span_context().trace_id();
+
+        Mock::given(header_regex("traceparent", &format!("^00-{}-", trace_id)))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .instrument(span)
+            .await;
+
+        // Assert
+        // Mock expectation are checked on drop
+    }
 }
@@ -0,0 +1,141 @@
+use std::net::IpAddr;
+
+use actix_web::HttpRequest;
+use ipnet::IpNet;
+
+///
+/// The proxies in front of the app, `application.trusted_proxies` in the configuration
+///
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+impl TrustedProxies {
+    ///
+    /// The address of the client behind the request. `X-Forwarded-For` is only read when the
+    /// connection comes from a trusted proxy, and is walked from the right, skipping further trusted
+    /// proxies, as anything to the left of the last untrusted hop could have been made up by the
+    /// client.
+    ///
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+
+        let forwarded: Vec<IpAddr> = req
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|hop| hop.trim().parse::<IpAddr>())
+            .collect::<Result<_, _>>()
+            // A garbled header can't be trusted at all
+            .unwrap_or_default();
+
+        let mut client = peer;
+        for hop in forwarded.into_iter().rev() {
+            client = hop;
+            if !self.is_trusted(hop) {
+                break;
+            }
+        }
+
+        Some(client)
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            v4 => v4,
+        };
+        self.0.iter().any(|proxy| proxy.contains(&ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrustedProxies;
+    use actix_web::test::TestRequest;
+    use std::net::{IpAddr, SocketAddr};
+
+    fn proxies(networks: &[&str]) -> TrustedProxies {
+        TrustedProxies(networks.iter().map(|n| n.parse().unwrap()).collect())
+    }
+
+    fn client_ip(proxies: &TrustedProxies, peer: &str, forwarded: Option<&str>) -> IpAddr {
+        let mut req = TestRequest::default().peer_addr(SocketAddr::new(peer.parse().unwrap(), 443));
+        if let Some(forwarded) = forwarded {
+            req = req.insert_header(("X-Forwarded-For", forwarded));
+        }
+
+        proxies.client_ip(&req.to_http_request()).unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_headers_from_untrusted_peers_are_ignored() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+
+        assert_eq!(
+            client_ip(&proxies, "203.0.113.9", Some("198.51.100.1")),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            client_ip(&TrustedProxies::default(), "10.0.0.1", Some("198.51.100.1")),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn the_rightmost_untrusted_hop_is_the_client() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+
+        assert_eq!(
+            client_ip(&proxies, "10.0.0.1", Some("198.51.100.1")),
+            ip("198.51.100.1")
+        );
+        // The client made up the first entry, our proxies appended the rest
+        assert_eq!(
+            client_ip(
+                &proxies,
+                "10.0.0.1",
+                Some("1.2.3.4, 198.51.100.1, 10.0.0.2")
+            ),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn the_peer_is_used_without_a_usable_forwarded_header() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+
+        assert_eq!(client_ip(&proxies, "10.0.0.1", None), ip("10.0.0.1"));
+        assert_eq!(
+            client_ip(&proxies, "10.0.0.1", Some("not-an-ip, 198.51.100.1")),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn the_leftmost_hop_is_used_when_every_hop_is_trusted() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+
+        assert_eq!(
+            client_ip(&proxies, "10.0.0.1", Some("10.0.0.3, 10.0.0.2")),
+            ip("10.0.0.3")
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_peers_match_ipv4_networks() {
+        let proxies = proxies(&["127.0.0.0/8"]);
+
+        assert_eq!(
+            client_ip(&proxies, "::ffff:127.0.0.1", Some("198.51.100.1")),
+            ip("198.51.100.1")
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use actix_web::http::header::{self, Header};
+use actix_web::{mime, HttpRequest};
+use askama::Template;
+
+///
+/// The representations our public endpoints can answer with
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Html,
+    Json,
+}
+
+impl ResponseFormat {
+    ///
+    /// Pick whichever of HTML and JSON the `Accept` header ranks highest, or `default` when it
+    /// names neither, e.g. `*/*` or no header at all
+    ///
+    pub fn negotiate(req: &HttpRequest, default: ResponseFormat) -> Self {
+        let Ok(accept) = header::Accept::parse(req) else {
+            return default;
+        };
+
+        accept
+            .ranked()
+            .into_iter()
+            .find_map(|mime| match (mime.type_(), mime.subtype()) {
+                (mime::APPLICATION, mime::JSON) => Some(ResponseFormat::Json),
+                (mime::TEXT, mime::HTML) => Some(ResponseFormat::Html),
+                _ => None,
+            })
+            .unwrap_or(default)
+    }
+
+    ///
+    /// JSON for requests which sent JSON, HTML otherwise
+    ///
+    pub fn of_request_body(req: &HttpRequest) -> Self {
+        let is_json = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+
+        if is_json {
+            ResponseFormat::Json
+        } else {
+            ResponseFormat::Html
+        }
+    }
+}
+
+///
+/// JSON body for errors from the public endpoints
+///
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ErrorBody<'a> {
+    /// Stable, machine readable code, e.g. `invalid_fields`
+    pub error: &'a str,
+    /// Suitable for showing to the subscriber as is
+    pub message: String,
+    /// Problems with individual fields, keyed by field name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<HashMap<String, String>>)]
+    pub fields: Option<&'a BTreeMap<&'static str, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_seconds: Option<u64>,
+}
+
+#[derive(Template)]
+#[template(path = "message.html")]
+struct MessagePage<'a> {
+    title: &'a str,
+    message: &'a str,
+}
+
+///
+/// A page for the short messages shown after submitting a public form
+///
+pub fn html_message_page(title: &str, message: &str) -> String {
+    MessagePage { title, message }
+        .render()
+        .expect("Rendering plain strings can't fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{html_message_page, ResponseFormat};
+    use actix_web::test::TestRequest;
+
+    fn negotiate(accept: &str) -> ResponseFormat {
+        let req = TestRequest::default()
+            .insert_header(("Accept", accept))
+            .to_http_request();
+
+        ResponseFormat::negotiate(&req, ResponseFormat::Html)
+    }
+
+    #[test]
+    fn the_highest_ranked_format_wins() {
+        assert_eq!(negotiate("application/json"), ResponseFormat::Json);
+        assert_eq!(
+            negotiate("text/html;q=0.5, application/json"),
+            ResponseFormat::Json
+        );
+        assert_eq!(
+            negotiate("text/html, application/json;q=0.9"),
+            ResponseFormat::Html
+        );
+    }
+
+    #[test]
+    fn the_default_is_used_when_neither_format_is_named() {
+        assert_eq!(negotiate("*/*"), ResponseFormat::Html);
+        assert_eq!(negotiate("image/png"), ResponseFormat::Html);
+
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(
+            ResponseFormat::negotiate(&req, ResponseFormat::Json),
+            ResponseFormat::Json
+        );
+    }
+
+    #[test]
+    fn the_request_body_format_follows_its_content_type() {
+        let req = TestRequest::default()
+            .insert_header(("Content-Type", "application/json; charset=utf-8"))
+            .to_http_request();
+        assert_eq!(ResponseFormat::of_request_body(&req), ResponseFormat::Json);
+
+        let req = TestRequest::default()
+            .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+            .to_http_request();
+        assert_eq!(ResponseFormat::of_request_body(&req), ResponseFormat::Html);
+    }
+
+    #[test]
+    fn message_pages_escape_the_title_and_message() {
+        let html = html_message_page("<b>Title</b>", "Tom & <Jerry>");
+
+        assert!(html.contains("<title>&lt;b&gt;Title&lt;/b&gt;</title>"));
+        assert!(html.contains("<p>Tom &amp; &lt;Jerry&gt;</p>"));
+    }
+}
@@ -1,7 +1,11 @@
+mod client_ip;
+mod content_negotiation;
 mod error_helper;
 mod error_utils;
 mod utils;
 
+pub use client_ip::*;
+pub use content_negotiation::*;
 pub use error_helper::*;
 pub use error_utils::*;
 pub use utils::*;
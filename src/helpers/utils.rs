@@ -1,8 +1,55 @@
-use actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
 use reqwest::header::LOCATION;
 
+use super::e500;
+
 pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
 }
+
+///
+/// A 200 with the rendered page. Templates live in `templates/` and are compiled into the binary,
+/// values interpolated into them are escaped.
+///
+pub fn render(page: &impl askama::Template) -> Result<HttpResponse, actix_web::Error> {
+    let html = page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+///
+/// Compare a submitted secret with the expected one, in time independent of where they differ
+///
+pub fn constant_time_eq(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+///
+/// The messages shown by `partials/flash_messages.html`
+///
+pub fn flash_messages(incoming: &IncomingFlashMessages) -> Vec<String> {
+    incoming.iter().map(|m| m.content().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::constant_time_eq;
+
+    #[test]
+    fn only_identical_secrets_are_equal() {
+        assert!(constant_time_eq("abc123", "abc123"));
+        assert!(!constant_time_eq("abc123", "abc124"));
+        assert!(!constant_time_eq("abc123", "abc12"));
+        assert!(!constant_time_eq("abc123", ""));
+    }
+}
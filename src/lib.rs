@@ -1,9 +1,18 @@
+pub mod audit_log;
 pub mod authentication;
+pub mod bootstrap;
 pub mod configuration;
+pub mod csrf;
 pub mod domain;
 pub mod email_client;
 pub mod helpers;
+pub mod metrics;
+pub mod personal_data;
+pub mod rate_limit;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
+pub mod webhook_delivery_worker;
+pub mod webhook_events;
@@ -1,17 +1,43 @@
+use anyhow::Context;
 use zero_to_production::{
     configuration::get_configuration,
     startup::Application,
-    telemetry::{get_subscriber, init_subscriber},
+    telemetry::{get_subscriber_with_telemetry, init_subscriber, shutdown_telemetry},
 };
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let subscriber = get_subscriber("zero-to-prod".into(), "info".into(), std::io::stdout);
+    let config = get_configuration().context("Failed to read configuration")?;
+
+    // Print the effective configuration, with secrets redacted
+    if std::env::args().any(|arg| arg == "--dump-config") {
+        println!("{:#?}", config);
+        return Ok(());
+    }
+
+    // Lets deploys catch a broken configuration before swapping the running server out
+    if std::env::args().any(|arg| arg == "--check-config") {
+        if let Err(e) = config.validate() {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("Configuration is valid");
+        return Ok(());
+    }
+
+    let subscriber = get_subscriber_with_telemetry(
+        "zero-to-prod".into(),
+        "info".into(),
+        std::io::stdout,
+        &config.telemetry,
+    )?;
     init_subscriber(subscriber);
 
-    let config = get_configuration().expect("Failed to read configuration");
     let application = Application::build(config).await?;
-    application.run_until_stopped().await?;
+    let outcome = application.run_until_stopped().await;
+
+    shutdown_telemetry();
+    outcome?;
 
     Ok(())
 }
@@ -0,0 +1,244 @@
+use std::future::{ready, Ready};
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use futures_util::future::LocalBoxFuture;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, Histogram, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use sqlx::PgPool;
+
+use crate::helpers::e500;
+use crate::webhook_delivery_worker::pending_webhook_deliveries;
+
+// Metrics live in the default registry, so they are shared by every server in the process
+static HTTP_REQUESTS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "http_requests_total",
+        "Number of HTTP requests handled, by route",
+        &["method", "route", "status"]
+    )
+    .expect("Failed to register http_requests_total")
+});
+
+static HTTP_REQUEST_DURATION_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time taken to handle HTTP requests, by route",
+        &["method", "route"]
+    )
+    .expect("Failed to register http_request_duration_seconds")
+});
+
+static EMAILS_SENT_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "emails_sent_total",
+        "Number of emails handed to the email provider, by outcome",
+        &["outcome"]
+    )
+    .expect("Failed to register emails_sent_total")
+});
+
+static EMAIL_SEND_DURATION_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "email_send_duration_seconds",
+        "Time taken by the email provider to accept or reject an email"
+    )
+    .expect("Failed to register email_send_duration_seconds")
+});
+
+static SIGNUPS_REJECTED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "signups_rejected_total",
+        "Number of signups turned away by the bot and abuse protections, by reason",
+        &["reason"]
+    )
+    .expect("Failed to register signups_rejected_total")
+});
+
+static WEBHOOK_DELIVERY_ATTEMPTS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "webhook_delivery_attempts_total",
+        "Number of outbound webhook requests, by the delivery's status afterwards",
+        &["status"]
+    )
+    .expect("Failed to register webhook_delivery_attempts_total")
+});
+
+static WEBHOOK_DELIVERIES_PENDING: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "webhook_deliveries_pending",
+        "Outbound webhook deliveries waiting to be sent or retried"
+    )
+    .expect("Failed to register webhook_deliveries_pending")
+});
+
+static DB_POOL_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "db_pool_connections",
+        "Connections held by the Postgres pool, by state",
+        &["state"]
+    )
+    .expect("Failed to register db_pool_connections")
+});
+
+static SUBSCRIBERS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "subscribers",
+        "Number of subscribers, by subscription status",
+        &["status"]
+    )
+    .expect("Failed to register subscribers")
+});
+
+///
+/// Record the outcome of a single email send
+///
+pub fn record_email_send(succeeded: bool, started_at: Instant) {
+    let outcome = if succeeded { "success" } else { "failure" };
+
+    EMAILS_SENT_TOTAL.with_label_values(&[outcome]).inc();
+    EMAIL_SEND_DURATION_SECONDS.observe(started_at.elapsed().as_secs_f64());
+}
+
+///
+/// Record a signup turned away by the bot and abuse protections
+///
+pub fn record_signup_rejected(reason: &str) {
+    SIGNUPS_REJECTED_TOTAL.with_label_values(&[reason]).inc();
+}
+
+///
+/// Record an outbound webhook request, by whether the delivery is now `delivered`, `pending` a
+/// retry or `failed` for good
+///
+pub fn record_webhook_attempt(status: &str) {
+    WEBHOOK_DELIVERY_ATTEMPTS_TOTAL
+        .with_label_values(&[status])
+        .inc();
+}
+
+///
+/// Prometheus scrape endpoint. Gauges backed by the database are refreshed on every scrape,
+/// everything else is recorded as it happens.
+///
+pub async fn metrics(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let in_use = pool.size() as usize - pool.num_idle();
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(pool.num_idle() as i64);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set(in_use as i64);
+
+    for (status, count) in get_subscriber_counts(&pool).await.map_err(e500)? {
+        SUBSCRIBERS.with_label_values(&[&status]).set(count);
+    }
+    WEBHOOK_DELIVERIES_PENDING.set(pending_webhook_deliveries(&pool).await.map_err(e500)?);
+
+    // Make sure every family is exposed, even before anything has been recorded
+    Lazy::force(&HTTP_REQUESTS_TOTAL);
+    Lazy::force(&HTTP_REQUEST_DURATION_SECONDS);
+    Lazy::force(&EMAILS_SENT_TOTAL);
+    Lazy::force(&EMAIL_SEND_DURATION_SECONDS);
+    Lazy::force(&SIGNUPS_REJECTED_TOTAL);
+    Lazy::force(&WEBHOOK_DELIVERY_ATTEMPTS_TOTAL);
+
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    encoder
+        .encode(&prometheus::gather(), &mut buffer)
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(encoder.format_type().parse().unwrap()))
+        .body(buffer))
+}
+
+#[tracing::instrument(name = "Get subscriber counts", skip(pool))]
+async fn get_subscriber_counts(pool: &PgPool) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+      SELECT status, COUNT(*) AS "count!"
+      FROM subscriptions
+      GROUP BY status
+    "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| (r.status, r.count)).collect())
+}
+
+///
+/// Middleware recording the count and latency of every request, labelled by the matched route
+/// pattern (e.g. `/admin/issues/{issue_id}`) rather than the raw path to keep cardinality bounded
+///
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware { service }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let started_at = Instant::now();
+        let method = request.method().to_string();
+        let route = request
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+
+        let response = self.service.call(request);
+
+        Box::pin(async move {
+            let response = response.await;
+            let status = match &response {
+                Ok(response) => response.status(),
+                Err(error) => error.as_response_error().status_code(),
+            };
+
+            HTTP_REQUESTS_TOTAL
+                .with_label_values(&[&method, &route, status.as_str()])
+                .inc();
+            HTTP_REQUEST_DURATION_SECONDS
+                .with_label_values(&[&method, &route])
+                .observe(started_at.elapsed().as_secs_f64());
+
+            response
+        })
+    }
+}
@@ -0,0 +1,348 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::DataExportToken;
+
+///
+/// How long the self-service export link in each issue keeps working
+///
+pub const DATA_EXPORT_LINK_VALIDITY: chrono::Duration = chrono::Duration::days(30);
+
+///
+/// Everything held on one subscriber, as handed over for a subject access request
+///
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscriberDataExport {
+    pub exported_at: DateTime<Utc>,
+    pub subscriber: ExportedSubscriber,
+    /// Tokens in the confirmation links sent to the subscriber
+    pub subscription_tokens: Vec<String>,
+    /// What they agreed to each time they signed up through the form
+    pub consents: Vec<ExportedConsent>,
+    pub deliveries: Vec<ExportedDelivery>,
+    pub opens: Vec<ExportedOpen>,
+    pub clicks: Vec<ExportedClick>,
+    /// Lifecycle events announced to webhook endpoints, once per event
+    pub webhook_events: Vec<ExportedWebhookEvent>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ExportedSubscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub normalised_email: Option<String>,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    #[schema(value_type = Object)]
+    pub attributes: serde_json::Value,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ExportedConsent {
+    pub consent_text: String,
+    pub consent_version: String,
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub given_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ExportedDelivery {
+    pub issue_id: Uuid,
+    pub issue_title: String,
+    /// The address the issue was sent to
+    pub email: String,
+    pub status: String,
+    pub sent_at: DateTime<Utc>,
+    pub bounced_at: Option<DateTime<Utc>>,
+    pub complained_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ExportedOpen {
+    pub issue_id: Uuid,
+    pub first_opened_at: Option<DateTime<Utc>>,
+    pub last_opened_at: Option<DateTime<Utc>>,
+    pub open_count: i32,
+    pub machine_open_count: i32,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ExportedClick {
+    pub issue_id: Uuid,
+    pub url: String,
+    pub clicked_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ExportedWebhookEvent {
+    pub event_id: Uuid,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(pool))]
+///
+/// Gather everything held on a subscriber, `None` if there is no such subscriber
+///
+pub async fn export_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDataExport>, anyhow::Error> {
+    // One snapshot, so the sections agree with each other
+    let mut transaction = pool.begin().await?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+        .execute(&mut transaction)
+        .await?;
+
+    let Some(subscriber) = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+      SELECT id, email, normalised_email, name, status, subscribed_at, attributes
+      FROM subscriptions
+      WHERE id = $1
+    "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to fetch the subscriber")?
+    else {
+        return Ok(None);
+    };
+
+    let subscription_tokens = sqlx::query!(
+        "SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch subscription tokens")?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+
+    let consents = sqlx::query_as!(
+        ExportedConsent,
+        r#"
+      SELECT consent_text, consent_version, source, ip_address, user_agent, given_at, confirmed_at
+      FROM subscription_consents
+      WHERE subscriber_id = $1
+      ORDER BY given_at
+    "#,
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch consent records")?;
+
+    let deliveries = sqlx::query_as!(
+        ExportedDelivery,
+        r#"
+      SELECT
+        l.newsletter_issue_id AS issue_id,
+        i.title AS issue_title,
+        l.subscriber_email AS email,
+        l.status,
+        l.sent_at,
+        l.bounced_at,
+        l.complained_at
+      FROM issue_delivery_log l
+      JOIN newsletter_issues i ON i.id = l.newsletter_issue_id
+      WHERE l.subscriber_id = $1
+      ORDER BY l.sent_at
+    "#,
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch the delivery log")?;
+
+    let opens = sqlx::query_as!(
+        ExportedOpen,
+        r#"
+      SELECT
+        newsletter_issue_id AS issue_id,
+        first_opened_at,
+        last_opened_at,
+        open_count,
+        machine_open_count
+      FROM issue_opens
+      WHERE subscriber_id = $1
+      ORDER BY newsletter_issue_id
+    "#,
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch opens")?;
+
+    let clicks = sqlx::query_as!(
+        ExportedClick,
+        r#"
+      SELECT c.newsletter_issue_id AS issue_id, l.url, c.clicked_at
+      FROM issue_clicks c
+      JOIN issue_links l USING (newsletter_issue_id, link_id)
+      WHERE c.subscriber_id = $1
+      ORDER BY c.clicked_at
+    "#,
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch clicks")?;
+
+    let webhook_events = sqlx::query_as!(
+        ExportedWebhookEvent,
+        r#"
+      SELECT event_id, event_type, MIN(created_at) AS "occurred_at!"
+      FROM webhook_deliveries
+      WHERE payload #>> '{data,subscriber,id}' = $1
+      GROUP BY event_id, event_type
+      ORDER BY 3
+    "#,
+        subscriber_id.to_string()
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch webhook events")?;
+
+    transaction.commit().await?;
+
+    Ok(Some(SubscriberDataExport {
+        exported_at: Utc::now(),
+        subscriber,
+        subscription_tokens,
+        consents,
+        deliveries,
+        opens,
+        clicks,
+        webhook_events,
+    }))
+}
+
+#[tracing::instrument(name = "Erase subscriber", skip(transaction))]
+///
+/// Delete a subscriber and every row mentioning them, leaving only the hash of their address in
+/// `suppressed_emails`. Returns `false` if there is no such subscriber.
+///
+pub async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    // Addresses from before normalisation was introduced may not have a normalised form
+    let Some(row) = sqlx::query!(
+        r#"
+      SELECT COALESCE(normalised_email, lower(email)) AS "normalised_email!"
+      FROM subscriptions
+      WHERE id = $1
+      FOR UPDATE
+    "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to fetch the subscriber")?
+    else {
+        return Ok(false);
+    };
+
+    sqlx::query!(
+        r#"
+      INSERT INTO suppressed_emails (email_hash, suppressed_at)
+      VALUES ($1, now())
+      ON CONFLICT (email_hash) DO NOTHING
+    "#,
+        suppression_hash(&row.normalised_email)
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to suppress the address")?;
+
+    sqlx::query!(
+        "DELETE FROM webhook_deliveries WHERE payload #>> '{data,subscriber,id}' = $1",
+        subscriber_id.to_string()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete webhook deliveries")?;
+    // Tokens predate the cascading foreign keys, consents, the delivery log, opens and clicks go
+    // with the subscriber
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete subscription tokens")?;
+    sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the subscriber")?;
+
+    Ok(true)
+}
+
+///
+/// Whether the normalised address belongs to an erased subscriber
+///
+pub async fn is_suppressed(
+    transaction: &mut Transaction<'_, Postgres>,
+    normalised_email: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM suppressed_emails WHERE email_hash = $1) AS "exists!""#,
+        suppression_hash(normalised_email)
+    )
+    .fetch_one(transaction)
+    .await?;
+
+    Ok(row.exists)
+}
+
+///
+/// Hex SHA-256 of the normalised address. It is deliberately not keyed with the HMAC secret, which
+/// would forget every suppression whenever the secret is rotated.
+///
+pub fn suppression_hash(normalised_email: &str) -> String {
+    format!("{:x}", Sha256::digest(normalised_email.as_bytes()))
+}
+
+///
+/// The self-service link to a subscriber's data, valid for [`DATA_EXPORT_LINK_VALIDITY`]
+///
+pub fn data_export_link(
+    app_base_url: &reqwest::Url,
+    subscriber_id: Uuid,
+    hmac_secret: &Secret<String>,
+) -> String {
+    let expires_at = Utc::now() + DATA_EXPORT_LINK_VALIDITY;
+    let token = DataExportToken::new(subscriber_id, expires_at.timestamp());
+
+    format!(
+        "{}subscriptions/export?token={}",
+        app_base_url.as_str(),
+        token.encode(hmac_secret)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::suppression_hash;
+
+    #[test]
+    fn suppression_hashes_are_the_hex_sha256_of_the_address() {
+        assert_eq!(
+            suppression_hash("ursula@example.com"),
+            "00b41d24b65242f8c998ceca4fa6b9a6cea2a78423b24557ad6e72ae5050276f"
+        );
+    }
+}
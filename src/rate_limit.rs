@@ -0,0 +1,154 @@
+use redis::aio::ConnectionManager;
+use std::future::Future;
+use std::time::Duration;
+
+///
+/// Fixed window counters kept in Redis, so limits hold across every instance of the app. Also
+/// hands out one-time claims on keys, for things which must only be used once.
+///
+/// Every call shares one multiplexed connection and gives up after `timeout`, so a slow Redis
+/// can't hold up the requests it guards.
+///
+#[derive(Clone)]
+pub struct RateLimiter {
+    connection: ConnectionManager,
+    key_prefix: String,
+    timeout: Duration,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RateLimit {
+    Allowed,
+    /// The limit has been reached, the window resets after `retry_after`
+    Exceeded {
+        retry_after: Duration,
+    },
+}
+
+impl RateLimiter {
+    pub fn new(connection: ConnectionManager, key_prefix: String, timeout: Duration) -> Self {
+        Self {
+            connection,
+            key_prefix,
+            timeout,
+        }
+    }
+
+    ///
+    /// Count one more hit against `key`, allowing at most `limit` hits per `window`
+    ///
+    #[tracing::instrument(name = "Check rate limit", skip(self))]
+    pub async fn hit(
+        &self,
+        key: &str,
+        limit: u32,
+        window: Duration,
+    ) -> Result<RateLimit, redis::RedisError> {
+        let key = format!("{}:{}", self.key_prefix, key);
+        let mut connection = self.connection.clone();
+
+        self.with_timeout(async move {
+            let hits: u64 = redis::cmd("INCR")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await?;
+            if hits == 1 {
+                redis::cmd("EXPIRE")
+                    .arg(&key)
+                    .arg(window.as_secs())
+                    .query_async::<_, ()>(&mut connection)
+                    .await?;
+            }
+
+            if hits <= u64::from(limit) {
+                return Ok(RateLimit::Allowed);
+            }
+
+            let ttl: i64 = redis::cmd("TTL")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await?;
+            let retry_after = match u64::try_from(ttl) {
+                Ok(ttl) if ttl > 0 => Duration::from_secs(ttl),
+                // The expiry was lost, e.g. we died between INCR and EXPIRE, so the key would never reset
+                _ => {
+                    redis::cmd("EXPIRE")
+                        .arg(&key)
+                        .arg(window.as_secs())
+                        .query_async::<_, ()>(&mut connection)
+                        .await?;
+                    window
+                }
+            };
+
+            Ok(RateLimit::Exceeded { retry_after })
+        })
+        .await
+    }
+
+    ///
+    /// Claim `key` for `ttl`, returning `false` if it has already been claimed
+    ///
+    #[tracing::instrument(name = "Claim key", skip(self))]
+    pub async fn claim(&self, key: &str, ttl: Duration) -> Result<bool, redis::RedisError> {
+        let key = format!("{}:{}", self.key_prefix, key);
+        let mut connection = self.connection.clone();
+
+        self.with_timeout(async move {
+            // SET NX replies nil when the key already exists
+            let claimed: Option<String> = redis::cmd("SET")
+                .arg(&key)
+                .arg(1)
+                .arg("NX")
+                .arg("EX")
+                .arg(ttl.as_secs().max(1))
+                .query_async(&mut connection)
+                .await?;
+
+            Ok(claimed.is_some())
+        })
+        .await
+    }
+
+    async fn with_timeout<T>(
+        &self,
+        commands: impl Future<Output = Result<T, redis::RedisError>>,
+    ) -> Result<T, redis::RedisError> {
+        tokio::time::timeout(self.timeout, commands)
+            .await
+            .map_err(|_| {
+                redis::RedisError::from((redis::ErrorKind::IoError, "Timed out waiting for Redis"))
+            })?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use claims::assert_err;
+    use redis::aio::ConnectionManager;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn calls_to_an_unresponsive_redis_time_out() {
+        // Connections are accepted by the OS but nothing ever answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client =
+            redis::Client::open(format!("redis://{}", listener.local_addr().unwrap())).unwrap();
+        let connection = ConnectionManager::new(client).await.unwrap();
+        let rate_limiter = RateLimiter::new(connection, "test".into(), Duration::from_millis(100));
+
+        let outcome = tokio::time::timeout(Duration::from_secs(5), async {
+            (
+                rate_limiter.hit("key", 1, Duration::from_secs(60)).await,
+                rate_limiter.claim("key", Duration::from_secs(60)).await,
+            )
+        })
+        .await
+        .expect("The rate limiter didn't give up on Redis");
+
+        assert_err!(outcome.0);
+        assert_err!(outcome.1);
+    }
+}
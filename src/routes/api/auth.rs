@@ -0,0 +1,57 @@
+use std::net::IpAddr;
+
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::ApiError;
+use crate::audit_log::Actor;
+use crate::authentication::{basic_authorisation, validate_credentials, AuthError};
+use crate::helpers::TrustedProxies;
+
+///
+/// A user authenticated with HTTP Basic credentials, the same ones accepted by `/newsletters`.
+/// Taking it as a handler argument is enough to protect an endpoint.
+///
+pub struct ApiUser {
+    pub user_id: Uuid,
+    pub client_ip: Option<IpAddr>,
+}
+
+impl ApiUser {
+    ///
+    /// The user and address to attribute changes to in the audit log
+    ///
+    pub fn actor(&self) -> Actor {
+        Actor::new(Some(self.user_id), self.client_ip)
+    }
+}
+
+impl FromRequest for ApiUser {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let credentials = basic_authorisation(req.headers());
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let client_ip = req
+            .app_data::<web::Data<TrustedProxies>>()
+            .and_then(|proxies| proxies.client_ip(req));
+
+        Box::pin(async move {
+            let credentials = credentials.map_err(ApiError::AuthError)?;
+            let pool =
+                pool.ok_or_else(|| anyhow::anyhow!("The database pool is not configured"))?;
+
+            let user_id = validate_credentials(credentials, &pool)
+                .await
+                .map_err(|e| match e {
+                    AuthError::InvalidCredentials(_) => ApiError::AuthError(e.into()),
+                    AuthError::UnexpectedError(_) => ApiError::UnexpectedError(e.into()),
+                })?;
+
+            Ok(ApiUser { user_id, client_ip })
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+
+use crate::helpers::{error_chain_fmt, ErrorBody};
+use crate::routes::FieldErrors;
+
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+
+    #[error("{0}")]
+    ValidationError(String),
+
+    #[error("{}", .0.values().cloned().collect::<Vec<_>>().join(" "))]
+    InvalidFields(FieldErrors),
+
+    #[error("There is no {0} with that id")]
+    NotFound(&'static str),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::AuthError(_) => "unauthorised",
+            ApiError::ValidationError(_) => "invalid_request",
+            ApiError::InvalidFields(_) => "invalid_fields",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            ApiError::ValidationError(_) | ApiError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // The cause is logged, there's nothing in it for the client
+        let message = match self {
+            ApiError::UnexpectedError(_) => "Something went wrong, please try again later".into(),
+            e => e.to_string(),
+        };
+
+        let mut response = HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: self.code(),
+            message,
+            fields: match self {
+                ApiError::InvalidFields(fields) => Some(fields),
+                _ => None,
+            },
+            retry_after_seconds: None,
+        });
+
+        if let ApiError::AuthError(_) = self {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="api""#),
+            );
+        }
+
+        response
+    }
+}
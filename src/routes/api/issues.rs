@@ -0,0 +1,200 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::pagination::{page_size, Cursor, Page};
+use super::{ApiError, ApiUser};
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct IssueSummary {
+    pub id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub track_opens: bool,
+    pub track_clicks: bool,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct Issue {
+    pub id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub track_opens: bool,
+    pub track_clicks: bool,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+///
+/// Headline delivery counts, the same ones shown on the issue's dashboard page
+///
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct DeliveryStats {
+    pub issue_id: Uuid,
+    /// Confirmed subscribers the issue was sent to
+    pub targeted: i64,
+    /// Accepted by the email provider and not bounced since
+    pub delivered: i64,
+    pub failed: i64,
+    pub bounced: i64,
+    pub complained: i64,
+    /// Recipients who opened the issue at least once
+    pub opened: i64,
+    /// Recipients who clicked at least one link
+    pub clicked: i64,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListIssuesQuery {
+    /// Only list issues published at or after this time
+    pub published_after: Option<DateTime<Utc>>,
+    /// Only list issues published before this time
+    pub published_before: Option<DateTime<Utc>>,
+    /// `next_cursor` from the previous page
+    pub cursor: Option<String>,
+    /// Page size, from 1 to 200, defaults to 50
+    pub limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues",
+    tag = "issues",
+    params(ListIssuesQuery),
+    responses(
+        (status = 200, description = "Issues, newest first", body = IssuePage),
+        (status = 400, description = "Invalid filter or cursor", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: list issues", skip_all)]
+pub async fn list_issues(
+    _user: ApiUser,
+    query: web::Query<ListIssuesQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = page_size(query.limit)?;
+    let cursor = query.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let (before, before_id) = match cursor {
+        Some(Cursor { at, id }) => (Some(at), Some(id)),
+        None => (None, None),
+    };
+
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+      SELECT id, title, published_at, track_opens, track_clicks
+      FROM newsletter_issues
+      WHERE ($1::TIMESTAMPTZ IS NULL OR published_at >= $1)
+        AND ($2::TIMESTAMPTZ IS NULL OR published_at < $2)
+        AND ($3::TIMESTAMPTZ IS NULL OR (published_at, id) < ($3, $4::UUID))
+      ORDER BY published_at DESC, id DESC
+      LIMIT $5
+    "#,
+        query.published_after,
+        query.published_before,
+        before,
+        before_id,
+        limit + 1
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to list newsletter issues")?;
+
+    let page = Page::from_rows(issues, limit, |i| Cursor {
+        at: i.published_at,
+        id: i.id,
+    });
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{issue_id}",
+    tag = "issues",
+    params(("issue_id" = Uuid, Path, description = "Id of the issue")),
+    responses(
+        (status = 200, description = "The issue, with its content", body = Issue),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "No such issue", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: get issue", skip(_user, pool))]
+pub async fn read_issue(
+    _user: ApiUser,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue = sqlx::query_as!(
+        Issue,
+        r#"
+      SELECT id, title, published_at, track_opens, track_clicks, text_content, html_content
+      FROM newsletter_issues
+      WHERE id = $1
+    "#,
+        *issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to get newsletter issue")?
+    .ok_or(ApiError::NotFound("issue"))?;
+
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{issue_id}/stats",
+    tag = "issues",
+    params(("issue_id" = Uuid, Path, description = "Id of the issue")),
+    responses(
+        (status = 200, description = "Delivery counts for the issue", body = DeliveryStats),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "No such issue", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: get issue delivery stats", skip(_user, pool))]
+pub async fn read_issue_stats(
+    _user: ApiUser,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = sqlx::query_as!(
+        DeliveryStats,
+        r#"
+      SELECT
+        i.id AS issue_id,
+        COUNT(d.subscriber_id) AS "targeted!",
+        COUNT(*) FILTER (WHERE d.status = 'delivered' AND d.bounced_at IS NULL) AS "delivered!",
+        COUNT(*) FILTER (WHERE d.status = 'failed') AS "failed!",
+        COUNT(*) FILTER (WHERE d.bounced_at IS NOT NULL) AS "bounced!",
+        COUNT(*) FILTER (WHERE d.complained_at IS NOT NULL) AS "complained!",
+        COUNT(*) FILTER (WHERE o.open_count > 0) AS "opened!",
+        COUNT(*) FILTER (
+          WHERE EXISTS (
+            SELECT 1
+            FROM issue_clicks c
+            WHERE c.newsletter_issue_id = d.newsletter_issue_id
+              AND c.subscriber_id = d.subscriber_id
+          )
+        ) AS "clicked!"
+      FROM newsletter_issues i
+      LEFT JOIN issue_delivery_log d ON d.newsletter_issue_id = i.id
+      LEFT JOIN issue_opens o
+        ON o.newsletter_issue_id = d.newsletter_issue_id AND o.subscriber_id = d.subscriber_id
+      WHERE i.id = $1
+      GROUP BY i.id
+    "#,
+        *issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to get issue delivery stats")?
+    .ok_or(ApiError::NotFound("issue"))?;
+
+    Ok(HttpResponse::Ok().json(stats))
+}
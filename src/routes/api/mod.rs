@@ -0,0 +1,69 @@
+mod auth;
+mod error;
+mod issues;
+mod openapi;
+mod pagination;
+mod subscribers;
+
+pub use auth::*;
+pub use error::*;
+pub use issues::*;
+pub use openapi::*;
+pub use pagination::*;
+pub use subscribers::*;
+
+use actix_web::{error::InternalError, web, HttpResponse};
+
+///
+/// Routes for the `/api/v1` scope. Malformed bodies and query strings get the same JSON errors as
+/// the handlers return.
+///
+pub fn api_v1(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(|e, _| {
+        let error = ApiError::ValidationError(e.to_string());
+        let response = actix_web::ResponseError::error_response(&error);
+        InternalError::from_response(e, response).into()
+    }))
+    .app_data(web::QueryConfig::default().error_handler(|e, _| {
+        let error = ApiError::ValidationError(e.to_string());
+        let response = actix_web::ResponseError::error_response(&error);
+        InternalError::from_response(e, response).into()
+    }))
+    .app_data(web::PathConfig::default().error_handler(|e, _| {
+        let error = ApiError::NotFound("resource");
+        let response = actix_web::ResponseError::error_response(&error);
+        InternalError::from_response(e, response).into()
+    }))
+    .route("/openapi.json", web::get().to(openapi_json))
+    .route("/subscribers", web::get().to(list_subscribers))
+    .route("/subscribers", web::post().to(create_subscriber))
+    .route(
+        "/subscribers/{subscriber_id}",
+        web::get().to(read_subscriber),
+    )
+    .route(
+        "/subscribers/{subscriber_id}",
+        web::patch().to(update_subscriber),
+    )
+    .route(
+        "/subscribers/{subscriber_id}",
+        web::delete().to(delete_subscriber),
+    )
+    .route(
+        "/subscribers/{subscriber_id}/export",
+        web::get().to(export_subscriber),
+    )
+    .route(
+        "/subscribers/{subscriber_id}/erase",
+        web::post().to(erase_subscriber),
+    )
+    .route("/issues", web::get().to(list_issues))
+    .route("/issues/{issue_id}", web::get().to(read_issue))
+    .route("/issues/{issue_id}/stats", web::get().to(read_issue_stats))
+    .default_service(web::to(|| async {
+        HttpResponse::NotFound().json(serde_json::json!({
+          "error": "not_found",
+          "message": "There is no such endpoint",
+        }))
+    }));
+}
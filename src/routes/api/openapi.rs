@@ -0,0 +1,80 @@
+use actix_web::HttpResponse;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::issues::{self, DeliveryStats, Issue, IssueSummary};
+use super::pagination::{IssuePage, SubscriberPage};
+use super::subscribers::{self, CreateSubscriber, Subscriber, SubscriberStatus, UpdateSubscriber};
+use crate::helpers::ErrorBody;
+use crate::personal_data::{
+    ExportedClick, ExportedConsent, ExportedDelivery, ExportedOpen, ExportedSubscriber,
+    ExportedWebhookEvent, SubscriberDataExport,
+};
+
+///
+/// The OpenAPI document for `/api/v1`, generated from the handlers and the types they exchange
+///
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Newsletter API", version = "1"),
+    paths(
+        subscribers::list_subscribers,
+        subscribers::create_subscriber,
+        subscribers::read_subscriber,
+        subscribers::update_subscriber,
+        subscribers::delete_subscriber,
+        subscribers::export_subscriber,
+        subscribers::erase_subscriber,
+        issues::list_issues,
+        issues::read_issue,
+        issues::read_issue_stats,
+    ),
+    components(schemas(
+        Subscriber,
+        SubscriberStatus,
+        SubscriberPage,
+        CreateSubscriber,
+        UpdateSubscriber,
+        SubscriberDataExport,
+        ExportedSubscriber,
+        ExportedConsent,
+        ExportedDelivery,
+        ExportedOpen,
+        ExportedClick,
+        ExportedWebhookEvent,
+        IssueSummary,
+        IssuePage,
+        Issue,
+        DeliveryStats,
+        ErrorBody,
+    )),
+    modifiers(&BasicAuth),
+    security(("basic_auth" = [])),
+    tags(
+        (name = "subscribers", description = "Manage the mailing list"),
+        (name = "issues", description = "Published issues and how they were received"),
+    )
+)]
+pub struct ApiDoc;
+
+///
+/// The same HTTP Basic credentials as `POST /newsletters`
+///
+struct BasicAuth;
+
+impl Modify for BasicAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "basic_auth",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).build()),
+        );
+    }
+}
+
+///
+/// Served without credentials, so clients can be generated before anyone has an account
+///
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
@@ -0,0 +1,138 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{ApiError, IssueSummary, Subscriber};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+///
+/// Position in a listing ordered by a timestamp, with the id breaking ties.
+/// Handed to clients as an opaque string, so the encoding can change without breaking them.
+///
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cursor {
+    pub at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}.{}", self.at.timestamp_micros(), self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, ApiError> {
+        let invalid = || ApiError::ValidationError("The cursor is not valid".into());
+
+        let decoded = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (micros, id) = decoded.split_once('.').ok_or_else(invalid)?;
+
+        Ok(Cursor {
+            at: micros
+                .parse()
+                .ok()
+                .and_then(DateTime::from_timestamp_micros)
+                .ok_or_else(invalid)?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+///
+/// Check the page size asked for, or fall back to the default
+///
+pub fn page_size(limit: Option<i64>) -> Result<i64, ApiError> {
+    match limit {
+        None => Ok(DEFAULT_PAGE_SIZE),
+        Some(limit) if (1..=MAX_PAGE_SIZE).contains(&limit) => Ok(limit),
+        Some(_) => Err(ApiError::ValidationError(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        ))),
+    }
+}
+
+///
+/// One page of a listing, `next_cursor` is missing on the last page
+///
+#[derive(serde::Serialize, utoipa::ToSchema)]
+#[aliases(
+    SubscriberPage = Page<Subscriber>,
+    IssuePage = Page<IssueSummary>
+)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass as `cursor` to fetch the next page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    ///
+    /// Build a page from up to `limit + 1` rows, the extra row only tells us there is another page
+    ///
+    pub fn from_rows(mut rows: Vec<T>, limit: i64, cursor_of: impl Fn(&T) -> Cursor) -> Self {
+        let has_more = rows.len() as i64 > limit;
+        rows.truncate(limit as usize);
+
+        let next_cursor = match rows.last() {
+            Some(last) if has_more => Some(cursor_of(last).encode()),
+            _ => None,
+        };
+
+        Page {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{page_size, Cursor, Page};
+    use chrono::{DateTime, Utc};
+    use claims::{assert_err, assert_ok_eq};
+    use uuid::Uuid;
+
+    fn cursor_at(micros: i64) -> Cursor {
+        Cursor {
+            at: DateTime::<Utc>::from_timestamp_micros(micros).unwrap(),
+            id: Uuid::new_v4(),
+        }
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = cursor_at(1_708_945_200_123_456);
+        assert_ok_eq!(Cursor::decode(&cursor.encode()), cursor);
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        assert_err!(Cursor::decode("not a cursor"));
+        assert_err!(Cursor::decode("MTIzNA"));
+    }
+
+    #[test]
+    fn page_sizes_are_bounded() {
+        assert_ok_eq!(page_size(None), 50);
+        assert_ok_eq!(page_size(Some(200)), 200);
+        assert_err!(page_size(Some(0)));
+        assert_err!(page_size(Some(201)));
+    }
+
+    #[test]
+    fn the_next_cursor_points_at_the_last_item_only_when_there_are_more() {
+        let page = Page::from_rows(vec![1, 2, 3], 2, |i| cursor_at(*i));
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(
+            Cursor::decode(&page.next_cursor.unwrap()).unwrap().at,
+            cursor_at(2).at
+        );
+
+        let page = Page::from_rows(vec![1, 2], 2, |i| cursor_at(*i));
+        assert_eq!(page.items, vec![1, 2]);
+        assert!(page.next_cursor.is_none());
+    }
+}
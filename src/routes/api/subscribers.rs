@@ -0,0 +1,626 @@
+use std::collections::BTreeSet;
+
+use actix_web::{http::header, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::pagination::{page_size, Cursor, Page};
+use super::{ApiError, ApiUser};
+use crate::audit_log::{diff, record_audit_event, AuditAction};
+use crate::domain::{EmailPolicy, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::personal_data::{self, export_subscriber_data, is_suppressed};
+use crate::routes::{get_subscriber_by_normalised_email, insert_subscriber, FieldErrors};
+use crate::webhook_events::{enqueue_webhook_event, WebhookEvent};
+
+/// Postgres' error code for a unique constraint violation
+const UNIQUE_VIOLATION: &str = "23505";
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriberStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriberStatus {
+    ///
+    /// The value stored in `subscriptions.status`
+    ///
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriberStatus::PendingConfirmation => "pending_confirmation",
+            SubscriberStatus::Confirmed => "confirmed",
+            SubscriberStatus::Unsubscribed => "unsubscribed",
+        }
+    }
+
+    fn parse(status: &str) -> Result<Self, anyhow::Error> {
+        match status {
+            "pending_confirmation" => Ok(SubscriberStatus::PendingConfirmation),
+            "confirmed" => Ok(SubscriberStatus::Confirmed),
+            "unsubscribed" => Ok(SubscriberStatus::Unsubscribed),
+            other => Err(anyhow::anyhow!("Unknown subscriber status {}", other)),
+        }
+    }
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct Subscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: SubscriberStatus,
+    pub subscribed_at: DateTime<Utc>,
+    /// Free-form values available to issue templates
+    #[schema(value_type = Object)]
+    pub attributes: serde_json::Value,
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    attributes: serde_json::Value,
+}
+
+impl TryFrom<SubscriberRow> for Subscriber {
+    type Error = anyhow::Error;
+
+    fn try_from(row: SubscriberRow) -> Result<Self, Self::Error> {
+        Ok(Subscriber {
+            id: row.id,
+            email: row.email,
+            name: row.name,
+            status: SubscriberStatus::parse(&row.status)?,
+            subscribed_at: row.subscribed_at,
+            attributes: row.attributes,
+        })
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListSubscribersQuery {
+    /// Only list subscribers with this status
+    pub status: Option<SubscriberStatus>,
+    /// Only list subscribers whose address contains this, ignoring case
+    pub email: Option<String>,
+    /// `next_cursor` from the previous page
+    pub cursor: Option<String>,
+    /// Page size, from 1 to 200, defaults to 50
+    pub limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct CreateSubscriber {
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub name: String,
+    /// Defaults to `confirmed`, no confirmation email is sent
+    pub status: Option<SubscriberStatus>,
+    #[schema(value_type = Option<Object>)]
+    pub attributes: Option<serde_json::Value>,
+}
+
+///
+/// Fields left out are unchanged. Addresses can't be changed, the new one would need confirming.
+///
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct UpdateSubscriber {
+    pub name: Option<String>,
+    pub status: Option<SubscriberStatus>,
+    #[schema(value_type = Option<Object>)]
+    pub attributes: Option<serde_json::Value>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    params(ListSubscribersQuery),
+    responses(
+        (status = 200, description = "Subscribers, oldest first", body = SubscriberPage),
+        (status = 400, description = "Invalid filter or cursor", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: list subscribers", skip_all)]
+pub async fn list_subscribers(
+    _user: ApiUser,
+    query: web::Query<ListSubscribersQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = page_size(query.limit)?;
+    let cursor = query.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let (after, after_id) = match cursor {
+        Some(Cursor { at, id }) => (Some(at), Some(id)),
+        None => (None, None),
+    };
+
+    let rows = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+      SELECT id, email, name, status, subscribed_at, attributes
+      FROM subscriptions
+      WHERE ($1::TEXT IS NULL OR status = $1)
+        AND ($2::TEXT IS NULL OR strpos(lower(email), lower($2)) > 0)
+        AND ($3::TIMESTAMPTZ IS NULL OR (subscribed_at, id) > ($3, $4::UUID))
+      ORDER BY subscribed_at, id
+      LIMIT $5
+    "#,
+        query.status.map(|s| s.as_str()),
+        query.email,
+        after,
+        after_id,
+        limit + 1
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to list subscribers")?;
+
+    let subscribers = rows
+        .into_iter()
+        .map(Subscriber::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let page = Page::from_rows(subscribers, limit, |s| Cursor {
+        at: s.subscribed_at,
+        id: s.id,
+    });
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    request_body = CreateSubscriber,
+    responses(
+        (status = 201, description = "The new subscriber", body = Subscriber),
+        (status = 400, description = "Invalid fields", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 409, description = "The mailbox is already subscribed, or was erased", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: create subscriber", skip_all)]
+pub async fn create_subscriber(
+    user: ApiUser,
+    body: web::Json<CreateSubscriber>,
+    pool: web::Data<PgPool>,
+    email_policy: web::Data<EmailPolicy>,
+) -> Result<HttpResponse, ApiError> {
+    let CreateSubscriber {
+        email,
+        name,
+        status,
+        attributes,
+    } = body.into_inner();
+
+    let new_subscriber = match (SubscriberName::parse(name), SubscriberEmail::parse(email)) {
+        (Ok(name), Ok(email)) => NewSubscriber { email, name },
+        (name, email) => {
+            return Err(ApiError::InvalidFields(
+                [("name", name.err()), ("email", email.err())]
+                    .into_iter()
+                    .filter_map(|(field, error)| Some((field, error?)))
+                    .collect(),
+            ))
+        }
+    };
+    let attributes = validate_attributes(attributes)?.unwrap_or_else(|| serde_json::json!({}));
+    let status = status.unwrap_or(SubscriberStatus::Confirmed);
+    let normalised_email = email_policy.normalise(&new_subscriber.email);
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    if get_subscriber_by_normalised_email(&mut transaction, &normalised_email)
+        .await
+        .context("Failed to look up existing subscribers")?
+        .is_some()
+    {
+        return Err(ApiError::Conflict(format!(
+            "{} is already subscribed",
+            new_subscriber.email
+        )));
+    }
+    // Erased subscribers are only let back in through the signup form, which they confirm
+    // themselves
+    if is_suppressed(&mut transaction, &normalised_email)
+        .await
+        .context("Failed to check the suppression list")?
+    {
+        return Err(ApiError::Conflict(format!(
+            "{} asked for their data to be erased and can't be added back",
+            new_subscriber.email
+        )));
+    }
+
+    let subscriber_id =
+        match insert_subscriber(&mut transaction, &new_subscriber, &normalised_email).await {
+            Ok(subscriber_id) => subscriber_id,
+            // A concurrent request added the same address after our lookup
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+                return Err(ApiError::Conflict(format!(
+                    "{} is already subscribed",
+                    new_subscriber.email
+                )));
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context("Failed to insert new subscriber in the database")
+                    .into())
+            }
+        };
+    sqlx::query!(
+        "UPDATE subscriptions SET status = $2, attributes = $3 WHERE id = $1",
+        subscriber_id,
+        status.as_str(),
+        attributes
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to set the new subscriber's status")?;
+
+    let events =
+        std::iter::once(WebhookEvent::Subscribed).chain(WebhookEvent::for_status(status.as_str()));
+    for event in events {
+        enqueue_webhook_event(&mut transaction, event, subscriber_id)
+            .await
+            .context("Failed to queue webhooks for the new subscriber")?;
+    }
+    record_audit_event(
+        &mut transaction,
+        user.actor(),
+        AuditAction::SubscriberCreated,
+        Some(&subscriber_id.to_string()),
+        audited_changes(None, Some((status.as_str(), &attributes))),
+    )
+    .await
+    .context("Failed to record the new subscriber in the audit log")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new subscriber")?;
+
+    let subscriber = get_subscriber(&pool, subscriber_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("The new subscriber disappeared"))?;
+
+    Ok(HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            format!("/api/v1/subscribers/{}", subscriber_id),
+        ))
+        .json(subscriber))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    responses(
+        (status = 200, description = "The subscriber", body = Subscriber),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "No such subscriber", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: get subscriber", skip(_user, pool))]
+pub async fn read_subscriber(
+    _user: ApiUser,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber = get_subscriber(&pool, *subscriber_id)
+        .await?
+        .ok_or(ApiError::NotFound("subscriber"))?;
+
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    request_body = UpdateSubscriber,
+    responses(
+        (status = 200, description = "The updated subscriber", body = Subscriber),
+        (status = 400, description = "Invalid fields", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "No such subscriber", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: update subscriber", skip(user, body, pool))]
+pub async fn update_subscriber(
+    user: ApiUser,
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<UpdateSubscriber>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let UpdateSubscriber {
+        name,
+        status,
+        attributes,
+    } = body.into_inner();
+
+    let name = name
+        .map(SubscriberName::parse)
+        .transpose()
+        .map_err(|e| ApiError::InvalidFields(FieldErrors::from([("name", e)])))?;
+    let attributes = validate_attributes(attributes)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let previous = sqlx::query!(
+        "SELECT status, attributes FROM subscriptions WHERE id = $1 FOR UPDATE",
+        *subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to get subscriber")?
+    .ok_or(ApiError::NotFound("subscriber"))?;
+
+    let row = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+      UPDATE subscriptions
+      SET
+        name = COALESCE($2, name),
+        status = COALESCE($3, status),
+        attributes = COALESCE($4, attributes)
+      WHERE id = $1
+      RETURNING id, email, name, status, subscribed_at, attributes
+    "#,
+        *subscriber_id,
+        name.as_ref().map(AsRef::<str>::as_ref),
+        status.map(|s| s.as_str()),
+        attributes
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to update subscriber")?;
+
+    if row.status != previous.status {
+        if let Some(event) = WebhookEvent::for_status(&row.status) {
+            enqueue_webhook_event(&mut transaction, event, row.id)
+                .await
+                .context("Failed to queue webhooks for the status change")?;
+        }
+    }
+    record_audit_event(
+        &mut transaction,
+        user.actor(),
+        AuditAction::SubscriberUpdated,
+        Some(&row.id.to_string()),
+        audited_changes(
+            Some((&previous.status, &previous.attributes)),
+            Some((&row.status, &row.attributes)),
+        ),
+    )
+    .await
+    .context("Failed to record the update in the audit log")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a subscriber")?;
+
+    Ok(HttpResponse::Ok().json(Subscriber::try_from(row)?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    responses(
+        (status = 204, description = "The subscriber and their delivery history are gone"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "No such subscriber", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: delete subscriber", skip(user, pool))]
+pub async fn delete_subscriber(
+    user: ApiUser,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // Tokens predate the cascading foreign keys, everything else goes with the subscriber
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        *subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete subscription tokens")?;
+    let deleted = sqlx::query!(
+        "DELETE FROM subscriptions WHERE id = $1 RETURNING status, attributes",
+        *subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to delete subscriber")?
+    .ok_or(ApiError::NotFound("subscriber"))?;
+    record_audit_event(
+        &mut transaction,
+        user.actor(),
+        AuditAction::SubscriberDeleted,
+        Some(&subscriber_id.to_string()),
+        audited_changes(Some((&deleted.status, &deleted.attributes)), None),
+    )
+    .await
+    .context("Failed to record the deletion in the audit log")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber")?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{subscriber_id}/export",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    responses(
+        (status = 200, description = "Everything held on the subscriber", body = SubscriberDataExport),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "No such subscriber", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: export subscriber", skip(_user, pool))]
+pub async fn export_subscriber(
+    _user: ApiUser,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let export = export_subscriber_data(&pool, *subscriber_id)
+        .await?
+        .ok_or(ApiError::NotFound("subscriber"))?;
+
+    Ok(HttpResponse::Ok().json(export))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers/{subscriber_id}/erase",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    responses(
+        (status = 204, description = "Everything held on the subscriber is gone, and the address can't be added back through the API"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "No such subscriber", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(name = "API: erase subscriber", skip(user, pool))]
+pub async fn erase_subscriber(
+    user: ApiUser,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    if !personal_data::erase_subscriber(&mut transaction, *subscriber_id).await? {
+        return Err(ApiError::NotFound("subscriber"));
+    }
+    record_audit_event(
+        &mut transaction,
+        user.actor(),
+        AuditAction::SubscriberErased,
+        Some(&subscriber_id.to_string()),
+        serde_json::json!({}),
+    )
+    .await
+    .context("Failed to record the erasure in the audit log")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber")?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// A subscriber's status and attributes, as seen by the audit log
+pub type AuditedSubscriber<'a> = (&'a str, &'a serde_json::Value);
+
+///
+/// The audit log changes for a subscriber going from `before` to `after`, `None` when it doesn't
+/// exist on that side. Subscribers are referred to by id, their email and name are left out, and
+/// only the names of changed attributes are kept, so erasing them leaves nothing identifying
+/// behind in the append-only log.
+///
+pub fn audited_changes(
+    before: Option<AuditedSubscriber>,
+    after: Option<AuditedSubscriber>,
+) -> serde_json::Value {
+    let status = |subscriber: Option<AuditedSubscriber>| match subscriber {
+        Some((status, _)) => serde_json::json!({ "status": status }),
+        None => serde_json::Value::Null,
+    };
+    let mut changes = diff(&status(before), &status(after));
+
+    let empty = serde_json::Map::new();
+    let before = attributes_of(before).unwrap_or(&empty);
+    let after = attributes_of(after).unwrap_or(&empty);
+    let changed_attributes: BTreeSet<&String> = before
+        .keys()
+        .chain(after.keys())
+        .filter(|key| before.get(*key) != after.get(*key))
+        .collect();
+    if !changed_attributes.is_empty() {
+        changes["attributes"] = serde_json::json!(changed_attributes);
+    }
+
+    changes
+}
+
+fn attributes_of<'a>(
+    subscriber: Option<AuditedSubscriber<'a>>,
+) -> Option<&'a serde_json::Map<String, serde_json::Value>> {
+    subscriber.and_then(|(_, attributes)| attributes.as_object())
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, ApiError> {
+    let row = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+      SELECT id, email, name, status, subscribed_at, attributes
+      FROM subscriptions
+      WHERE id = $1
+    "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to get subscriber")?;
+
+    Ok(row.map(Subscriber::try_from).transpose()?)
+}
+
+///
+/// Templates look attributes up by name, so they have to be an object
+///
+fn validate_attributes(
+    attributes: Option<serde_json::Value>,
+) -> Result<Option<serde_json::Value>, ApiError> {
+    match attributes {
+        Some(attributes) if !attributes.is_object() => {
+            Err(ApiError::InvalidFields(FieldErrors::from([(
+                "attributes",
+                "attributes must be a JSON object.".to_string(),
+            )])))
+        }
+        attributes => Ok(attributes),
+    }
+}
@@ -0,0 +1,116 @@
+use actix_web::{web, HttpResponse};
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit_log::AuditAction,
+    helpers::{e500, render, see_other},
+    session_state::TypedSession,
+};
+
+/// Entries shown on the page, newest first
+const AUDIT_LOG_PAGE_LENGTH: i64 = 200;
+
+#[derive(serde::Deserialize)]
+pub struct AuditLogFilter {
+    action: Option<String>,
+    username: Option<String>,
+    target: Option<String>,
+}
+
+struct AuditEntry {
+    occurred_at: chrono::DateTime<chrono::Utc>,
+    user_id: Option<Uuid>,
+    username: Option<String>,
+    action: String,
+    target: Option<String>,
+    ip_address: Option<String>,
+    changes: serde_json::Value,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard/audit_log.html")]
+struct AuditLogPage {
+    actions: Vec<&'static str>,
+    action: Option<String>,
+    username: Option<String>,
+    target: Option<String>,
+    entries: Vec<AuditEntry>,
+}
+
+///
+/// The audit log, filtered by action, the user who acted and what they acted on
+///
+pub async fn admin_audit_log(
+    filter: web::Query<AuditLogFilter>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let non_empty = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_owned)
+    };
+    let action = non_empty(&filter.action);
+    let username = non_empty(&filter.username);
+    let target = non_empty(&filter.target);
+
+    let entries = get_audit_entries(
+        &pool,
+        action.as_deref(),
+        username.as_deref(),
+        target.as_deref(),
+    )
+    .await
+    .map_err(e500)?;
+
+    render(&AuditLogPage {
+        actions: AuditAction::ALL.iter().map(AuditAction::as_str).collect(),
+        action,
+        username,
+        target,
+        entries,
+    })
+}
+
+#[tracing::instrument(name = "Get audit log entries", skip(pool))]
+async fn get_audit_entries(
+    pool: &PgPool,
+    action: Option<&str>,
+    username: Option<&str>,
+    target: Option<&str>,
+) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditEntry,
+        r#"
+      SELECT
+        a.occurred_at,
+        a.user_id,
+        u.username AS "username?",
+        a.action,
+        a.target,
+        a.ip_address,
+        a.changes
+      FROM audit_log a
+      LEFT JOIN users u ON u.user_id = a.user_id
+      WHERE ($1::TEXT IS NULL OR a.action = $1)
+        AND ($2::TEXT IS NULL OR u.username = $2)
+        AND ($3::TEXT IS NULL OR a.target = $3)
+      ORDER BY a.occurred_at DESC
+      LIMIT $4
+    "#,
+        action,
+        username,
+        target,
+        AUDIT_LOG_PAGE_LENGTH
+    )
+    .fetch_all(pool)
+    .await
+}
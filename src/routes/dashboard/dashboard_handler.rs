@@ -1,10 +1,22 @@
-use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web::{web, HttpResponse};
 use anyhow::Context;
+use askama::Template;
 use reqwest::header::LOCATION;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{helpers::e500, session_state::TypedSession};
+use crate::{
+    csrf::csrf_token,
+    helpers::{e500, render},
+    session_state::TypedSession,
+};
+
+#[derive(Template)]
+#[template(path = "dashboard/index.html")]
+struct DashboardPage {
+    username: String,
+    csrf_token: String,
+}
 
 pub async fn admin_dashboard(
     session: TypedSession,
@@ -18,29 +30,10 @@ pub async fn admin_dashboard(
             .finish());
     };
 
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-            <html lang="en">
-            <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Admin dashboard</title>
-            </head>
-            <body>
-                <p>Welcome {username}!</p>
-                <p>Available actions:</p>
-                <ol>
-                    <li><a href="/admin/password">Change password</a></li>
-                    <li>
-                      <form name="logoutForm" action="/admin/logout" method="post">
-                        <input type="submit" value="Logout">
-                      </form>
-                    </li>
-                </ol>
-            </body>
-            </html>"#
-        )))
+    render(&DashboardPage {
+        username,
+        csrf_token: csrf_token(&session)?,
+    })
 }
 
 #[tracing::instrument(name = "Get username")]
@@ -0,0 +1,62 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    helpers::{e500, see_other},
+    session_state::TypedSession,
+};
+
+#[derive(serde::Serialize)]
+struct LinkClicks {
+    url: String,
+    clicks: i64,
+    unique_clicks: i64,
+}
+
+///
+/// Per-link click counts for a single issue, links which were never clicked are included with 0
+///
+pub async fn issue_clicks(
+    issue_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let links = get_link_clicks(&pool, *issue_id).await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok().json(links))
+}
+
+#[tracing::instrument(name = "Get issue link clicks", skip(pool))]
+async fn get_link_clicks(pool: &PgPool, issue_id: Uuid) -> Result<Vec<LinkClicks>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+      SELECT
+        l.url,
+        COUNT(c.id) AS "clicks!",
+        COUNT(DISTINCT c.subscriber_id) AS "unique_clicks!"
+      FROM issue_links l
+      LEFT JOIN issue_clicks c
+        ON c.newsletter_issue_id = l.newsletter_issue_id AND c.link_id = l.link_id
+      WHERE l.newsletter_issue_id = $1
+      GROUP BY l.link_id, l.url
+      ORDER BY l.link_id
+    "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| LinkClicks {
+            url: r.url,
+            clicks: r.clicks,
+            unique_clicks: r.unique_clicks,
+        })
+        .collect())
+}
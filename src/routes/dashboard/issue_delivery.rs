@@ -0,0 +1,148 @@
+use actix_web::{web, HttpResponse};
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    helpers::{e500, render, see_other},
+    session_state::TypedSession,
+};
+
+struct IssueSummary {
+    title: String,
+    published_at: chrono::DateTime<chrono::Utc>,
+}
+
+struct RecipientDelivery {
+    subscriber_email: String,
+    status: String,
+    message_id: Option<String>,
+    error: Option<String>,
+    bounced: bool,
+    complained: bool,
+    opens: i32,
+    clicks: i64,
+}
+
+impl RecipientDelivery {
+    fn outcome(&self) -> &str {
+        if self.complained {
+            "complained"
+        } else if self.bounced {
+            "bounced"
+        } else {
+            &self.status
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "dashboard/issue_delivery.html")]
+struct IssueDeliveryPage {
+    issue: IssueSummary,
+    targeted: usize,
+    delivered: usize,
+    failed: usize,
+    bounced: usize,
+    complained: usize,
+    opened: usize,
+    clicked: usize,
+    recipients: Vec<RecipientDelivery>,
+}
+
+///
+/// Delivery analytics for a single issue: headline counts and the outcome for every recipient
+///
+pub async fn issue_delivery(
+    issue_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let Some(issue) = get_issue_summary(&pool, *issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let recipients = get_recipient_deliveries(&pool, *issue_id)
+        .await
+        .map_err(e500)?;
+
+    let count = |f: fn(&RecipientDelivery) -> bool| recipients.iter().filter(|r| f(r)).count();
+    let targeted = recipients.len();
+    let delivered = count(|r| r.status == "delivered" && !r.bounced);
+    let failed = count(|r| r.status == "failed");
+    let bounced = count(|r| r.bounced);
+    let complained = count(|r| r.complained);
+    let opened = count(|r| r.opens > 0);
+    let clicked = count(|r| r.clicks > 0);
+
+    render(&IssueDeliveryPage {
+        issue,
+        targeted,
+        delivered,
+        failed,
+        bounced,
+        complained,
+        opened,
+        clicked,
+        recipients,
+    })
+}
+
+#[tracing::instrument(name = "Get issue summary", skip(pool))]
+async fn get_issue_summary(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueSummary>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        IssueSummary,
+        r#"
+      SELECT title, published_at
+      FROM newsletter_issues
+      WHERE id = $1
+    "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Get recipient deliveries", skip(pool))]
+async fn get_recipient_deliveries(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<RecipientDelivery>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        RecipientDelivery,
+        r#"
+      SELECT
+        d.subscriber_email,
+        d.status,
+        d.message_id,
+        d.error,
+        d.bounced_at IS NOT NULL AS "bounced!",
+        d.complained_at IS NOT NULL AS "complained!",
+        COALESCE(o.open_count, 0) AS "opens!",
+        (
+          SELECT COUNT(*)
+          FROM issue_clicks c
+          WHERE c.newsletter_issue_id = d.newsletter_issue_id
+            AND c.subscriber_id = d.subscriber_id
+        ) AS "clicks!"
+      FROM issue_delivery_log d
+      LEFT JOIN issue_opens o
+        ON o.newsletter_issue_id = d.newsletter_issue_id AND o.subscriber_id = d.subscriber_id
+      WHERE d.newsletter_issue_id = $1
+      ORDER BY d.sent_at, d.subscriber_email
+    "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
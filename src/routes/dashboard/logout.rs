@@ -1,17 +1,34 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
 use crate::{
-    helpers::{e500, see_other},
+    audit_log::{record_audit_event, Actor, AuditAction},
+    helpers::{e500, see_other, TrustedProxies},
     session_state::TypedSession,
 };
 
-pub async fn logout(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
-    if session.get_user_id().map_err(e500)?.is_none() {
+pub async fn logout(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+    trusted_proxies: web::Data<TrustedProxies>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_user_id().map_err(e500)? else {
         return Ok(see_other("/login"));
-    }
+    };
+
+    record_audit_event(
+        pool.get_ref(),
+        Actor::new(Some(user_id), trusted_proxies.client_ip(&request)),
+        AuditAction::Logout,
+        None,
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
 
     session.logout();
     FlashMessage::info("You have successfully logged out").send();
-    return Ok(see_other("/login"));
+    Ok(see_other("/login"))
 }
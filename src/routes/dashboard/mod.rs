@@ -1,7 +1,17 @@
+mod audit_log;
 mod dashboard_handler;
+mod issue_clicks;
+mod issue_delivery;
 mod logout;
 mod password;
+mod subscribers;
+mod webhook_endpoints;
 
+pub use audit_log::*;
 pub use dashboard_handler::*;
+pub use issue_clicks::*;
+pub use issue_delivery::*;
 pub use logout::*;
 pub use password::*;
+pub use subscribers::*;
+pub use webhook_endpoints::*;
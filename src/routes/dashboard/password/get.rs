@@ -1,13 +1,20 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
-
-use std::fmt::Write;
+use askama::Template;
 
 use crate::{
-    helpers::{e500, see_other},
+    csrf::csrf_token,
+    helpers::{e500, flash_messages, render, see_other},
     session_state::TypedSession,
 };
 
+#[derive(Template)]
+#[template(path = "dashboard/password.html")]
+struct ChangePasswordPage {
+    flash_messages: Vec<String>,
+    csrf_token: String,
+}
+
 pub async fn change_password_form(
     session: TypedSession,
     flash_message: IncomingFlashMessages,
@@ -16,53 +23,8 @@ pub async fn change_password_form(
         return Ok(see_other("/login"));
     }
 
-    let mut msg_html = String::new();
-    for m in flash_message.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
-
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
-        <!DOCTYPE html>
-    <html lang="en">
-    <head>
-        <meta http-equiv="content-type" content="text/html; charset=utf-8">
-        <title>Change Password</title>
-    </head>
-    <body>
-        {msg_html}
-        <form action="/admin/password" method="post">
-            <label>Current password
-                <input
-                    type="password"
-                    placeholder="Enter current password"
-                    name="current_password"
-                >
-            </label>
-            <br>
-            <label>New password
-                <input
-                    type="password"
-                    placeholder="Enter new password"
-                    name="new_password"
-                >
-            </label>
-            <br>
-            <label>Confirm new password
-                <input
-                    type="password"
-                    placeholder="Type the new password again"
-                    name="new_password_check"
-                >
-            </label>
-            <br>
-            <button type="submit">Change password</button>
-        </form>
-        <p><a href="/admin/dashboard">&lt;- Back</a></p>
-    </body>
-    </html>
-        "#
-        )))
+    render(&ChangePasswordPage {
+        flash_messages: flash_messages(&flash_message),
+        csrf_token: csrf_token(&session)?,
+    })
 }
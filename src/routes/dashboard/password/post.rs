@@ -1,11 +1,12 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
-    helpers::{e500, see_other},
+    audit_log::{record_audit_event, Actor, AuditAction},
+    authentication::{self, validate_credentials, AuthError, Credentials},
+    helpers::{e500, see_other, TrustedProxies},
     routes::get_username,
     session_state::TypedSession,
 };
@@ -21,6 +22,8 @@ pub async fn change_password(
     form: web::Form<ChangePasswordForm>,
     session: TypedSession,
     pool: web::Data<PgPool>,
+    request: HttpRequest,
+    trusted_proxies: web::Data<TrustedProxies>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = session.get_user_id().map_err(e500)?;
 
@@ -30,10 +33,10 @@ pub async fn change_password(
 
     let user_id = user_id.unwrap();
     let _current_password = form.0.current_password.expose_secret();
-    let new_password = form.0.new_password.expose_secret();
+    let new_password = form.0.new_password.expose_secret().to_owned();
     let new_password_check = form.0.new_password_check.expose_secret();
 
-    if check_if_password_match(&new_password, &new_password_check) {
+    if check_if_password_match(&new_password, new_password_check) {
         return Ok(send_flash_message_and_redirect(
             "You entered two different new passwords - the field values must match.",
             "/admin/password",
@@ -50,7 +53,7 @@ pub async fn change_password(
     let username = get_username(user_id, &pool).await.map_err(e500)?;
 
     let credentials = Credentials {
-        username,
+        username: username.clone(),
         password: form.0.current_password,
     };
 
@@ -60,16 +63,30 @@ pub async fn change_password(
                 FlashMessage::error("The current password is incorrect").send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
 
-    todo!()
+    authentication::change_password(user_id, Secret::new(new_password), &pool)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        Actor::new(Some(user_id), trusted_proxies.client_ip(&request)),
+        AuditAction::PasswordChanged,
+        Some(&username),
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("Your password has been changed.").send();
+    Ok(see_other("/admin/password"))
 }
 
 fn send_flash_message_and_redirect(flash_message: &str, redirect: &str) -> HttpResponse {
     FlashMessage::error(flash_message.to_string()).send();
-    return see_other(redirect);
+    see_other(redirect)
 }
 
 fn check_if_password_length_is_greater_than_12(password: &str) -> bool {
@@ -0,0 +1,145 @@
+use actix_web::{web, HttpResponse};
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    helpers::{e500, render, see_other},
+    session_state::TypedSession,
+};
+
+/// Subscribers listed on the search page, newest first
+const SEARCH_RESULTS_LENGTH: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberSearch {
+    email: Option<String>,
+}
+
+struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: chrono::DateTime<chrono::Utc>,
+}
+
+struct Consent {
+    consent_text: String,
+    consent_version: String,
+    source: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    given_at: chrono::DateTime<chrono::Utc>,
+    confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard/subscribers.html")]
+struct SubscribersPage<'a> {
+    email: &'a str,
+    subscribers: Vec<SubscriberSummary>,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard/subscriber.html")]
+struct SubscriberPage {
+    subscriber: SubscriberSummary,
+    consents: Vec<Consent>,
+}
+
+///
+/// The most recent subscribers, optionally narrowed down by part of their email address
+///
+pub async fn admin_subscribers(
+    search: web::Query<SubscriberSearch>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let email = search.email.as_deref().map(str::trim).unwrap_or("");
+    let subscribers = search_subscribers(&pool, email).await.map_err(e500)?;
+
+    render(&SubscribersPage { email, subscribers })
+}
+
+///
+/// A subscriber's details and the consent they gave each time they signed up
+///
+pub async fn admin_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let Some(subscriber) = get_subscriber(&pool, *subscriber_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let consents = get_consents(&pool, *subscriber_id).await.map_err(e500)?;
+
+    render(&SubscriberPage {
+        subscriber,
+        consents,
+    })
+}
+
+#[tracing::instrument(name = "Search subscribers", skip(pool))]
+async fn search_subscribers(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Vec<SubscriberSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+      SELECT id, email, name, status, subscribed_at
+      FROM subscriptions
+      WHERE email ILIKE '%' || $1 || '%'
+      ORDER BY subscribed_at DESC
+      LIMIT $2
+    "#,
+        email,
+        SEARCH_RESULTS_LENGTH
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+      SELECT id, email, name, status, subscribed_at
+      FROM subscriptions
+      WHERE id = $1
+    "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get consent records", skip(pool))]
+async fn get_consents(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<Consent>, sqlx::Error> {
+    sqlx::query_as!(
+        Consent,
+        r#"
+      SELECT consent_text, consent_version, source, ip_address, user_agent, given_at, confirmed_at
+      FROM subscription_consents
+      WHERE subscriber_id = $1
+      ORDER BY given_at DESC
+    "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+}
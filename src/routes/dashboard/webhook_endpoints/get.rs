@@ -0,0 +1,172 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    csrf::csrf_token,
+    helpers::{e500, flash_messages, render, see_other},
+    session_state::TypedSession,
+    webhook_events::WebhookEvent,
+};
+
+/// Deliveries shown on an endpoint's page, newest first
+const DELIVERY_LOG_LENGTH: i64 = 100;
+
+struct EndpointSummary {
+    id: Uuid,
+    url: String,
+    events: Vec<String>,
+    enabled: bool,
+    pending: i64,
+    failed: i64,
+}
+
+struct Endpoint {
+    url: String,
+    secret: String,
+    events: Vec<String>,
+    enabled: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+struct Delivery {
+    event_type: String,
+    status: String,
+    attempts: i32,
+    created_at: chrono::DateTime<chrono::Utc>,
+    next_attempt_at: chrono::DateTime<chrono::Utc>,
+    last_response_status: Option<i16>,
+    last_error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard/webhook_endpoints.html")]
+struct WebhookEndpointsPage {
+    flash_messages: Vec<String>,
+    csrf_token: String,
+    endpoints: Vec<EndpointSummary>,
+    events: Vec<&'static str>,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard/webhook_endpoint.html")]
+struct WebhookEndpointPage {
+    flash_messages: Vec<String>,
+    csrf_token: String,
+    endpoint_id: Uuid,
+    endpoint: Endpoint,
+    deliveries: Vec<Delivery>,
+}
+
+///
+/// Registered webhook endpoints, with a form to add another
+///
+pub async fn webhook_endpoints(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let endpoints = get_endpoint_summaries(&pool).await.map_err(e500)?;
+
+    render(&WebhookEndpointsPage {
+        flash_messages: flash_messages(&flash_message),
+        csrf_token: csrf_token(&session)?,
+        endpoints,
+        events: WebhookEvent::ALL.iter().map(WebhookEvent::as_str).collect(),
+    })
+}
+
+///
+/// An endpoint's settings and signing secret, along with its most recent deliveries
+///
+pub async fn webhook_endpoint(
+    endpoint_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let Some(endpoint) = get_endpoint(&pool, *endpoint_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let deliveries = get_deliveries(&pool, *endpoint_id).await.map_err(e500)?;
+
+    render(&WebhookEndpointPage {
+        flash_messages: flash_messages(&flash_message),
+        csrf_token: csrf_token(&session)?,
+        endpoint_id: *endpoint_id,
+        endpoint,
+        deliveries,
+    })
+}
+
+#[tracing::instrument(name = "Get webhook endpoint summaries", skip(pool))]
+async fn get_endpoint_summaries(pool: &PgPool) -> Result<Vec<EndpointSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        EndpointSummary,
+        r#"
+      SELECT
+        e.id,
+        e.url,
+        e.events,
+        e.enabled,
+        COUNT(d.id) FILTER (WHERE d.status = 'pending') AS "pending!",
+        COUNT(d.id) FILTER (WHERE d.status = 'failed') AS "failed!"
+      FROM webhook_endpoints e
+      LEFT JOIN webhook_deliveries d ON d.endpoint_id = e.id
+      GROUP BY e.id
+      ORDER BY e.created_at
+    "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get webhook endpoint", skip(pool))]
+async fn get_endpoint(pool: &PgPool, endpoint_id: Uuid) -> Result<Option<Endpoint>, sqlx::Error> {
+    sqlx::query_as!(
+        Endpoint,
+        r#"
+      SELECT url, secret, events, enabled, created_at
+      FROM webhook_endpoints
+      WHERE id = $1
+    "#,
+        endpoint_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get webhook deliveries", skip(pool))]
+async fn get_deliveries(pool: &PgPool, endpoint_id: Uuid) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as!(
+        Delivery,
+        r#"
+      SELECT
+        event_type,
+        status,
+        attempts,
+        created_at,
+        next_attempt_at,
+        last_response_status,
+        last_error
+      FROM webhook_deliveries
+      WHERE endpoint_id = $1
+      ORDER BY created_at DESC
+      LIMIT $2
+    "#,
+        endpoint_id,
+        DELIVERY_LOG_LENGTH
+    )
+    .fetch_all(pool)
+    .await
+}
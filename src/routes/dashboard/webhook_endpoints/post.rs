@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    helpers::{e500, see_other},
+    session_state::TypedSession,
+    webhook_events::{generate_webhook_secret, WebhookEvent},
+};
+
+#[derive(serde::Deserialize)]
+pub struct EnabledForm {
+    enabled: bool,
+}
+
+///
+/// Register a new endpoint. The form posts `url` along with one checkbox per event type, named
+/// after the event.
+///
+pub async fn create_webhook_endpoint(
+    form: web::Form<HashMap<String, String>>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let url = form.get("url").map(|url| url.trim()).unwrap_or_default();
+    if !reqwest::Url::parse(url).is_ok_and(|url| ["http", "https"].contains(&url.scheme())) {
+        FlashMessage::error("The endpoint must be an http:// or https:// URL.").send();
+        return Ok(see_other("/admin/webhooks"));
+    }
+
+    let events: Vec<String> = WebhookEvent::ALL
+        .iter()
+        .filter(|event| form.contains_key(event.as_str()))
+        .map(|event| event.as_str().to_string())
+        .collect();
+    if events.is_empty() {
+        FlashMessage::error("Pick at least one event to send to the endpoint.").send();
+        return Ok(see_other("/admin/webhooks"));
+    }
+
+    let endpoint_id = Uuid::new_v4();
+    let secret = generate_webhook_secret();
+    sqlx::query!(
+        r#"
+      INSERT INTO webhook_endpoints (id, url, secret, events, enabled, created_at)
+      VALUES ($1, $2, $3, $4, true, now())
+    "#,
+        endpoint_id,
+        url,
+        secret.expose_secret(),
+        &events
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("The endpoint has been added, use the secret below to verify requests.")
+        .send();
+    Ok(see_other(&format!("/admin/webhooks/{}", endpoint_id)))
+}
+
+///
+/// Pause or resume an endpoint. Deliveries queued while it is disabled are kept and go out once
+/// it is enabled again, new events are not queued for it in the meantime.
+///
+pub async fn set_webhook_endpoint_enabled(
+    endpoint_id: web::Path<Uuid>,
+    form: web::Form<EnabledForm>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    sqlx::query!(
+        "UPDATE webhook_endpoints SET enabled = $2 WHERE id = $1",
+        *endpoint_id,
+        form.enabled
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    Ok(see_other(&format!("/admin/webhooks/{}", endpoint_id)))
+}
+
+///
+/// Remove an endpoint along with its delivery log
+///
+pub async fn delete_webhook_endpoint(
+    endpoint_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    sqlx::query!("DELETE FROM webhook_endpoints WHERE id = $1", *endpoint_id)
+        .execute(pool.get_ref())
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info("The endpoint has been deleted.").send();
+    Ok(see_other("/admin/webhooks"))
+}
@@ -1,4 +1,13 @@
-use actix_web::HttpResponse;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Instant;
+
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::configuration::HealthSettings;
+use crate::email_client::EmailClient;
+use crate::shutdown::Shutdown;
 
 ///
 /// Health check endpoint, returns an empty 200 OK.
@@ -7,3 +16,131 @@ use actix_web::HttpResponse;
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
+
+#[derive(serde::Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Up,
+    Down,
+}
+
+#[derive(serde::Serialize)]
+struct ComponentHealth {
+    status: Status,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Readiness {
+    status: Status,
+    shutting_down: bool,
+    checks: BTreeMap<&'static str, ComponentHealth>,
+}
+
+///
+/// Liveness probe, the process is up and serving requests. Dependencies are deliberately not
+/// checked, restarting us would not fix them.
+///
+pub async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "up" }))
+}
+
+///
+/// Readiness probe, checks every dependency concurrently and returns 503 if any of them is down
+/// so that traffic is routed to other instances. Once shutdown has begun the checks are skipped.
+///
+pub async fn readiness(
+    shutdown: web::Data<Shutdown>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<redis::Client>,
+    email_client: web::Data<EmailClient>,
+    settings: web::Data<HealthSettings>,
+) -> HttpResponse {
+    if shutdown.is_triggered() {
+        return HttpResponse::ServiceUnavailable().json(Readiness {
+            status: Status::Down,
+            shutting_down: true,
+            checks: BTreeMap::new(),
+        });
+    }
+
+    let timeout = settings.timeout();
+
+    let (postgres, redis) = tokio::join!(
+        check("postgres", timeout, check_postgres(&pool)),
+        check("redis", timeout, check_redis(&redis)),
+    );
+
+    let mut checks = BTreeMap::from([("postgres", postgres), ("redis", redis)]);
+    if settings.check_email_provider {
+        checks.insert(
+            "email_provider",
+            check("email_provider", timeout, email_client.check_connection()).await,
+        );
+    }
+
+    let status = if checks.values().all(|c| c.status == Status::Up) {
+        Status::Up
+    } else {
+        Status::Down
+    };
+
+    let mut response = match status {
+        Status::Up => HttpResponse::Ok(),
+        Status::Down => HttpResponse::ServiceUnavailable(),
+    };
+
+    response.json(Readiness {
+        status,
+        shutting_down: false,
+        checks,
+    })
+}
+
+///
+/// Run one dependency's probe. The probe's error is only logged, it can carry hostnames and
+/// other internals which an unauthenticated caller has no business seeing.
+///
+async fn check<E: std::fmt::Debug>(
+    component: &'static str,
+    timeout: std::time::Duration,
+    probe: impl Future<Output = Result<(), E>>,
+) -> ComponentHealth {
+    let started_at = Instant::now();
+    let outcome = tokio::time::timeout(timeout, probe).await;
+    let latency_ms = started_at.elapsed().as_millis();
+
+    let error = match outcome {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            tracing::warn!(component, error.cause_chain = ?e, "Readiness check failed");
+            Some("Check failed".to_string())
+        }
+        Err(_) => {
+            tracing::warn!(component, "Readiness check timed out");
+            Some(format!("Timed out after {}ms", timeout.as_millis()))
+        }
+    };
+
+    ComponentHealth {
+        status: if error.is_none() {
+            Status::Up
+        } else {
+            Status::Down
+        },
+        latency_ms,
+        error,
+    }
+}
+
+async fn check_postgres(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+async fn check_redis(client: &redis::Client) -> Result<(), redis::RedisError> {
+    let mut connection = client.get_async_connection().await?;
+    redis::cmd("PING").query_async(&mut connection).await
+}
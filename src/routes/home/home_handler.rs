@@ -1,7 +1,30 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{web, HttpResponse};
+use askama::Template;
+use chrono::Utc;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+use crate::{
+    configuration::SignupSettings, domain::SignupFormToken, helpers::render, startup::HmacSecret,
+};
+
+#[derive(Template)]
+#[template(path = "home.html")]
+struct HomePage<'a> {
+    form_token: String,
+    proof_of_work_difficulty: u8,
+    consent_version: &'a str,
+    consent_text: &'a str,
+}
+
+pub async fn home(
+    hmac_secret: web::Data<HmacSecret>,
+    signup: web::Data<SignupSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form_token = SignupFormToken::new(Utc::now().timestamp()).encode(&hmac_secret.0);
+
+    render(&HomePage {
+        form_token,
+        proof_of_work_difficulty: signup.proof_of_work_difficulty,
+        consent_version: &signup.consent_version,
+        consent_text: &signup.consent_text,
+    })
 }
@@ -1,44 +1,46 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama::Template;
 
-#[tracing::instrument(name = "/GET Login form handler", skip(flash_messages))]
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut flash_message_html = String::new();
+use crate::{
+    csrf::csrf_token,
+    helpers::{flash_messages, render},
+    session_state::TypedSession,
+};
 
-    for m in flash_messages.iter() {
-        writeln!(flash_message_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginPage {
+    flash_messages: Vec<String>,
+    csrf_token: String,
+}
+
+#[tracing::instrument(name = "/GET Login form handler", skip(incoming, session))]
+pub async fn login_form(
+    incoming: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    render(&LoginPage {
+        flash_messages: flash_messages(&incoming),
+        csrf_token: csrf_token(&session)?,
+    })
+}
 
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-          <html lang="en">
-          <head>
-              <meta http-equiv="content-type" content="text/html; charset=utf-8">
-              <title>Login</title>
-          </head>
-          <body>
-              {flash_message_html}
-              <form action="/login" method="post">
-                  <label>Username
-                      <input
-                          type="text"
-                          placeholder="Enter Username"
-                          name="username"
-                      >
-                  </label>
-                  <label>Password
-                      <input
-                          type="password"
-                          placeholder="Enter Password"
-                          name="password"
-                      >
-                  </label>
-                  <button type="submit">Login</button>
-              </form>
-          </body>
-          </html>"#
-        ))
+#[cfg(test)]
+mod tests {
+    use super::LoginPage;
+    use askama::Template;
+
+    #[test]
+    fn flash_messages_are_escaped() {
+        let page = LoginPage {
+            flash_messages: vec!["<script>alert(1)</script>".to_string()],
+            csrf_token: "token".to_string(),
+        };
+
+        let html = page.render().unwrap();
+
+        assert!(html.contains("<p><i>&lt;script&gt;alert(1)&lt;/script&gt;</i></p>"));
+        assert!(html.contains(r#"<input type="hidden" name="csrf_token" value="token">"#));
+    }
 }
@@ -1,12 +1,13 @@
-use actix_web::{error::InternalError, web, HttpResponse, ResponseError};
+use actix_web::{error::InternalError, web, HttpRequest, HttpResponse, ResponseError};
 use actix_web_flash_messages::FlashMessage;
 use reqwest::{header::LOCATION, StatusCode};
 use secrecy::Secret;
 use sqlx::PgPool;
 
 use crate::{
+    audit_log::{record_audit_event, Actor, AuditAction},
     authentication::{validate_credentials, AuthError, Credentials},
-    helpers::error_chain_fmt,
+    helpers::{error_chain_fmt, TrustedProxies},
     session_state::TypedSession,
 };
 
@@ -39,24 +40,38 @@ impl ResponseError for LoginError {
 
 #[tracing::instrument(
   name = "Process login POST/",
-  skip(form, pool, session),
+  skip(form, pool, session, request, trusted_proxies),
   fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    request: HttpRequest,
+    trusted_proxies: web::Data<TrustedProxies>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let username = form.0.username;
     let credentials = Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
+    let client_ip = trusted_proxies.client_ip(&request);
 
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
 
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+            record_audit_event(
+                pool.get_ref(),
+                Actor::new(Some(user_id), client_ip),
+                AuditAction::LoginSucceeded,
+                Some(&username),
+                serde_json::json!({}),
+            )
+            .await
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
 
             session.renew();
 
@@ -69,6 +84,18 @@ pub async fn login(
                 .finish())
         }
         Err(e) => {
+            if let AuthError::InvalidCredentials(_) = e {
+                record_audit_event(
+                    pool.get_ref(),
+                    Actor::new(None, client_ip),
+                    AuditAction::LoginFailed,
+                    Some(&username),
+                    serde_json::json!({}),
+                )
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            }
+
             let e = match e {
                 AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
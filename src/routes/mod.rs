@@ -1,15 +1,27 @@
+mod api;
 mod dashboard;
 mod health_check;
 mod home;
 mod login;
 mod newsletters;
+mod setup;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_export;
+mod subscriptions_unsubscribe;
+mod tracking;
+mod webhooks;
 
+pub use api::*;
 pub use dashboard::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
 pub use newsletters::*;
+pub use setup::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_export::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
+pub use webhooks::*;
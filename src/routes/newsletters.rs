@@ -1,31 +1,86 @@
-use actix_web::http::header::HeaderMap;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
-use base64::Engine;
 use reqwest::header::{self, HeaderValue};
 use secrecy::Secret;
 use sqlx::PgPool;
+use uuid::Uuid;
 
-use crate::authentication::{validate_credentials, AuthError, Credentials};
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::helpers::error_chain_fmt;
+use crate::audit_log::{diff, record_audit_event, Actor, AuditAction};
+use crate::authentication::{basic_authorisation, validate_credentials, AuthError};
+use crate::configuration::TrackingSettings;
+use crate::domain::{
+    ClickToken, EmailHtml, IssueTemplate, MarkdownIssue, PersonalisationContext, SubscriberEmail,
+    TemplateEscape, TrackedLinks, TrackingToken,
+};
+use crate::email_client::{EmailClient, EmailHeader};
+use crate::helpers::{error_chain_fmt, TrustedProxies};
+use crate::personal_data::data_export_link;
+use crate::routes::unsubscribe_link;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
 
 #[derive(serde::Deserialize)]
 pub struct BodyData {
-    title: String,
-    content: Content,
+    pub title: String,
+    pub content: Content,
+    /// Embed a per-recipient tracking pixel, ignored while tracking is disabled globally
+    #[serde(default)]
+    pub track_opens: bool,
+    /// Rewrite links to go through the click tracker, ignored while tracking is disabled globally
+    #[serde(default)]
+    pub track_clicks: bool,
 }
 
 #[derive(serde::Deserialize)]
 pub struct Content {
-    html: String,
-    text: String,
+    pub html: Option<String>,
+    pub text: Option<String>,
+    pub markdown: Option<String>,
+}
+
+impl Content {
+    ///
+    /// Resolve the HTML and plain text parts of the issue.
+    /// Explicit parts always win, missing ones are derived from the Markdown body.
+    ///
+    fn into_parts(self, title: &str) -> Result<(String, String), String> {
+        let markdown = self.markdown.map(MarkdownIssue::new);
+
+        let html = match (self.html, &markdown) {
+            (Some(html), _) => html,
+            (None, Some(markdown)) => markdown.to_html(title),
+            (None, None) => return Err("Content must contain either markdown or html".into()),
+        };
+
+        let text = match (self.text, &markdown) {
+            (Some(text), _) => text,
+            (None, Some(markdown)) => markdown.to_text(),
+            (None, None) => return Err("Content must contain either markdown or text".into()),
+        };
+
+        Ok((html, text))
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct PublishResponse {
+    pub issue_id: Uuid,
+    pub warnings: Vec<String>,
+}
+
+struct NewsletterIssue<'a> {
+    title: &'a str,
+    text_content: &'a str,
+    html_content: &'a str,
+    track_opens: bool,
+    track_clicks: bool,
 }
 
 struct ConfirmedSubscriber {
+    id: Uuid,
     email: SubscriberEmail,
+    name: String,
+    attributes: serde_json::Value,
 }
 
 #[derive(thiserror::Error)]
@@ -33,6 +88,9 @@ pub enum PublishError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
 
+    #[error("{0}")]
+    ValidationError(String),
+
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -49,6 +107,7 @@ impl ResponseError for PublishError {
             PublishError::UnexpectedError(_) => {
                 HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
             }
+            PublishError::ValidationError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
             PublishError::AuthError(_) => {
                 let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
                 let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
@@ -65,18 +124,32 @@ impl ResponseError for PublishError {
 
 #[tracing::instrument(
     name = "Publish newsletters to subscribers",
-    skip(body, pool, email_client, request),
+    skip(
+        body,
+        pool,
+        email_client,
+        app_base_url,
+        hmac_secret,
+        tracking,
+        request,
+        trusted_proxies
+    ),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
+    app_base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    tracking: web::Data<TrackingSettings>,
     request: HttpRequest,
+    trusted_proxies: web::Data<TrustedProxies>,
 ) -> Result<HttpResponse, PublishError> {
     let credentials = basic_authorisation(request.headers()).map_err(PublishError::AuthError)?;
 
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
 
     let user_id = validate_credentials(credentials, &pool)
         .await
@@ -84,27 +157,146 @@ pub async fn publish_newsletter(
             AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
             AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
         })?;
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let response = publish_issue(
+        &pool,
+        &email_client,
+        &app_base_url.0,
+        &hmac_secret.0,
+        &tracking,
+        Actor::new(Some(user_id), trusted_proxies.client_ip(&request)),
+        body.0,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
 
-    let subscribers = get_confirmed_subscribers(&pool).await?;
+#[tracing::instrument(
+    name = "Publish newsletter issue",
+    skip_all,
+    fields(title = %body.title)
+)]
+///
+/// Store the issue and send it to every confirmed subscriber, shared by the HTTP endpoint and the
+/// admin CLI. Individual send failures don't stop the rest of the list, they are reported back as
+/// warnings. The publication is audited as `actor` along with the issue, before anything is sent.
+///
+pub async fn publish_issue(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    app_base_url: &reqwest::Url,
+    hmac_secret: &Secret<String>,
+    tracking: &TrackingSettings,
+    actor: Actor,
+    body: BodyData,
+) -> Result<PublishResponse, PublishError> {
+    let BodyData {
+        title,
+        content,
+        track_opens,
+        track_clicks,
+    } = body;
+    let (html, text) = content
+        .into_parts(&title)
+        .map_err(PublishError::ValidationError)?;
+    let EmailHtml { html, mut warnings } =
+        EmailHtml::process(&html).map_err(PublishError::ValidationError)?;
+
+    let track_opens = track_opens && tracking.enabled;
+    let track_clicks = track_clicks && tracking.enabled;
+
+    let (tracked_html, tracked_text, links) = if track_clicks {
+        TrackedLinks::extract(&html, &text)
+    } else {
+        (html.clone(), text.clone(), TrackedLinks::default())
+    };
+
+    // Reject unknown variables before anything is sent, rather than half way through the list
+    let html_template =
+        IssueTemplate::parse(&tracked_html).map_err(PublishError::ValidationError)?;
+    let text_template =
+        IssueTemplate::parse(&tracked_text).map_err(PublishError::ValidationError)?;
+
+    let issue = NewsletterIssue {
+        title: &title,
+        text_content: &text,
+        html_content: &html,
+        track_opens,
+        track_clicks,
+    };
+    let issue_id = insert_newsletter_issue(pool, &issue, &links, actor)
+        .await
+        .context("Failed to store newsletter issue")?;
+
+    let subscribers = get_confirmed_subscribers(pool).await?;
+    let mut failed_deliveries = 0;
 
     for subscriber in subscribers {
         match subscriber {
             Ok(valid_subscriber) => {
-                email_client
-                    .send_email(
+                let unsubscribe_url =
+                    unsubscribe_link(app_base_url, valid_subscriber.id, hmac_secret);
+                let data_export_url =
+                    data_export_link(app_base_url, valid_subscriber.id, hmac_secret);
+                let context = PersonalisationContext {
+                    subscriber_name: &valid_subscriber.name,
+                    subscriber_email: valid_subscriber.email.as_ref(),
+                    unsubscribe_url: &unsubscribe_url,
+                    data_export_url: &data_export_url,
+                    attributes: &valid_subscriber.attributes,
+                };
+
+                let click_tracking_link = |link_id: usize| {
+                    let token = ClickToken::new(issue_id, valid_subscriber.id, link_id as i32);
+                    click_tracking_link(app_base_url, &token, hmac_secret)
+                };
+                let html = html_template.render(&context, TemplateEscape::Html);
+                let mut html = links.apply(&html, TemplateEscape::Html, click_tracking_link);
+                let text = text_template.render(&context, TemplateEscape::None);
+                let text = links.apply(&text, TemplateEscape::None, click_tracking_link);
+
+                if track_opens {
+                    let token = TrackingToken::new(issue_id, valid_subscriber.id);
+                    let pixel_url = open_tracking_link(app_base_url, &token, hmac_secret);
+                    html = with_tracking_pixel(&html, &pixel_url);
+                }
+
+                // RFC 8058: mail clients offer their own unsubscribe button, posting to the link
+                let list_unsubscribe = format!("<{}>", unsubscribe_url);
+                let headers = [
+                    EmailHeader {
+                        name: "List-Unsubscribe",
+                        value: &list_unsubscribe,
+                    },
+                    EmailHeader {
+                        name: "List-Unsubscribe-Post",
+                        value: "List-Unsubscribe=One-Click",
+                    },
+                ];
+
+                // Carry on with the rest of the list, the failure is recorded in the delivery log
+                let outcome = email_client
+                    .send_email_with_headers(
                         &valid_subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
+                        &title,
+                        &html,
+                        &text,
+                        &headers,
                     )
+                    .await;
+                if let Err(error) = &outcome {
+                    failed_deliveries += 1;
+                    tracing::error!(
+                      error.cause_chain = ?error,
+                      "Failed to send newsletter issue to a confirmed subscriber"
+                    );
+                }
+
+                log_delivery(pool, issue_id, &valid_subscriber, &outcome)
                     .await
-                    .with_context(|| {
-                        format!(
-                            "Failed to send newsletter issue to {}",
-                            valid_subscriber.email
-                        )
-                    })?;
+                    .context("Failed to log newsletter issue delivery")?;
             }
 
             Err(error) => {
@@ -116,7 +308,14 @@ pub async fn publish_newsletter(
         }
     }
 
-    Ok(HttpResponse::Ok().finish())
+    if failed_deliveries > 0 {
+        warnings.push(format!(
+            "Failed to deliver the issue to {} subscriber(s)",
+            failed_deliveries
+        ));
+    }
+
+    Ok(PublishResponse { issue_id, warnings })
 }
 
 #[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
@@ -125,7 +324,7 @@ async fn get_confirmed_subscribers(
 ) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
     let rows = sqlx::query!(
         r#"
-          SELECT email
+          SELECT id, email, name, attributes
           FROM subscriptions
           WHERE status = 'confirmed'
         "#
@@ -136,7 +335,12 @@ async fn get_confirmed_subscribers(
     let confirmed_subscribers = rows
         .into_iter()
         .map(|r| match SubscriberEmail::parse(r.email) {
-            Ok(email) => Ok(ConfirmedSubscriber { email }),
+            Ok(email) => Ok(ConfirmedSubscriber {
+                id: r.id,
+                email,
+                name: r.name,
+                attributes: r.attributes,
+            }),
             Err(error) => Err(anyhow::anyhow!(error)),
         })
         .collect();
@@ -144,42 +348,141 @@ async fn get_confirmed_subscribers(
     Ok(confirmed_subscribers)
 }
 
-#[tracing::instrument(
-    name = "Basic authorisation: extract username and password",
-    skip(headers)
-)]
-fn basic_authorisation(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
-    let header_value = headers
-        .get("Authorization")
-        .context("The 'Authorization' header was missing")?
-        .to_str()
-        .context("The 'Authorization' header was not a valid UTF8 string")?;
-
-    let base64encoded_segment = header_value
-        .strip_prefix("Basic ")
-        .context("The authorization scheme was not 'Basic '")?;
-
-    let decoded_bytes = base64::engine::general_purpose::STANDARD
-        .decode(base64encoded_segment)
-        .context("Failed to base64-decode 'Basic' credentials")?;
-
-    let decoded_credentials = String::from_utf8(decoded_bytes)
-        .context("The decoded credential string is not valid UTF8")?;
-
-    let mut credentials = decoded_credentials.splitn(2, ':');
-
-    let username = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth"))?
-        .to_string();
-
-    let password = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth"))?
-        .to_string();
-
-    Ok(Credentials {
-        username,
-        password: Secret::new(password),
-    })
+#[tracing::instrument(name = "Log newsletter issue delivery", skip_all)]
+async fn log_delivery(
+    pool: &PgPool,
+    issue_id: Uuid,
+    subscriber: &ConfirmedSubscriber,
+    outcome: &Result<Option<String>, reqwest::Error>,
+) -> Result<(), sqlx::Error> {
+    let (status, message_id, error) = match outcome {
+        Ok(message_id) => ("delivered", message_id.clone(), None),
+        Err(error) => ("failed", None, Some(error.to_string())),
+    };
+
+    sqlx::query!(
+        r#"
+          INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_id,
+            subscriber_email,
+            status,
+            message_id,
+            error,
+            sent_at
+          )
+          VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        issue_id,
+        subscriber.id,
+        subscriber.email.as_ref(),
+        status,
+        message_id,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+fn click_tracking_link(
+    app_base_url: &reqwest::Url,
+    token: &ClickToken,
+    hmac_secret: &Secret<String>,
+) -> String {
+    format!("{}t/c/{}", app_base_url.as_str(), token.encode(hmac_secret))
+}
+
+fn open_tracking_link(
+    app_base_url: &reqwest::Url,
+    token: &TrackingToken,
+    hmac_secret: &Secret<String>,
+) -> String {
+    format!(
+        "{}t/o/{}.gif",
+        app_base_url.as_str(),
+        token.encode(hmac_secret)
+    )
+}
+
+///
+/// Append the tracking pixel as the last element of the body, so it is only fetched once the
+/// rest of the issue has loaded
+///
+fn with_tracking_pixel(html: &str, pixel_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display: none;">"#,
+        pixel_url
+    );
+
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], pixel, &html[index..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+#[tracing::instrument(name = "Store newsletter issue", skip_all)]
+async fn insert_newsletter_issue(
+    pool: &PgPool,
+    issue: &NewsletterIssue<'_>,
+    links: &TrackedLinks,
+    actor: Actor,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+          INSERT INTO newsletter_issues (
+            id,
+            title,
+            text_content,
+            html_content,
+            track_opens,
+            track_clicks,
+            published_at
+          )
+          VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        newsletter_issue_id,
+        issue.title,
+        issue.text_content,
+        issue.html_content,
+        issue.track_opens,
+        issue.track_clicks
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    for (link_id, url) in links.urls().iter().enumerate() {
+        sqlx::query!(
+            r#"
+              INSERT INTO issue_links (newsletter_issue_id, link_id, url)
+              VALUES ($1, $2, $3)
+            "#,
+            newsletter_issue_id,
+            link_id as i32,
+            url
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+
+    // Written with the issue, so an audit failure can't follow a send that already happened
+    record_audit_event(
+        &mut transaction,
+        actor,
+        AuditAction::IssuePublished,
+        Some(&newsletter_issue_id.to_string()),
+        diff(
+            &serde_json::Value::Null,
+            &serde_json::json!({ "title": issue.title }),
+        ),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(newsletter_issue_id)
 }
@@ -0,0 +1,62 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    bootstrap::users_exist,
+    bootstrap::SetupToken,
+    helpers::{e500, flash_messages, render},
+};
+
+#[derive(serde::Deserialize)]
+pub struct SetupQuery {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "setup.html")]
+struct SetupPage<'a> {
+    flash_messages: Vec<String>,
+    token: &'a str,
+}
+
+///
+/// First-run form for creating the owner account, only reachable with the setup token logged at
+/// startup and only until the first user exists
+///
+#[tracing::instrument(name = "/GET Setup form handler", skip_all)]
+pub async fn setup_form(
+    query: web::Query<SetupQuery>,
+    setup_token: web::Data<SetupToken>,
+    pool: web::Data<PgPool>,
+    incoming: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !setup_token.matches(&query.token) || users_exist(&pool).await.map_err(e500)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    render(&SetupPage {
+        flash_messages: flash_messages(&incoming),
+        token: &query.token,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SetupPage;
+    use askama::Template;
+
+    #[test]
+    fn flash_messages_and_the_token_are_escaped() {
+        let page = SetupPage {
+            flash_messages: vec!["<script>alert(1)</script>".to_string()],
+            token: r#""><script>"#,
+        };
+
+        let html = page.render().unwrap();
+
+        assert!(html.contains("<p><i>&lt;script&gt;alert(1)&lt;/script&gt;</i></p>"));
+        assert!(!html.contains(r#""><script>"#));
+    }
+}
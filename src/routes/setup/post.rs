@@ -0,0 +1,131 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::compute_password_hash,
+    bootstrap::{users_exist, SetupToken},
+    helpers::{e500, see_other},
+    telemetry::spawn_blocking_with_tracing,
+};
+
+/// Serialises owner creation across every instance sharing the database, each has its own token
+const SETUP_LOCK_KEY: i64 = 0x7365_7475_7000;
+
+#[derive(serde::Deserialize)]
+pub struct SetupForm {
+    token: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Process setup POST/",
+    skip(form, setup_token, pool),
+    fields(username = %form.username)
+)]
+pub async fn setup(
+    form: web::Form<SetupForm>,
+    setup_token: web::Data<SetupToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let SetupForm {
+        token,
+        username,
+        password,
+        password_check,
+    } = form.0;
+
+    if !setup_token.matches(&token) || users_exist(&pool).await.map_err(e500)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let retry = format!("/setup?token={}", token);
+    let username = username.trim();
+    let length = password.expose_secret().chars().count();
+
+    if username.is_empty() {
+        FlashMessage::error("A username is required").send();
+        return Ok(see_other(&retry));
+    }
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&retry));
+    }
+    if !(12..=128).contains(&length) {
+        FlashMessage::error("Passwords must be between 12 and 128 characters long").send();
+        return Ok(see_other(&retry));
+    }
+
+    // Only one request gets to use the token, a concurrent one finds it gone
+    let Some(taken) = setup_token.take(&token) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    match create_owner(username, password, &pool).await {
+        Ok(true) => {}
+        // Another instance got there first, the token stays used up
+        Ok(false) => return Ok(HttpResponse::NotFound().finish()),
+        Err(e) => {
+            setup_token.restore(taken);
+            return Err(e500(e));
+        }
+    }
+
+    tracing::info!("Created the owner account, setup is complete");
+    FlashMessage::info("Your account has been created, you can now log in").send();
+    Ok(see_other("/login"))
+}
+
+///
+/// Create the owner account, unless any user exists by now. Returns whether it was created.
+///
+#[tracing::instrument(name = "Create owner account", skip(password, pool))]
+async fn create_owner(
+    username: &str,
+    password: Secret<String>,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await?
+        .context("Failed to hash password")?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // Without the lock, two instances could both see no users and both insert
+    sqlx::query!("SELECT pg_advisory_xact_lock($1)", SETUP_LOCK_KEY)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to lock the setup")?;
+
+    let created = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        SELECT $1, $2, $3
+        WHERE NOT EXISTS (SELECT 1 FROM users)
+      "#,
+        Uuid::new_v4(),
+        username,
+        password_hash.expose_secret()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to create the owner account")?
+    .rows_affected()
+        == 1;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the owner account")?;
+
+    Ok(created)
+}
@@ -1,39 +1,133 @@
-use actix_web::{web, HttpResponse, ResponseError};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use actix_web::{
+    error::InternalError,
+    http::header::{self, ContentType},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
 use anyhow::Context;
+use askama::Template;
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use reqwest::{StatusCode, Url};
+use sha2::{Digest, Sha256};
 use sqlx::{query, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    configuration::SignupSettings,
+    domain::{
+        verify_proof_of_work, EmailPolicy, NewSubscriber, SignupFormToken, SubscriberEmail,
+        SubscriberName,
+    },
     email_client::EmailClient,
-    helpers::error_chain_fmt,
-    startup::ApplicationBaseUrl,
+    helpers::{error_chain_fmt, html_message_page, ErrorBody, ResponseFormat, TrustedProxies},
+    metrics,
+    rate_limit::{RateLimit, RateLimiter},
+    startup::{ApplicationBaseUrl, HmacSecret},
+    webhook_events::{enqueue_webhook_event, WebhookEvent},
 };
 
+/// Signup forms left open for longer than this have to be reloaded
+const FORM_TOKEN_MAX_AGE_SECONDS: i64 = 24 * 60 * 60;
+
+/// Longest `source` accepted from the signup form
+const MAX_SOURCE_LENGTH: usize = 64;
+
+/// Longest user agent kept with a consent record, anything past it is cut off
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+///
+/// What a subscriber agreed to on the signup form, and where from
+///
+#[derive(Debug)]
+pub struct Consent<'a> {
+    pub text: &'a str,
+    pub version: &'a str,
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+///
+/// The signup form, posted either url-encoded or as JSON. Missing fields are treated as empty so
+/// they are reported alongside any other invalid field.
+///
 #[derive(serde::Deserialize, Debug)]
 pub struct FormData {
+    #[serde(default)]
     pub email: String,
+    #[serde(default)]
     pub name: String,
+    /// Honeypot, hidden from people on the signup form so only bots fill it in
+    #[serde(default)]
+    pub website: String,
+    #[serde(default)]
+    pub form_token: Option<String>,
+    #[serde(default)]
+    pub proof_of_work: Option<String>,
+    /// `signup.consent_version` when the form was rendered
+    #[serde(default)]
+    pub consent_version: Option<String>,
+    /// Which form the signup came from, e.g. `home`, recorded with the consent
+    #[serde(default)]
+    pub source: Option<String>,
+    /// The email's domain, sent back once the subscriber has confirmed a suspected typo is right
+    #[serde(default)]
+    pub confirmed_domain: Option<String>,
+}
+
+///
+/// Asks the subscriber whether a suspected typo is what they meant. Both choices post the form
+/// again, with the same token, so they don't have to fill it in twice.
+///
+#[derive(Template, Debug)]
+#[template(path = "confirm_email.html")]
+pub struct ConfirmEmailPage {
+    email: String,
+    suggestion: String,
+    domain: String,
+    /// Everything else from the original submission
+    hidden_fields: Vec<(&'static str, String)>,
 }
 
+/// Validation problems, keyed by the name of the field they concern
+pub type FieldErrors = BTreeMap<&'static str, String>;
+
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = FieldErrors;
 
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(NewSubscriber { email, name })
+        match (
+            SubscriberName::parse(value.name),
+            SubscriberEmail::parse(value.email),
+        ) {
+            (Ok(name), Ok(email)) => Ok(NewSubscriber { email, name }),
+            (name, email) => Err([("name", name.err()), ("email", email.err())]
+                .into_iter()
+                .filter_map(|(field, error)| Some((field, error?)))
+                .collect()),
+        }
     }
 }
 
 #[derive(thiserror::Error)]
 pub enum SubscriberError {
+    #[error("{}", .0.values().cloned().collect::<Vec<_>>().join(" "))]
+    InvalidFields(FieldErrors),
+
+    /// The submission as a whole was rejected, rather than any one field
     #[error("{0}")]
     ValidationError(String),
 
+    #[error("Too many signup attempts, please try again later")]
+    RateLimited { retry_after: Duration },
+
+    /// The domain looks like a typo, the subscriber can correct it or confirm it is right
+    #[error("{} looks like a typo, did you mean {}?", .0.email, .0.suggestion)]
+    PossibleTypo(Box<ConfirmEmailPage>),
+
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -43,12 +137,84 @@ pub struct StoreTokenError(sqlx::Error);
 impl ResponseError for SubscriberError {
     fn status_code(&self) -> reqwest::StatusCode {
         match self {
-            SubscriberError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscriberError::InvalidFields(_)
+            | SubscriberError::ValidationError(_)
+            | SubscriberError::PossibleTypo(_) => StatusCode::BAD_REQUEST,
+            SubscriberError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
             SubscriberError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+impl SubscriberError {
+    fn code(&self) -> &'static str {
+        match self {
+            SubscriberError::InvalidFields(_) => "invalid_fields",
+            SubscriberError::ValidationError(_) => "invalid_submission",
+            SubscriberError::RateLimited { .. } => "rate_limited",
+            SubscriberError::PossibleTypo(_) => "possible_typo",
+            SubscriberError::UnexpectedError(_) => "internal_error",
+        }
+    }
+
+    ///
+    /// Render the error for the signup widget (JSON) or the form on the home page (HTML)
+    ///
+    fn render(&self, format: ResponseFormat) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+
+        let retry_after_seconds = match self {
+            // Retry-After is in whole seconds, round up so clients never retry too early
+            SubscriberError::RateLimited { retry_after } => {
+                let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                Some(seconds.max(1))
+            }
+            _ => None,
+        };
+        if let Some(seconds) = retry_after_seconds {
+            response.insert_header((header::RETRY_AFTER, seconds));
+        }
+
+        // The cause is logged, there's nothing in it for the subscriber
+        let message = match self {
+            SubscriberError::UnexpectedError(_) => {
+                "Something went wrong, please try again later".to_string()
+            }
+            e => e.to_string(),
+        };
+
+        // Reported against the email field, so widgets show it next to the address
+        let typo_fields = match self {
+            SubscriberError::PossibleTypo(_) => {
+                Some(FieldErrors::from([("email", message.clone())]))
+            }
+            _ => None,
+        };
+
+        match format {
+            ResponseFormat::Json => response.json(ErrorBody {
+                error: self.code(),
+                fields: match self {
+                    SubscriberError::InvalidFields(fields) => Some(fields),
+                    _ => typo_fields.as_ref(),
+                },
+                message,
+                retry_after_seconds,
+            }),
+            ResponseFormat::Html => {
+                let html = match self {
+                    SubscriberError::PossibleTypo(page) => page.render().unwrap_or_else(|e| {
+                        tracing::error!(error.cause_chain = ?e, "Failed to render the typo check");
+                        html_message_page("We couldn't subscribe you", &message)
+                    }),
+                    _ => html_message_page("We couldn't subscribe you", &message),
+                };
+                response.content_type(ContentType::html()).body(html)
+            }
+        }
+    }
+}
+
 impl std::fmt::Debug for SubscriberError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -78,55 +244,367 @@ impl std::fmt::Display for StoreTokenError {
 
 #[tracing::instrument(
   name = "Adding a new subscriber",
-  skip(form, db_pool, email_client, app_base_url),
+  skip(
+      req,
+      body,
+      db_pool,
+      email_client,
+      app_base_url,
+      rate_limiter,
+      signup,
+      hmac_secret,
+      email_policy,
+      trusted_proxies
+  ),
   fields(
-      subscriber_email = %form.email,
-      subscriber_name = %form.name
+      subscriber_email = tracing::field::Empty,
+      subscriber_name = tracing::field::Empty
   )
 )]
 ///
 /// Subscribe method which will take in POST'ed request body, extract user's email and name, save to db, and return a 200 back to client
 ///
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    req: HttpRequest,
+    body: web::Either<web::Json<FormData>, web::Form<FormData>>,
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     app_base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscriberError> {
-    let new_subscriber: NewSubscriber = form
-        .0
-        .try_into()
-        .map_err(SubscriberError::ValidationError)?;
-
-    let mut transaction = db_pool
-        .begin()
-        .await
-        .context("Failed to acquire a Postgres connection from the pool")?;
+    rate_limiter: web::Data<RateLimiter>,
+    signup: web::Data<SignupSettings>,
+    hmac_secret: web::Data<HmacSecret>,
+    email_policy: web::Data<EmailPolicy>,
+    trusted_proxies: web::Data<TrustedProxies>,
+) -> Result<HttpResponse, InternalError<SubscriberError>> {
+    // Answer in whatever the client sent, unless it asks for something else
+    let format = ResponseFormat::negotiate(&req, ResponseFormat::of_request_body(&req));
+    let (mut form, default_source) = match body {
+        web::Either::Left(json) => (json.into_inner(), "json"),
+        web::Either::Right(form) => (form.into_inner(), "form"),
+    };
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
-        .await
-        .context("Failed to insert new subscriber in the database")?;
+    let span = tracing::Span::current();
+    span.record("subscriber_email", tracing::field::display(&form.email));
+    span.record("subscriber_name", tracing::field::display(&form.name));
+
+    let outcome: Result<(), SubscriberError> = async {
+        // Every attempt counts, so a script can't probe the checks below for free
+        let client_ip = trusted_proxies.client_ip(&req).map(|ip| ip.to_string());
+        match client_ip.as_deref() {
+            Some(ip) => {
+                enforce_rate_limit(
+                    &rate_limiter,
+                    &format!("ip:{}", ip),
+                    signup.per_ip_limit,
+                    signup.per_ip_window(),
+                    "ip_rate_limit",
+                )
+                .await?
+            }
+            // Sharing one bucket between every such request would let one client lock all the
+            // others out, so only the per email limit applies
+            None => tracing::warn!(
+                "Could not tell the client's address, skipping the per IP signup rate limit"
+            ),
+        }
+
+        if !form.website.is_empty() {
+            // Look like a success, so the bot has nothing to learn from
+            tracing::info!("Ignoring a signup which filled in the honeypot field");
+            metrics::record_signup_rejected("honeypot");
+            return Ok(());
+        }
+
+        let form_token = if signup.requires_form_token() {
+            let token = check_form_token(&form, &signup, &hmac_secret.0).map_err(|e| {
+                metrics::record_signup_rejected("form_token");
+                SubscriberError::ValidationError(e)
+            })?;
+            Some(token)
+        } else {
+            None
+        };
+
+        let consent = Consent {
+            text: &signup.consent_text,
+            version: check_consent_version(&form, &signup)?,
+            source: parse_source(form.source.take(), default_source)
+                .map_err(|e| SubscriberError::InvalidFields(FieldErrors::from([("source", e)])))?,
+            ip_address: client_ip,
+            user_agent: req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+        };
+
+        let confirmed_domain = form.confirmed_domain.take();
+        let hidden_fields = [
+            ("name", Some(form.name.clone())),
+            ("form_token", form.form_token.clone()),
+            ("proof_of_work", form.proof_of_work.clone()),
+            ("consent_version", form.consent_version.clone()),
+            ("source", Some(consent.source.clone())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect();
+
+        let new_subscriber: NewSubscriber =
+            form.try_into().map_err(SubscriberError::InvalidFields)?;
+        email_policy
+            .check(&new_subscriber.email)
+            .map_err(|e| SubscriberError::InvalidFields(FieldErrors::from([("email", e)])))?;
+
+        if let Some(suggestion) = email_policy.suggest_correction(&new_subscriber.email) {
+            let (_, domain) = new_subscriber.email.as_ref().rsplit_once('@').unwrap();
+            if !matches!(confirmed_domain.as_deref(), Some(confirmed) if confirmed.eq_ignore_ascii_case(domain))
+            {
+                return Err(SubscriberError::PossibleTypo(Box::new(ConfirmEmailPage {
+                    email: new_subscriber.email.as_ref().to_string(),
+                    suggestion,
+                    domain: domain.to_string(),
+                    hidden_fields,
+                })));
+            }
+        }
+
+        let normalised_email = email_policy.normalise(&new_subscriber.email);
+
+        // Claimed only once the address passed, so a typo can be corrected and the form resent
+        if let Some(token) = form_token {
+            claim_form_token(&rate_limiter, &token).await?;
+        }
+
+        // Only counted once the address is valid, and keyed on a hash so Redis never holds addresses
+        let email_digest = Sha256::digest(&normalised_email);
+        enforce_rate_limit(
+            &rate_limiter,
+            &format!("email:{:x}", email_digest),
+            signup.per_email_limit,
+            signup.per_email_window(),
+            "email_rate_limit",
+        )
+        .await?;
+
+        let mut transaction = db_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+        let subscriber_id =
+            match get_subscriber_by_normalised_email(&mut transaction, &normalised_email)
+                .await
+                .context("Failed to look up an existing subscriber")?
+            {
+                // Answer exactly as for a new signup, so the form can't be used to find out who is
+                // subscribed
+                Some((_, status)) if status == "confirmed" => {
+                    return Ok(());
+                }
+                // Still pending, they most likely lost the first confirmation email
+                Some((subscriber_id, _)) => subscriber_id,
+                None => {
+                    let subscriber_id =
+                        insert_subscriber(&mut transaction, &new_subscriber, &normalised_email)
+                            .await
+                            .context("Failed to insert new subscriber in the database")?;
+                    enqueue_webhook_event(
+                        &mut transaction,
+                        WebhookEvent::Subscribed,
+                        subscriber_id,
+                    )
+                    .await
+                    .context("Failed to queue webhooks for the new subscriber")?;
+                    subscriber_id
+                }
+            };
+
+        let subscription_token = generate_subscription_token();
+        store_token(&mut transaction, subscriber_id, &subscription_token)
+            .await
+            .context("Failed to store the confirmation token for a new subscriber")?;
+        record_consent(&mut transaction, subscriber_id, &consent)
+            .await
+            .context("Failed to record the subscriber's consent")?;
+
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store a new subscriber")?;
+
+        send_confirmation_email(
+            &email_client,
+            &new_subscriber,
+            &app_base_url.0,
+            &subscription_token,
+        )
         .await
-        .context("Failed to store the confirmation token for a new subscriber")?;
+        .context("Failed to send a confirmation email")?;
+
+        Ok(())
+    }
+    .await;
+
+    match outcome {
+        Ok(()) => Ok(subscribed_response(format)),
+        Err(e) => {
+            let response = e.render(format);
+            Err(InternalError::from_response(e, response))
+        }
+    }
+}
+
+///
+/// The same answer is given for new, pending and already confirmed subscribers
+///
+fn subscribed_response(format: ResponseFormat) -> HttpResponse {
+    const MESSAGE: &str =
+        "Thanks for subscribing! Check your inbox for an email to confirm your subscription.";
+
+    match format {
+        ResponseFormat::Json => HttpResponse::Ok().json(serde_json::json!({
+          "status": "pending_confirmation",
+          "message": MESSAGE,
+        })),
+        ResponseFormat::Html => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(html_message_page("Thanks for subscribing", MESSAGE)),
+    }
+}
 
-    transaction
-        .commit()
+///
+/// Count the attempt against `key`, letting it through if Redis can't be reached rather than
+/// turning every signup away
+///
+async fn enforce_rate_limit(
+    rate_limiter: &RateLimiter,
+    key: &str,
+    limit: u32,
+    window: Duration,
+    reason: &str,
+) -> Result<(), SubscriberError> {
+    match rate_limiter.hit(key, limit, window).await {
+        Ok(RateLimit::Allowed) => Ok(()),
+        Ok(RateLimit::Exceeded { retry_after }) => {
+            metrics::record_signup_rejected(reason);
+            Err(SubscriberError::RateLimited { retry_after })
+        }
+        Err(error) => {
+            tracing::error!(
+              error.cause_chain = ?error,
+              "Failed to check the signup rate limit, letting the request through"
+            );
+            Ok(())
+        }
+    }
+}
+
+///
+/// Use up the form token, so one solved form can't be replayed for many signups. Like the rate
+/// limits, the submission is let through if Redis can't be reached.
+///
+async fn claim_form_token(
+    rate_limiter: &RateLimiter,
+    token: &SignupFormToken,
+) -> Result<(), SubscriberError> {
+    // Past its maximum age the token is rejected anyway, so the claim needn't outlive it
+    let remaining = token.issued_at + FORM_TOKEN_MAX_AGE_SECONDS - Utc::now().timestamp();
+    let ttl = Duration::from_secs(remaining.max(1) as u64);
+
+    match rate_limiter
+        .claim(&format!("form_token:{}", token.id()), ttl)
         .await
-        .context("Failed to commit SQL transaction to store a new subscriber")?;
+    {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            metrics::record_signup_rejected("form_token");
+            Err(SubscriberError::ValidationError(
+                "The form has already been submitted, please reload the page and try again".into(),
+            ))
+        }
+        Err(error) => {
+            tracing::error!(
+              error.cause_chain = ?error,
+              "Failed to claim the signup form token, letting the request through"
+            );
+            Ok(())
+        }
+    }
+}
 
-    send_confirmation_email(
-        &email_client,
-        &new_subscriber,
-        &app_base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email")?;
+///
+/// Check the submission came from a signup form we rendered, that it took a person's amount of
+/// time to fill in, and that the proof of work was solved when one is required
+///
+fn check_form_token(
+    form: &FormData,
+    signup: &SignupSettings,
+    hmac_secret: &secrecy::Secret<String>,
+) -> Result<SignupFormToken, String> {
+    let encoded = form
+        .form_token
+        .as_deref()
+        .ok_or("Please subscribe using the form on our website")?;
+    let token = SignupFormToken::decode(encoded, hmac_secret)?;
+
+    let age = Utc::now().timestamp() - token.issued_at;
+    if age > FORM_TOKEN_MAX_AGE_SECONDS {
+        return Err("The form has expired, please reload the page and try again".into());
+    }
+    if age < signup.min_fill_seconds as i64 {
+        return Err("The form was submitted too quickly, please try again".into());
+    }
+
+    let solution = form.proof_of_work.as_deref().unwrap_or_default();
+    if !verify_proof_of_work(encoded, solution, signup.proof_of_work_difficulty) {
+        return Err("The proof of work is missing or incorrect".into());
+    }
+
+    Ok(token)
+}
+
+///
+/// The consent version the form was rendered with has to be the current one, otherwise the
+/// subscriber agreed to wording we no longer have. JSON clients which don't say are taken to have
+/// agreed to the current wording.
+///
+fn check_consent_version<'a>(
+    form: &FormData,
+    signup: &'a SignupSettings,
+) -> Result<&'a str, SubscriberError> {
+    match form.consent_version.as_deref() {
+        Some(version) if version != signup.consent_version => {
+            metrics::record_signup_rejected("consent_version");
+            Err(SubscriberError::ValidationError(
+                "The signup form has changed, please reload the page and try again".into(),
+            ))
+        }
+        _ => Ok(&signup.consent_version),
+    }
+}
+
+///
+/// Sources are labels like `home` or `footer`, so anything else is turned away rather than stored
+///
+fn parse_source(source: Option<String>, default: &str) -> Result<String, String> {
+    let Some(source) = source.filter(|s| !s.is_empty()) else {
+        return Ok(default.to_string());
+    };
+
+    if source.len() > MAX_SOURCE_LENGTH
+        || !source
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "The source must be up to {} letters, digits, dashes or underscores.",
+            MAX_SOURCE_LENGTH
+        ));
+    }
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(source)
 }
 
 #[tracing::instrument(
@@ -162,29 +640,59 @@ Click <a href=\"{}\">here</a> to confirm your subscription.",
 
     email_client
         .send_email(&new_subscriber.email, "Welcome!", &plain_body, &html_body)
-        .await
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Get subscriber by normalised email",
+    skip(transaction, normalised_email)
+)]
+///
+/// Find the subscriber already signed up for this mailbox, with their status
+///
+pub async fn get_subscriber_by_normalised_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    normalised_email: &str,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let row = query!(
+        r#"
+SELECT id, status
+FROM subscriptions
+WHERE normalised_email = $1
+FOR UPDATE
+"#,
+        normalised_email
+    )
+    .fetch_optional(transaction)
+    .await?;
+
+    Ok(row.map(|r| (r.id, r.status)))
 }
 
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(new_subscriber, transaction)
+    skip(new_subscriber, transaction, normalised_email)
 )]
 ///
 /// Runs the SQL query to insert the form data into the db, will bubble the error up to caller to handle
 ///
-async fn insert_subscriber(
+pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
+    normalised_email: &str,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
 
     query!(
         r#"
-INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-VALUES ($1, $2, $3, $4, 'pending_confirmation')
+INSERT INTO subscriptions (id, email, normalised_email, name, subscribed_at, status)
+VALUES ($1, $2, $3, $4, $5, 'pending_confirmation')
 "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        normalised_email,
         new_subscriber.name.as_ref(),
         Utc::now()
     )
@@ -221,7 +729,37 @@ pub async fn store_token(
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
+#[tracing::instrument(name = "Record consent", skip(transaction, consent))]
+///
+/// Keep proof of what the subscriber agreed to. The row can't be changed afterwards, other than
+/// `confirm_subscriber` stamping when they confirmed.
+///
+pub async fn record_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    consent: &Consent<'_>,
+) -> Result<(), sqlx::Error> {
+    query!(
+        r#"
+  INSERT INTO subscription_consents
+    (id, subscriber_id, consent_text, consent_version, source, ip_address, user_agent, given_at)
+  VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+      "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        consent.text,
+        consent.version,
+        consent.source,
+        consent.ip_address,
+        consent.user_agent
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
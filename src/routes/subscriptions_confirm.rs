@@ -1,10 +1,13 @@
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{
+    error::InternalError, http::header::ContentType, web, HttpRequest, HttpResponse, ResponseError,
+};
 use anyhow::Context;
 use reqwest::StatusCode;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::helpers::error_chain_fmt;
+use crate::helpers::{error_chain_fmt, html_message_page, ErrorBody, ResponseFormat};
+use crate::webhook_events::{enqueue_webhook_event, WebhookEvent};
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
@@ -29,30 +32,97 @@ impl ResponseError for SubscribeConfirmError {
     }
 }
 
+impl SubscribeConfirmError {
+    ///
+    /// Render the error for the signup widget (JSON) or someone following the link (HTML)
+    ///
+    fn render(&self, format: ResponseFormat) -> HttpResponse {
+        let (code, message) = match self {
+            SubscribeConfirmError::UnknownToken => ("unknown_token", self.to_string()),
+            // The cause is logged, there's nothing in it for the subscriber
+            SubscribeConfirmError::UnexpectedError(_) => (
+                "internal_error",
+                "Something went wrong, please try again later".to_string(),
+            ),
+        };
+
+        let mut response = HttpResponse::build(self.status_code());
+        match format {
+            ResponseFormat::Json => response.json(ErrorBody {
+                error: code,
+                message,
+                fields: None,
+                retry_after_seconds: None,
+            }),
+            ResponseFormat::Html => {
+                response
+                    .content_type(ContentType::html())
+                    .body(html_message_page(
+                        "We couldn't confirm your subscription",
+                        &message,
+                    ))
+            }
+        }
+    }
+}
+
 impl std::fmt::Debug for SubscribeConfirmError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
     }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(_parameters))]
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(req, _parameters, db_pool))]
 ///
-/// Handler to confirm subscriber in db
+/// Handler to confirm subscriber in db.
+/// Confirmation links are opened in a browser, so HTML is the default unless JSON is asked for.
 ///
 pub async fn confirm(
+    req: HttpRequest,
     _parameters: web::Query<Parameters>,
     db_pool: web::Data<PgPool>,
-) -> Result<HttpResponse, SubscribeConfirmError> {
-    let subscriber_id = get_subscriber_id_from_token(&db_pool, &_parameters.subscription_token)
-        .await
-        .context("Failed to get subscriber with supplied subscriber id")?
-        .ok_or(SubscribeConfirmError::UnknownToken)?;
+) -> Result<HttpResponse, InternalError<SubscribeConfirmError>> {
+    let format = ResponseFormat::negotiate(&req, ResponseFormat::Html);
+
+    let outcome: Result<(), SubscribeConfirmError> = async {
+        let subscriber_id = get_subscriber_id_from_token(&db_pool, &_parameters.subscription_token)
+            .await
+            .context("Failed to get subscriber with supplied subscriber id")?
+            .ok_or(SubscribeConfirmError::UnknownToken)?;
 
-    confirm_subscriber(&db_pool, subscriber_id)
-        .await
-        .context("Failed to confirm subscriber")?;
+        let mut transaction = db_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        confirm_subscriber(&mut transaction, subscriber_id)
+            .await
+            .context("Failed to confirm subscriber")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to confirm a subscriber")?;
+
+        Ok(())
+    }
+    .await;
 
-    Ok(HttpResponse::Ok().finish())
+    if let Err(e) = outcome {
+        let response = e.render(format);
+        return Err(InternalError::from_response(e, response));
+    }
+
+    const MESSAGE: &str = "Your subscription is confirmed, the next issue will be in your inbox.";
+    let response = match format {
+        ResponseFormat::Json => HttpResponse::Ok().json(serde_json::json!({
+          "status": "confirmed",
+          "message": MESSAGE,
+        })),
+        ResponseFormat::Html => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(html_message_page("Subscription confirmed", MESSAGE)),
+    };
+
+    Ok(response)
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(pool, subscription_token))]
@@ -76,21 +146,43 @@ pub async fn get_subscriber_id_from_token(
     Ok(result.map(|r| r.subscriber_id))
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool, subscriber_id))]
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(transaction, subscriber_id)
+)]
 ///
-/// Update the subscriber in the db and mark their status as 'confirmed'
+/// Update the subscriber in the db and mark their status as 'confirmed', stamping their consent
+/// records with the time. Following the link again changes nothing, so it isn't announced to
+/// webhooks again either.
 ///
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let result = sqlx::query!(
         r#"
     UPDATE subscriptions 
     SET status = 'confirmed'
-    WHERE id = $1
+    WHERE id = $1 AND status <> 'confirmed'
   "#,
         subscriber_id
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await?;
 
+    if result.rows_affected() > 0 {
+        sqlx::query!(
+            r#"
+      UPDATE subscription_consents
+      SET confirmed_at = now()
+      WHERE subscriber_id = $1 AND confirmed_at IS NULL
+    "#,
+            subscriber_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+        enqueue_webhook_event(transaction, WebhookEvent::Confirmed, subscriber_id).await?;
+    }
+
     Ok(())
 }
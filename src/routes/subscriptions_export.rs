@@ -0,0 +1,74 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::Utc;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+
+use crate::domain::DataExportToken;
+use crate::helpers::error_chain_fmt;
+use crate::personal_data::export_subscriber_data;
+use crate::startup::HmacSecret;
+
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ExportError {
+    #[error("The link is not valid")]
+    InvalidToken(String),
+
+    #[error("The link has expired, use the one in a more recent issue")]
+    ExpiredToken,
+
+    #[error("There is no data held for this subscriber")]
+    UnknownSubscriber,
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for ExportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ExportError::InvalidToken(_) | ExportError::ExpiredToken => StatusCode::UNAUTHORIZED,
+            ExportError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            ExportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[tracing::instrument(name = "Export a subscriber's data", skip_all)]
+///
+/// Handler behind the `{{ data_export_url }}` link, downloads everything held on the subscriber
+/// as JSON
+///
+pub async fn export_data(
+    parameters: web::Query<ExportParameters>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, ExportError> {
+    let token = DataExportToken::decode(&parameters.token, &hmac_secret.0)
+        .map_err(ExportError::InvalidToken)?;
+    if token.is_expired(Utc::now().timestamp()) {
+        return Err(ExportError::ExpiredToken);
+    }
+
+    let export = export_subscriber_data(&db_pool, token.subscriber_id)
+        .await?
+        .ok_or(ExportError::UnknownSubscriber)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscriber-data.json".into())],
+        })
+        .json(export))
+}
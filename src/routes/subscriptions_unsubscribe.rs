@@ -0,0 +1,174 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use askama::Template;
+use reqwest::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::UnsubscribeToken;
+use crate::helpers::{e500, error_chain_fmt, render};
+use crate::startup::HmacSecret;
+use crate::webhook_events::{enqueue_webhook_event, WebhookEvent};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link has no token")]
+    MissingToken,
+
+    #[error("The unsubscribe link is not valid")]
+    InvalidToken(String),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            UnsubscribeError::MissingToken => StatusCode::BAD_REQUEST,
+            UnsubscribeError::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe.html")]
+struct UnsubscribePage<'a> {
+    token: &'a str,
+    unsubscribed: bool,
+}
+
+#[tracing::instrument(name = "Unsubscribe form", skip_all)]
+///
+/// Handler behind the `{{ unsubscribe_url }}` link sent in every newsletter issue. Only asks for
+/// confirmation: link scanners and mail clients prefetch every link, so a GET must not unsubscribe.
+///
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = UnsubscribeToken::decode(&parameters.token, &hmac_secret.0)
+        .map_err(UnsubscribeError::InvalidToken)?;
+
+    let subscribed = is_subscribed(&db_pool, token.subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    render(&UnsubscribePage {
+        token: &parameters.token,
+        unsubscribed: !subscribed,
+    })
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip_all)]
+///
+/// Submitted by the form on the unsubscribe page, or directly by mail clients supporting RFC 8058
+/// one-click unsubscribe. Those POST `List-Unsubscribe=One-Click` to the link itself, so the token
+/// is in the query string rather than the body.
+///
+pub async fn unsubscribe(
+    query: Option<web::Query<UnsubscribeParameters>>,
+    form: Option<web::Form<UnsubscribeParameters>>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = query
+        .map(|q| q.into_inner().token)
+        .or_else(|| form.map(|f| f.into_inner().token))
+        .ok_or(UnsubscribeError::MissingToken)?;
+    let token =
+        UnsubscribeToken::decode(&token, &hmac_secret.0).map_err(UnsubscribeError::InvalidToken)?;
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(UnsubscribeError::UnexpectedError)?;
+    mark_subscriber_as_unsubscribed(&mut transaction, token.subscriber_id)
+        .await
+        .context("Failed to unsubscribe subscriber")
+        .map_err(UnsubscribeError::UnexpectedError)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber")
+        .map_err(UnsubscribeError::UnexpectedError)?;
+
+    render(&UnsubscribePage {
+        token: "",
+        unsubscribed: true,
+    })
+}
+
+///
+/// The link put in every issue, see [`unsubscribe_form`]
+///
+pub fn unsubscribe_link(
+    app_base_url: &reqwest::Url,
+    subscriber_id: Uuid,
+    hmac_secret: &secrecy::Secret<String>,
+) -> String {
+    format!(
+        "{}subscriptions/unsubscribe?token={}",
+        app_base_url.as_str(),
+        UnsubscribeToken::new(subscriber_id).encode(hmac_secret)
+    )
+}
+
+#[tracing::instrument(name = "Check subscription status", skip(pool))]
+async fn is_subscribed(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT EXISTS (
+      SELECT 1 FROM subscriptions WHERE id = $1 AND status <> 'unsubscribed'
+    ) AS "exists!"
+  "#,
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.exists)
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed",
+    skip(transaction, subscriber_id)
+)]
+///
+/// Update the subscriber in the db and mark their status as 'unsubscribed'
+///
+pub async fn mark_subscriber_as_unsubscribed(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+    UPDATE subscriptions
+    SET status = 'unsubscribed'
+    WHERE id = $1 AND status <> 'unsubscribed'
+  "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if result.rows_affected() > 0 {
+        enqueue_webhook_event(transaction, WebhookEvent::Unsubscribed, subscriber_id).await?;
+    }
+
+    Ok(())
+}
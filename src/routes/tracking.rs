@@ -0,0 +1,203 @@
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+
+use crate::configuration::TrackingSettings;
+use crate::domain::{is_machine_open, ClickToken, TrackingToken};
+use crate::helpers::error_chain_fmt;
+use crate::startup::HmacSecret;
+
+/// The smallest transparent 1x1 GIF
+const TRANSPARENT_GIF: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(thiserror::Error)]
+pub enum TrackingError {
+    #[error("The tracking token is invalid")]
+    InvalidToken(#[source] anyhow::Error),
+
+    #[error("The tracked link does not exist")]
+    UnknownLink,
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            TrackingError::InvalidToken(_) | TrackingError::UnknownLink => StatusCode::NOT_FOUND,
+            TrackingError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[tracing::instrument(
+    name = "Track a newsletter issue open",
+    skip(token, request, pool, hmac_secret, tracking)
+)]
+///
+/// Handler behind the tracking pixel embedded in issues published with `track_opens`.
+/// Always answers with a transparent GIF, opens are only recorded while tracking is enabled.
+///
+pub async fn track_open(
+    token: web::Path<String>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    tracking: web::Data<TrackingSettings>,
+) -> Result<HttpResponse, TrackingError> {
+    let token = TrackingToken::decode(&token, &hmac_secret.0)
+        .map_err(|e| TrackingError::InvalidToken(anyhow::anyhow!(e)))?;
+
+    if tracking.enabled {
+        let user_agent = request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|ua| ua.to_str().ok());
+
+        record_open(&pool, &token, is_machine_open(user_agent))
+            .await
+            .context("Failed to record newsletter issue open")?;
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::NoCache,
+            CacheDirective::MustRevalidate,
+            CacheDirective::Private,
+        ]))
+        .body(TRANSPARENT_GIF.to_vec()))
+}
+
+#[tracing::instrument(name = "Record newsletter issue open", skip(pool, token))]
+///
+/// Upsert the open counters for the recipient, ignoring issues published without open tracking
+///
+async fn record_open(
+    pool: &PgPool,
+    token: &TrackingToken,
+    is_machine_open: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+      INSERT INTO issue_opens (
+        newsletter_issue_id,
+        subscriber_id,
+        first_opened_at,
+        last_opened_at,
+        open_count,
+        machine_open_count
+      )
+      SELECT
+        i.id,
+        s.id,
+        CASE WHEN $3 THEN NULL ELSE now() END,
+        CASE WHEN $3 THEN NULL ELSE now() END,
+        CASE WHEN $3 THEN 0 ELSE 1 END,
+        CASE WHEN $3 THEN 1 ELSE 0 END
+      FROM newsletter_issues i, subscriptions s
+      WHERE i.id = $1 AND i.track_opens AND s.id = $2
+      ON CONFLICT (newsletter_issue_id, subscriber_id) DO UPDATE SET
+        first_opened_at = COALESCE(issue_opens.first_opened_at, EXCLUDED.first_opened_at),
+        last_opened_at = COALESCE(EXCLUDED.last_opened_at, issue_opens.last_opened_at),
+        open_count = issue_opens.open_count + EXCLUDED.open_count,
+        machine_open_count = issue_opens.machine_open_count + EXCLUDED.machine_open_count
+    "#,
+        token.issue_id,
+        token.subscriber_id,
+        is_machine_open
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Track a newsletter issue link click",
+    skip(token, pool, hmac_secret, tracking)
+)]
+///
+/// Handler behind the links rewritten in issues published with `track_clicks`.
+/// Only redirects to URLs stored against the issue, so it cannot be used as an open redirect.
+///
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    tracking: web::Data<TrackingSettings>,
+) -> Result<HttpResponse, TrackingError> {
+    let token = ClickToken::decode(&token, &hmac_secret.0)
+        .map_err(|e| TrackingError::InvalidToken(anyhow::anyhow!(e)))?;
+
+    let url = get_link_url(&pool, &token)
+        .await
+        .context("Failed to retrieve tracked link")?
+        .ok_or(TrackingError::UnknownLink)?;
+
+    if tracking.enabled {
+        record_click(&pool, &token)
+            .await
+            .context("Failed to record newsletter issue link click")?;
+    }
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::Private,
+        ]))
+        .finish())
+}
+
+#[tracing::instrument(name = "Get tracked link url", skip(pool, token))]
+async fn get_link_url(pool: &PgPool, token: &ClickToken) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+      SELECT url FROM issue_links
+      WHERE newsletter_issue_id = $1 AND link_id = $2
+    "#,
+        token.issue_id,
+        token.link_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.url))
+}
+
+#[tracing::instrument(name = "Record newsletter issue link click", skip(pool, token))]
+///
+/// Log the click, unless the subscriber has since been removed
+///
+async fn record_click(pool: &PgPool, token: &ClickToken) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+      INSERT INTO issue_clicks (newsletter_issue_id, link_id, subscriber_id, clicked_at)
+      SELECT $1, $2, id, now()
+      FROM subscriptions
+      WHERE id = $3
+    "#,
+        token.issue_id,
+        token.link_id,
+        token.subscriber_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
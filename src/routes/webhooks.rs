@@ -0,0 +1,171 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use reqwest::header::{self, HeaderValue};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+use crate::authentication::basic_authorisation;
+use crate::helpers::{constant_time_eq, error_chain_fmt};
+use crate::startup::PostmarkWebhookSecret;
+use crate::webhook_events::{enqueue_webhook_event, WebhookEvent};
+
+///
+/// The subset of a Postmark webhook payload we act on, see
+/// https://postmarkapp.com/developer/webhooks/bounce-webhook
+///
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    #[serde(rename = "Type")]
+    bounce_type: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            WebhookError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            WebhookError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+
+                response
+            }
+        }
+    }
+}
+
+enum DeliveryEvent {
+    Bounced,
+    Complained,
+}
+
+impl PostmarkEvent {
+    /// Bounce types which mean the address will never accept email
+    const PERMANENT_BOUNCE_TYPES: [&'static str; 2] = ["HardBounce", "BadEmailAddress"];
+
+    fn delivery_event(&self) -> Option<DeliveryEvent> {
+        match self.record_type.as_str() {
+            "Bounce"
+                if self
+                    .bounce_type
+                    .as_deref()
+                    .is_some_and(|t| Self::PERMANENT_BOUNCE_TYPES.contains(&t)) =>
+            {
+                Some(DeliveryEvent::Bounced)
+            }
+            "SpamComplaint" => Some(DeliveryEvent::Complained),
+            _ => None,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Handle Postmark webhook",
+    skip(event, pool, webhook_secret, request),
+    fields(record_type = %event.record_type, message_id = ?event.message_id)
+)]
+///
+/// Receives delivery events from Postmark. The webhook is configured with basic auth credentials
+/// in its URL, any username and `email_client.webhook_secret` as the password. Events we don't act on, or for emails we didn't log, are acknowledged and dropped
+/// so that Postmark does not keep retrying them.
+///
+pub async fn postmark_webhook(
+    event: web::Json<PostmarkEvent>,
+    pool: web::Data<PgPool>,
+    webhook_secret: web::Data<PostmarkWebhookSecret>,
+    request: HttpRequest,
+) -> Result<HttpResponse, WebhookError> {
+    let credentials = basic_authorisation(request.headers()).map_err(WebhookError::AuthError)?;
+
+    if !constant_time_eq(
+        webhook_secret.0.expose_secret(),
+        credentials.password.expose_secret(),
+    ) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook secret"
+        )));
+    }
+
+    if let (Some(delivery_event), Some(message_id)) =
+        (event.delivery_event(), event.message_id.as_deref())
+    {
+        record_delivery_event(&pool, message_id, delivery_event)
+            .await
+            .context("Failed to record delivery event")?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Record delivery event", skip(pool, delivery_event))]
+///
+/// Mark the delivery as bounced or complained about. The first permanent bounce for a subscriber
+/// is passed on to our own webhooks.
+///
+async fn record_delivery_event(
+    pool: &PgPool,
+    message_id: &str,
+    delivery_event: DeliveryEvent,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    match delivery_event {
+        DeliveryEvent::Bounced => {
+            let bounced = sqlx::query!(
+                r#"
+          UPDATE issue_delivery_log
+          SET bounced_at = now()
+          WHERE message_id = $1 AND bounced_at IS NULL
+          RETURNING subscriber_id
+        "#,
+                message_id
+            )
+            .fetch_all(&mut transaction)
+            .await?;
+
+            for row in bounced {
+                enqueue_webhook_event(&mut transaction, WebhookEvent::Bounced, row.subscriber_id)
+                    .await?;
+            }
+        }
+        DeliveryEvent::Complained => {
+            sqlx::query!(
+                r#"
+          UPDATE issue_delivery_log
+          SET complained_at = now()
+          WHERE message_id = $1 AND complained_at IS NULL
+        "#,
+                message_id
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+    }
+
+    transaction.commit().await
+}
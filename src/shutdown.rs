@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+///
+/// Broadcasts that the application has started shutting down. Readiness checks start failing as
+/// soon as it is triggered, and background workers should finish the job in hand and stop.
+///
+#[derive(Clone)]
+pub struct Shutdown(Arc<watch::Sender<bool>>);
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self(Arc::new(sender))
+    }
+
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    ///
+    /// Resolves once shutdown has been triggered, immediately if it already has
+    ///
+    pub async fn triggered(&self) {
+        let mut receiver = self.0.subscribe();
+        // The sender lives as long as `self`, so this can't fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Resolves on the first SIGINT (Ctrl-C) or SIGTERM
+///
+pub async fn wait_for_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => tracing::info!("Received SIGINT"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shutdown;
+
+    #[tokio::test]
+    async fn triggering_wakes_every_clone() {
+        // Arrange
+        let shutdown = Shutdown::new();
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.triggered().await }
+        });
+
+        // Act
+        shutdown.trigger();
+
+        // Assert
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiter)
+            .await
+            .expect("Waiter was not woken")
+            .unwrap();
+        assert!(shutdown.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn waiting_after_the_trigger_resolves_immediately() {
+        // Arrange
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+
+        // Act
+        let outcome =
+            tokio::time::timeout(std::time::Duration::from_millis(100), shutdown.triggered()).await;
+
+        // Assert
+        assert!(outcome.is_ok());
+    }
+}
@@ -1,84 +1,218 @@
 use crate::{
-    configuration::{DatabaseSettings, Settings},
+    audit_log::run_purge_until_stopped,
+    bootstrap::{bootstrap, normalise_stored_emails, SetupToken},
+    configuration::{AuditSettings, DatabaseSettings, Settings, ShutdownSettings, WebhookSettings},
+    csrf::CsrfProtection,
     email_client::EmailClient,
+    helpers::TrustedProxies,
+    metrics::{metrics, RequestMetrics},
+    rate_limit::RateLimiter,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, health_check, home, login,
-        login_form, logout, publish_newsletter, subscribe,
+        admin_audit_log, admin_dashboard, admin_subscriber, admin_subscribers, api_v1,
+        change_password, change_password_form, confirm, create_webhook_endpoint,
+        delete_webhook_endpoint, export_data, health_check, home, issue_clicks, issue_delivery,
+        liveness, login, login_form, logout, postmark_webhook, publish_newsletter, readiness,
+        set_webhook_endpoint_enabled, setup, setup_form, subscribe, track_click, track_open,
+        unsubscribe, unsubscribe_form, webhook_endpoint, webhook_endpoints,
     },
+    shutdown::{wait_for_signal, Shutdown},
+    webhook_delivery_worker::run_worker_until_stopped,
 };
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{
     cookie::Key,
-    dev::Server,
+    dev::{Server, ServerHandle},
     web::{self, Data},
     App, HttpServer,
 };
 use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
+use redis::aio::ConnectionManager;
 use reqwest::Url;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::net::TcpListener;
+use std::time::Duration;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
     port: u16,
     server: Server,
+    metrics_port: Option<u16>,
+    metrics_server: Option<Server>,
+    db_pool: PgPool,
+    shutdown: Shutdown,
+    shutdown_settings: ShutdownSettings,
+    webhook_settings: WebhookSettings,
+    audit_settings: AuditSettings,
+    setup_token: Option<String>,
 }
 
 impl Application {
     pub async fn build(config: Settings) -> Result<Self, anyhow::Error> {
-        let connection_pool = get_connection_pool(&config.database);
-
-        let app_base_url = config.application.base_url().expect("Invalid app base url");
-
-        let email_base_url = config
-            .email_client
-            .base_url_transformed()
-            .expect("Invalid email base url");
+        config.validate()?;
 
-        let sender_email = config
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-
-        let timeout = config.email_client.timeout();
+        let connection_pool = get_connection_pool(&config.database);
 
-        let email_client = EmailClient::new(
-            email_base_url,
-            sender_email,
-            config.email_client.authroisatation_token,
-            timeout,
-        );
+        let email_client = config.email_client.clone().client();
 
         let address = format!("{}:{}", config.application.host, config.application.port);
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
 
         println!("listening on {:?}", listener.local_addr().unwrap());
+
+        let app_base_url = config.application.base_url().map_err(anyhow::Error::msg)?;
+        let setup_token = bootstrap(&connection_pool, config.environment, &app_base_url).await?;
+        normalise_stored_emails(&connection_pool, config.email_policy.fold_provider_aliases)
+            .await?;
+
+        let setup_token_value = setup_token.expose();
+
+        let shutdown = Shutdown::new();
+        let shutdown_settings = config.shutdown.clone();
+        let webhook_settings = config.webhooks.clone();
+        let audit_settings = config.audit.clone();
+
+        let (metrics_port, metrics_server) = match config.application.metrics_port {
+            Some(metrics_port) => {
+                let address = format!("{}:{}", config.application.host, metrics_port);
+                let listener = TcpListener::bind(address)?;
+                let metrics_port = listener.local_addr().unwrap().port();
+                let server = run_metrics(listener, connection_pool.clone(), &shutdown_settings)?;
+
+                (Some(metrics_port), Some(server))
+            }
+            None => (None, None),
+        };
+
         let server = run(
             listener,
-            connection_pool,
+            connection_pool.clone(),
             email_client,
-            app_base_url,
-            config.application.hmac_secret,
-            config.redis_uri,
+            config,
+            shutdown.clone(),
+            setup_token,
         )
         .await?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            metrics_port,
+            metrics_server,
+            db_pool: connection_pool,
+            shutdown,
+            shutdown_settings,
+            webhook_settings,
+            audit_settings,
+            setup_token: setup_token_value,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    ///
+    /// The port `/metrics` is served on, when it is separate from the application port
+    ///
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics_port
+    }
+
+    ///
+    /// The token for `/setup`, when the application started without any users
+    ///
+    pub fn setup_token(&self) -> Option<String> {
+        self.setup_token.clone()
+    }
+
+    ///
+    /// Handle to begin a graceful shutdown without a signal, and for background workers to watch
+    ///
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    ///
+    /// Serve until SIGINT/SIGTERM or `Shutdown::trigger`. Readiness starts failing straight away,
+    /// we keep accepting requests for the drain delay, then stop accepting and give in-flight
+    /// requests the grace period to finish before closing the database pool.
+    /// The webhook delivery worker runs alongside, and finishes the delivery in hand on shutdown,
+    /// as does the audit log purge.
+    ///
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let webhook_worker = self.webhook_settings.worker_enabled.then(|| {
+            tokio::spawn(run_worker_until_stopped(
+                self.db_pool.clone(),
+                self.webhook_settings.clone(),
+                self.shutdown.clone(),
+            ))
+        });
+        let audit_purge = self.audit_settings.purge_enabled.then(|| {
+            tokio::spawn(run_purge_until_stopped(
+                self.db_pool.clone(),
+                self.audit_settings.clone(),
+                self.shutdown.clone(),
+            ))
+        });
+
+        let handles: Vec<ServerHandle> = std::iter::once(self.server.handle())
+            .chain(self.metrics_server.as_ref().map(Server::handle))
+            .collect();
+        let stopper = tokio::spawn(stop_on_shutdown(
+            self.shutdown.clone(),
+            self.shutdown_settings.drain_delay(),
+            handles,
+        ));
+
+        let outcome = match self.metrics_server {
+            Some(metrics_server) => tokio::try_join!(self.server, metrics_server).map(|_| ()),
+            None => self.server.await,
+        };
+
+        stopper.abort();
+        // The servers may also stop because of an error, make sure the worker hears about it
+        self.shutdown.trigger();
+        if let Some(webhook_worker) = webhook_worker {
+            match webhook_worker.await {
+                Ok(Err(error)) => tracing::error!(
+                  error.cause_chain = ?error,
+                  "The webhook delivery worker failed"
+                ),
+                Err(error) => {
+                    tracing::error!(error = %error, "The webhook delivery worker panicked")
+                }
+                Ok(Ok(())) => {}
+            }
+        }
+        if let Some(audit_purge) = audit_purge {
+            if let Err(error) = audit_purge.await {
+                tracing::error!(error = %error, "The audit log purge panicked")
+            }
+        }
+        self.db_pool.close().await;
+        tracing::info!("Shutdown complete");
+
+        outcome
     }
 }
 
+async fn stop_on_shutdown(shutdown: Shutdown, drain_delay: Duration, handles: Vec<ServerHandle>) {
+    tokio::select! {
+        _ = wait_for_signal() => shutdown.trigger(),
+        _ = shutdown.triggered() => {}
+    }
+
+    tracing::info!("Shutting down, draining for {:?}", drain_delay);
+    tokio::time::sleep(drain_delay).await;
+
+    futures_util::future::join_all(handles.iter().map(|handle| handle.stop(true))).await;
+}
+
 pub struct ApplicationBaseUrl(pub Url);
 pub struct HmacSecret(pub Secret<String>);
+pub struct PostmarkWebhookSecret(pub Secret<String>);
 
 ///
 /// Main app entry to web server.
@@ -88,14 +222,36 @@ pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: Url,
-    hmac_secret: Secret<String>,
-    redis_uri: Secret<String>,
+    config: Settings,
+    shutdown: Shutdown,
+    setup_token: SetupToken,
 ) -> Result<Server, anyhow::Error> {
+    let base_url = config.application.base_url().map_err(anyhow::Error::msg)?;
+    let hmac_secret = config.application.hmac_secret;
+    let webhook_secret = web::Data::new(PostmarkWebhookSecret(config.email_client.webhook_secret));
+    let trusted_proxies = web::Data::new(TrustedProxies(config.application.trusted_proxies));
+    let redis_uri = config.redis_uri;
+    // `/metrics` is served by its own server when it has a dedicated port
+    let expose_metrics = config.application.public_metrics;
+
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let app_base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let tracking = web::Data::new(config.tracking);
+    let health = web::Data::new(config.health);
+    let shutdown = web::Data::new(shutdown);
+    let setup_token = web::Data::new(setup_token);
+    let grace_period = config.shutdown.grace_period_seconds;
+    let redis_client = web::Data::new(redis::Client::open(redis_uri.expose_secret().as_str())?);
+    let rate_limiter = web::Data::new(RateLimiter::new(
+        ConnectionManager::new(redis_client.get_ref().clone()).await?,
+        config.signup.rate_limit_key_prefix.clone(),
+        config.signup.rate_limit_timeout(),
+    ));
+    let signup = web::Data::new(config.signup);
+    let email_policy = web::Data::new(config.email_policy.policy()?);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
+    let cookies = config.cookies;
 
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
@@ -106,28 +262,113 @@ pub async fn run(
 
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(CsrfProtection)
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .cookie_same_site(cookies.same_site.into())
+                    .cookie_secure(cookies.secure)
+                    .build(),
+            )
+            .wrap(RequestMetrics)
             .wrap(TracingLogger::default())
+            .configure(|cfg| {
+                if expose_metrics {
+                    cfg.route("/metrics", web::get().to(metrics));
+                }
+            })
             .route("/health_check", web::get().to(health_check))
+            .route("/health/live", web::get().to(liveness))
+            .route("/health/ready", web::get().to(readiness))
             .route("/admin/dashboard", web::get().to(admin_dashboard))
             .route("/admin/logout", web::post().to(logout))
             .route("/admin/password", web::get().to(change_password_form))
             .route("/admin/password", web::post().to(change_password))
+            .route("/admin/issues/{issue_id}", web::get().to(issue_delivery))
+            .route(
+                "/admin/issues/{issue_id}/clicks",
+                web::get().to(issue_clicks),
+            )
+            .route("/admin/audit", web::get().to(admin_audit_log))
+            .route("/admin/subscribers", web::get().to(admin_subscribers))
+            .route(
+                "/admin/subscribers/{subscriber_id}",
+                web::get().to(admin_subscriber),
+            )
+            .service(web::scope("/api/v1").configure(api_v1))
+            .route("/admin/webhooks", web::get().to(webhook_endpoints))
+            .route("/admin/webhooks", web::post().to(create_webhook_endpoint))
+            .route(
+                "/admin/webhooks/{endpoint_id}",
+                web::get().to(webhook_endpoint),
+            )
+            .route(
+                "/admin/webhooks/{endpoint_id}/enabled",
+                web::post().to(set_webhook_endpoint_enabled),
+            )
+            .route(
+                "/admin/webhooks/{endpoint_id}/delete",
+                web::post().to(delete_webhook_endpoint),
+            )
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/subscriptions/export", web::get().to(export_data))
             .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/t/o/{token}.gif", web::get().to(track_open))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
+            .route("/setup", web::get().to(setup_form))
+            .route("/setup", web::post().to(setup))
             .route("/", web::get().to(home))
             .route("login", web::get().to(login_form))
             .route("login", web::post().to(login))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(app_base_url.clone())
+            .app_data(tracking.clone())
+            .app_data(health.clone())
+            .app_data(shutdown.clone())
+            .app_data(setup_token.clone())
+            .app_data(redis_client.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(signup.clone())
+            .app_data(email_policy.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(webhook_secret.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
     })
+    // Signals are handled by `Application` so that readiness can fail before we stop accepting
+    .disable_signals()
+    .shutdown_timeout(grace_period)
+    .listen(listener)?
+    .run();
+
+    Ok(server)
+}
+
+///
+/// A server exposing only `/metrics`, for when it is bound to a separate admin port
+///
+pub fn run_metrics(
+    listener: TcpListener,
+    db_pool: PgPool,
+    shutdown_settings: &ShutdownSettings,
+) -> Result<Server, anyhow::Error> {
+    let db_pool = web::Data::new(db_pool);
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .route("/metrics", web::get().to(metrics))
+            .app_data(db_pool.clone())
+    })
+    .disable_signals()
+    .shutdown_timeout(shutdown_settings.grace_period_seconds)
     .listen(listener)?
     .run();
 
@@ -1,16 +1,47 @@
+use opentelemetry::global;
+use opentelemetry::propagation::Injector;
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{self, Sampler, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use tokio::task::JoinHandle;
 use tracing::{subscriber::set_global_default, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{
     fmt::MakeWriter, prelude::__tracing_subscriber_SubscriberExt, EnvFilter, Registry,
 };
 
+use crate::configuration::TelemetrySettings;
+
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
 ) -> impl Subscriber + Send + Sync
+where
+    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    // Without an endpoint there is no exporter to build, so this cannot fail
+    get_subscriber_with_telemetry(name, env_filter, sink, &TelemetrySettings::default())
+        .expect("Failed to build a tracer without an exporter")
+}
+
+///
+/// As `get_subscriber`, but every span is also given an OpenTelemetry trace context so it can be
+/// propagated to the services we call. Spans are exported over OTLP/HTTP when an endpoint is
+/// configured.
+///
+pub fn get_subscriber_with_telemetry<Sink>(
+    name: String,
+    env_filter: String,
+    sink: Sink,
+    settings: &TelemetrySettings,
+) -> Result<impl Subscriber + Send + Sync, TraceError>
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
@@ -18,18 +49,86 @@ where
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
 
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
+    let telemetry_layer = tracing_opentelemetry::layer().with_tracer(build_tracer(settings)?);
 
-    Registry::default()
+    Ok(Registry::default()
         .with(env_filter)
+        .with(telemetry_layer)
         .with(JsonStorageLayer)
-        .with(formatting_layer)
+        .with(formatting_layer))
+}
+
+fn build_tracer(settings: &TelemetrySettings) -> Result<Tracer, TraceError> {
+    let config = trace::config()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            settings.sampling_ratio,
+        ))))
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            settings.service_name.clone(),
+        )]));
+
+    let provider = match &settings.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint)
+                .build_span_exporter()?;
+
+            TracerProvider::builder()
+                .with_config(config)
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .build()
+        }
+        None => TracerProvider::builder().with_config(config).build(),
+    };
+
+    // The tracer only holds a weak reference, the global keeps the provider alive until shutdown
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    global::set_tracer_provider(provider);
+
+    Ok(tracer)
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set logger");
+    global::set_text_map_propagator(TraceContextPropagator::new());
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
 
+///
+/// Export any spans still buffered, call before the process exits
+///
+pub fn shutdown_telemetry() {
+    global::shutdown_tracer_provider();
+}
+
+///
+/// W3C `traceparent` (and `tracestate`) headers for the current span, so that outgoing requests
+/// join the trace of the request that caused them
+///
+pub fn trace_context_headers() -> HeaderMap {
+    let context = tracing::Span::current().context();
+    let mut headers = HeaderInjector(HeaderMap::new());
+
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+
+    headers.0
+}
+
+struct HeaderInjector(HeaderMap);
+
+impl Injector for HeaderInjector {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 where
     F: FnOnce() -> R + Send + 'static,
@@ -0,0 +1,205 @@
+use anyhow::Context;
+use chrono::Utc;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::WebhookSettings;
+use crate::metrics;
+use crate::shutdown::Shutdown;
+use crate::webhook_events::sign_webhook;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct PendingDelivery {
+    id: Uuid,
+    event_type: String,
+    payload: serde_json::Value,
+    attempts: i32,
+    url: String,
+    secret: String,
+}
+
+///
+/// Work through due webhook deliveries until shutdown is triggered. The delivery in hand is
+/// finished first, anything else stays queued for the next start.
+///
+pub async fn run_worker_until_stopped(
+    pool: PgPool,
+    settings: WebhookSettings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let http_client = reqwest::Client::builder()
+        .timeout(settings.request_timeout())
+        .build()?;
+
+    while !shutdown.is_triggered() {
+        let outcome = try_execute_task(&pool, &http_client, &settings).await;
+        if let Err(error) = &outcome {
+            tracing::error!(
+              error.cause_chain = ?error,
+              "Failed to process webhook deliveries"
+            );
+        }
+
+        if !matches!(outcome, Ok(ExecutionOutcome::TaskCompleted)) {
+            tokio::select! {
+                _ = tokio::time::sleep(settings.poll_interval()) => {}
+                _ = shutdown.triggered() => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Deliver webhook",
+    skip_all,
+    fields(delivery_id = tracing::field::Empty, event_type = tracing::field::Empty)
+)]
+///
+/// Attempt the delivery that has been due the longest. The row stays locked while the request is
+/// in flight, so several workers can share the queue.
+///
+pub async fn try_execute_task(
+    pool: &PgPool,
+    http_client: &reqwest::Client,
+    settings: &WebhookSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let Some(delivery) = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+      SELECT d.id, d.event_type, d.payload, d.attempts, e.url, e.secret
+      FROM webhook_deliveries d
+      JOIN webhook_endpoints e ON e.id = d.endpoint_id
+      WHERE d.status = 'pending' AND d.next_attempt_at <= now() AND e.enabled
+      ORDER BY d.next_attempt_at
+      LIMIT 1
+      FOR UPDATE OF d SKIP LOCKED
+    "#
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to fetch the next webhook delivery")?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+
+    let span = tracing::Span::current();
+    span.record("delivery_id", tracing::field::display(delivery.id));
+    span.record("event_type", tracing::field::display(&delivery.event_type));
+
+    let outcome = send(http_client, &delivery).await;
+    let attempts = delivery.attempts + 1;
+
+    let (status, next_attempt_at) = match &outcome {
+        Ok(_) => ("delivered", None),
+        Err(_) if attempts as u32 >= settings.max_attempts => ("failed", None),
+        Err(_) => {
+            let wait = settings.backoff(attempts as u32);
+            (
+                "pending",
+                Some(Utc::now() + chrono::Duration::from_std(wait)?),
+            )
+        }
+    };
+    let (response_status, error) = match outcome {
+        Ok(response_status) => (Some(response_status), None),
+        Err(SendError { status, error }) => {
+            tracing::warn!(error = %error, attempts, "Webhook delivery attempt failed");
+            (status, Some(error))
+        }
+    };
+    metrics::record_webhook_attempt(status);
+
+    sqlx::query!(
+        r#"
+      UPDATE webhook_deliveries
+      SET
+        status = $2,
+        attempts = $3,
+        next_attempt_at = COALESCE($4, next_attempt_at),
+        last_attempt_at = now(),
+        last_response_status = $5,
+        last_error = $6,
+        delivered_at = CASE WHEN $2 = 'delivered' THEN now() ELSE NULL END
+      WHERE id = $1
+    "#,
+        delivery.id,
+        status,
+        attempts,
+        next_attempt_at,
+        response_status,
+        error
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record the webhook delivery attempt")?;
+
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct SendError {
+    status: Option<i16>,
+    error: String,
+}
+
+///
+/// POST the signed payload, any 2xx response counts as delivered
+///
+async fn send(http_client: &reqwest::Client, delivery: &PendingDelivery) -> Result<i16, SendError> {
+    let body = delivery.payload.to_string();
+    let timestamp = Utc::now().timestamp();
+    let signature = sign_webhook(
+        &Secret::new(delivery.secret.clone()),
+        delivery.id,
+        timestamp,
+        &body,
+    );
+
+    let response = http_client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("Webhook-Id", delivery.id.to_string())
+        .header("Webhook-Event", &delivery.event_type)
+        .header("Webhook-Timestamp", timestamp.to_string())
+        .header("Webhook-Signature", signature)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| SendError {
+            status: None,
+            error: e.to_string(),
+        })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(SendError {
+            status: Some(status.as_u16() as i16),
+            error: format!("The endpoint responded with {}", status),
+        });
+    }
+
+    Ok(status.as_u16() as i16)
+}
+
+///
+/// Deliveries still waiting to go out, for the queue depth gauge
+///
+pub async fn pending_webhook_deliveries(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM webhook_deliveries WHERE status = 'pending'"#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.count)
+}
@@ -0,0 +1,315 @@
+use std::process::{Command, Output};
+
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email,
+    create_unconfirmed_subscriber_with_email, spawn_app, TestApp,
+};
+
+const PASSWORD: &str = "a-long-enough-password";
+
+///
+/// Run `newsletter-admin` against the test app's database and mock email server
+///
+async fn admin_cli(app: &TestApp, args: &[&str], password: Option<&str>) -> Output {
+    let database_name: String = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_newsletter-admin"));
+    command
+        .args(args)
+        .env("APP_DATABASE__DATABASE_NAME", database_name)
+        .env("APP_EMAIL_CLIENT__BASE_URL", app.email_server.uri())
+        .env_remove("NEWSLETTER_ADMIN_PASSWORD");
+    if let Some(password) = password {
+        command.env("NEWSLETTER_ADMIN_PASSWORD", password);
+    }
+
+    // The app under test shares this runtime, so don't block it while the CLI runs
+    let output = tokio::task::spawn_blocking(move || command.output())
+        .await
+        .unwrap()
+        .expect("Failed to run newsletter-admin");
+    assert!(
+        output.status.success(),
+        "newsletter-admin {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    output
+}
+
+async fn login(app: &TestApp, username: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+      "username": username,
+      "password": password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn created_users_can_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+
+    // Act
+    admin_cli(&app, &["users", "create", &username], Some(PASSWORD)).await;
+
+    // Assert
+    let response = login(&app, &username, PASSWORD).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[test]
+fn short_passwords_are_rejected() {
+    // Act
+    let output = Command::new(env!("CARGO_BIN_EXE_newsletter-admin"))
+        .args(["users", "create", "too-short"])
+        .env("NEWSLETTER_ADMIN_PASSWORD", "short")
+        .output()
+        .unwrap();
+
+    // Assert
+    // Rejected before the database is touched
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("at least 12 characters"));
+}
+
+#[tokio::test]
+async fn disabled_users_can_no_longer_log_in_or_publish() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    admin_cli(&app, &["users", "disable", &app.test_user.username], None).await;
+
+    // Assert
+    let response = login(&app, &app.test_user.username, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let action = sqlx::query_scalar!("SELECT action FROM audit_log WHERE user_id IS NULL LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(action, "user.disabled");
+
+    let response = app
+        .post_newsletters_json(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "markdown": "Newsletter body" },
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn reset_passwords_replace_the_old_one() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    admin_cli(
+        &app,
+        &["users", "reset-password", &app.test_user.username],
+        Some(PASSWORD),
+    )
+    .await;
+
+    // Assert
+    let response = login(&app, &app.test_user.username, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = login(&app, &app.test_user.username, PASSWORD).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_listed_confirmed_and_removed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber_with_email(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber_with_email(&app, "octavia_butler@gmail.com").await;
+
+    // Act
+    // Found the same way repeat signups are, whatever the case, dots or +tag
+    admin_cli(
+        &app,
+        &["subscribers", "confirm", "Ursula_Le_Guin+news@Gmail.com"],
+        None,
+    )
+    .await;
+    admin_cli(
+        &app,
+        &["subscribers", "remove", "octavia_butler@gmail.com"],
+        None,
+    )
+    .await;
+    let output = admin_cli(&app, &["subscribers", "list"], None).await;
+
+    // Assert
+    let listing = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = listing.lines().collect();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with("ursula_le_guin@gmail.com\t"));
+    assert!(lines[0].contains("\tconfirmed\t"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_exported_and_erased() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let export = admin_cli(
+        &app,
+        &["subscribers", "export", "ursula_le_guin@gmail.com"],
+        None,
+    )
+    .await;
+    let link = admin_cli(
+        &app,
+        &["subscribers", "export-link", "ursula_le_guin@gmail.com"],
+        None,
+    )
+    .await;
+    admin_cli(
+        &app,
+        &["subscribers", "erase", "ursula_le_guin@gmail.com"],
+        None,
+    )
+    .await;
+
+    // Assert
+    let export: serde_json::Value = serde_json::from_slice(&export.stdout).unwrap();
+    assert_eq!(export["subscriber"]["email"], "ursula_le_guin@gmail.com");
+    let link = String::from_utf8(link.stdout).unwrap();
+    assert!(link.contains("/subscriptions/export?token="));
+
+    let suppressed = sqlx::query!(
+        r#"
+      SELECT
+        (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+        (SELECT COUNT(*) FROM suppressed_emails) AS "suppressed!"
+    "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(suppressed.subscriptions, 0);
+    assert_eq!(suppressed.suppressed, 1);
+}
+
+#[tokio::test]
+async fn issues_can_be_published_from_a_markdown_file() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula_le_guin@gmail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let file = std::env::temp_dir().join(format!("{}.md", Uuid::new_v4()));
+    std::fs::write(&file, "# Hello\n\nNewsletter body as *Markdown*").unwrap();
+
+    // Act
+    admin_cli(
+        &app,
+        &[
+            "publish",
+            file.to_str().unwrap(),
+            "--title",
+            "Newsletter title",
+        ],
+        None,
+    )
+    .await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT title, html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Newsletter title");
+    assert!(issue.html_content.contains("<em>Markdown</em>"));
+
+    std::fs::remove_file(file).unwrap();
+}
+
+#[tokio::test]
+async fn seeding_adds_a_mix_of_confirmed_and_pending_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    admin_cli(&app, &["seed", "--subscribers", "9"], None).await;
+
+    // Assert
+    let counts = sqlx::query!(
+        r#"SELECT status, COUNT(*) AS "count!" FROM subscriptions GROUP BY status ORDER BY status"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts[0].status, "confirmed");
+    assert_eq!(counts[0].count, 6);
+    assert_eq!(counts[1].status, "pending_confirmation");
+    assert_eq!(counts[1].count, 3);
+}
+
+#[test]
+fn seeding_is_refused_outside_local_without_force() {
+    // Act
+    let output = Command::new(env!("CARGO_BIN_EXE_newsletter-admin"))
+        .args(["seed"])
+        .env("APP_ENVIRONMENT", "production")
+        .env(
+            "APP_APPLICATION__BASE_URL",
+            "https://newsletter.example.com",
+        )
+        .env("APP_APPLICATION__HMAC_SECRET", "a".repeat(64))
+        .env("APP_DATABASE__PASSWORD", "a-real-password")
+        .env("APP_EMAIL_CLIENT__AUTHROISATATION_TOKEN", "a-real-token")
+        .env(
+            "APP_EMAIL_CLIENT__WEBHOOK_SECRET",
+            "a-real-postmark-webhook-secret-of-enough-length",
+        )
+        .output()
+        .unwrap();
+
+    // Assert
+    // Rejected before the database is touched
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--force"));
+}
+
+#[tokio::test]
+async fn migrate_brings_the_database_up_to_date() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let output = admin_cli(&app, &["migrate"], None).await;
+
+    // Assert
+    // The test database is already migrated, so this must be a no-op rather than an error
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        "Database is up to date"
+    );
+}
@@ -61,3 +61,28 @@ async fn logout_clears_session_state() {
     let response = app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn the_username_is_escaped_on_the_dashboard() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+      "username": &app.test_user.username,
+      "password": &app.test_user.password
+    }))
+    .await;
+    sqlx::query!(
+        "UPDATE users SET username = '<script>alert(1)</script>' WHERE username = $1",
+        app.test_user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("Welcome &lt;script&gt;alert(1)&lt;/script&gt;!"));
+}
@@ -0,0 +1,409 @@
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn create_subscriber(app: &TestApp, body: Value) -> reqwest::Response {
+    app.api_v1(Method::POST, "/subscribers")
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+///
+/// Every `$ref` in the document, e.g. `#/components/schemas/Subscriber`
+///
+fn references(value: &Value) -> Vec<String> {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .flat_map(|(key, value)| match (key.as_str(), value) {
+                ("$ref", Value::String(reference)) => vec![reference.clone()],
+                _ => references(value),
+            })
+            .collect(),
+        Value::Array(values) => values.iter().flat_map(references).collect(),
+        _ => vec![],
+    }
+}
+
+async fn get_json(app: &TestApp, path: &str) -> Value {
+    let response = app.api_v1(Method::GET, path).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn every_documented_endpoint_exists_and_requires_credentials() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let document: Value = reqwest::get(format!("{}/api/v1/openapi.json", app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    let paths = document["paths"].as_object().unwrap();
+    assert_eq!(paths.len(), 7);
+
+    for (path, operations) in paths {
+        let path = path.replace("{subscriber_id}", &Uuid::new_v4().to_string());
+        let path = path.replace("{issue_id}", &Uuid::new_v4().to_string());
+
+        for method in operations.as_object().unwrap().keys() {
+            let response = app
+                .api_client
+                .request(
+                    method.to_uppercase().parse().unwrap(),
+                    format!("{}{}", app.address, path),
+                )
+                .json(&json!({}))
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(
+                response.status().as_u16(),
+                401,
+                "{} {} should exist and require credentials",
+                method,
+                path
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_openapi_document_describes_the_response_types() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let document: Value = reqwest::get(format!("{}/api/v1/openapi.json", app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+    let schemas = document["components"]["schemas"].as_object().unwrap();
+    for schema in ["Subscriber", "SubscriberPage", "IssuePage", "DeliveryStats"] {
+        assert!(schemas.contains_key(schema), "{} is missing", schema);
+    }
+    for reference in references(&document) {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        assert!(schemas.contains_key(name), "{} is not defined", reference);
+    }
+    assert_eq!(
+        document["components"]["securitySchemes"]["basic_auth"]["scheme"],
+        "basic"
+    );
+}
+
+#[tokio::test]
+async fn requests_with_invalid_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/subscribers", app.address))
+        .basic_auth(&app.test_user.username, Some("wrong password"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="api""#
+    );
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "unauthorised");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_created_read_updated_and_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - create
+    let response = create_subscriber(
+        &app,
+        json!({ "email": "ursula@example.com", "name": "Ursula", "attributes": { "plan": "pro" } }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    let created: Value = response.json().await.unwrap();
+    assert_eq!(created["status"], "confirmed");
+    assert_eq!(created["attributes"]["plan"], "pro");
+    assert_eq!(
+        location,
+        format!("/api/v1/subscribers/{}", created["id"].as_str().unwrap())
+    );
+
+    // Act - read
+    let path = location.trim_start_matches("/api/v1");
+    let read = get_json(&app, path).await;
+    assert_eq!(read, created);
+
+    // Act - update
+    let response = app
+        .api_v1(Method::PATCH, path)
+        .json(&json!({ "name": "Ursula K. Le Guin", "status": "unsubscribed" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let updated: Value = response.json().await.unwrap();
+    assert_eq!(updated["name"], "Ursula K. Le Guin");
+    assert_eq!(updated["status"], "unsubscribed");
+    assert_eq!(updated["attributes"]["plan"], "pro");
+
+    // Act - delete
+    let response = app.api_v1(Method::DELETE, path).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+
+    // Assert
+    let response = app.api_v1(Method::GET, path).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "not_found");
+}
+
+#[tokio::test]
+async fn creating_a_subscriber_reports_invalid_fields() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = create_subscriber(
+        &app,
+        json!({ "email": "not-an-email", "name": "", "attributes": [] }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "invalid_fields");
+    assert!(body["fields"]["email"].is_string());
+    assert!(body["fields"]["name"].is_string());
+}
+
+#[tokio::test]
+async fn creating_a_subscriber_for_an_existing_mailbox_is_a_conflict() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(
+        &app,
+        json!({ "email": "ursula@example.com", "name": "Ursula" }),
+    )
+    .await;
+
+    // Act
+    let response = create_subscriber(
+        &app,
+        json!({ "email": "URSULA@example.com", "name": "Ursula" }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn concurrent_creates_for_one_mailbox_create_it_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = json!({ "email": "ursula@example.com", "name": "Ursula" });
+
+    // Act
+    let responses = tokio::join!(
+        create_subscriber(&app, body.clone()),
+        create_subscriber(&app, body.clone()),
+        create_subscriber(&app, body.clone()),
+    );
+
+    // Assert
+    let mut statuses = [responses.0, responses.1, responses.2].map(|r| r.status().as_u16());
+    statuses.sort();
+    assert_eq!(statuses, [201, 409, 409]);
+}
+
+#[tokio::test]
+async fn malformed_json_bodies_get_a_json_error() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "/subscribers")
+        .header("Content-Type", "application/json")
+        .body("{ not json")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "invalid_request");
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_with_a_cursor() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..5 {
+        create_subscriber(
+            &app,
+            json!({ "email": format!("reader{}@example.com", i), "name": format!("Reader {}", i) }),
+        )
+        .await;
+    }
+
+    // Act
+    let mut emails = Vec::new();
+    let mut page = get_json(&app, "/subscribers?limit=2").await;
+    let mut pages = 1;
+    loop {
+        for item in page["items"].as_array().unwrap() {
+            emails.push(item["email"].as_str().unwrap().to_owned());
+        }
+        let Some(cursor) = page["next_cursor"].as_str() else {
+            break;
+        };
+        page = get_json(&app, &format!("/subscribers?limit=2&cursor={}", cursor)).await;
+        pages += 1;
+    }
+
+    // Assert
+    assert_eq!(pages, 3);
+    assert_eq!(
+        emails,
+        (0..5)
+            .map(|i| format!("reader{}@example.com", i))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_email() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app, json!({ "email": "ada@example.com", "name": "Ada" })).await;
+    create_subscriber(
+        &app,
+        json!({ "email": "grace@example.com", "name": "Grace", "status": "pending_confirmation" }),
+    )
+    .await;
+    create_subscriber(&app, json!({ "email": "alan@example.org", "name": "Alan" })).await;
+
+    // Act
+    let pending = get_json(&app, "/subscribers?status=pending_confirmation").await;
+    let dot_com = get_json(&app, "/subscribers?status=confirmed&email=EXAMPLE.COM").await;
+
+    // Assert
+    let emails = |page: &Value| -> Vec<String> {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["email"].as_str().unwrap().to_owned())
+            .collect()
+    };
+    assert_eq!(emails(&pending), vec!["grace@example.com"]);
+    assert_eq!(emails(&dot_com), vec!["ada@example.com"]);
+}
+
+#[tokio::test]
+async fn invalid_cursors_and_limits_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for query in ["cursor=garbage", "limit=0", "limit=1000", "status=unknown"] {
+        // Act
+        let response = app
+            .api_v1(Method::GET, &format!("/subscribers?{}", query))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400, "{}", query);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "invalid_request");
+    }
+}
+
+#[tokio::test]
+async fn published_issues_can_be_listed_with_their_delivery_stats() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for title in ["First issue", "Second issue"] {
+        let response = app
+            .post_newsletters_json(json!({
+                "title": title,
+                "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Act
+    let issues = get_json(&app, "/issues").await;
+
+    // Assert
+    let items = issues["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0]["title"], "Second issue");
+    assert_eq!(items[1]["title"], "First issue");
+    assert!(issues["next_cursor"].is_null());
+
+    let issue_id = items[0]["id"].as_str().unwrap();
+    let issue = get_json(&app, &format!("/issues/{}", issue_id)).await;
+    assert_eq!(issue["text_content"], "Plain text");
+
+    let stats = get_json(&app, &format!("/issues/{}/stats", issue_id)).await;
+    assert_eq!(stats["targeted"], 1);
+    assert_eq!(stats["delivered"], 1);
+    assert_eq!(stats["failed"], 0);
+    assert_eq!(stats["opened"], 0);
+}
+
+#[tokio::test]
+async fn unknown_issues_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::GET, &format!("/issues/{}/stats", Uuid::new_v4()))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
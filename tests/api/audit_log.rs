@@ -0,0 +1,337 @@
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero_to_production::audit_log::purge_expired_audit_events;
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+struct AuditEntry {
+    user_id: Option<Uuid>,
+    action: String,
+    target: Option<String>,
+    ip_address: Option<String>,
+    changes: Value,
+}
+
+async fn audit_entries(app: &TestApp) -> Vec<AuditEntry> {
+    sqlx::query_as!(
+        AuditEntry,
+        r#"
+      SELECT user_id, action, target, ip_address, changes
+      FROM audit_log
+      ORDER BY occurred_at
+    "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn test_user_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!(
+        "SELECT user_id FROM users WHERE username = $1",
+        app.test_user.username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+#[tokio::test]
+async fn logins_and_logouts_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    let user_id = test_user_id(&app).await;
+
+    // Act
+    app.post_login(&json!({
+        "username": &app.test_user.username,
+        "password": "not-the-password",
+    }))
+    .await;
+    login(&app).await;
+    app.post_logout().await;
+
+    // Assert
+    let entries = audit_entries(&app).await;
+    let actions: Vec<_> = entries.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(actions, ["login.failed", "login.succeeded", "logout"]);
+
+    assert_eq!(entries[0].user_id, None);
+    assert_eq!(
+        entries[0].target.as_deref(),
+        Some(app.test_user.username.as_str())
+    );
+    assert_eq!(entries[1].user_id, Some(user_id));
+    assert_eq!(entries[2].user_id, Some(user_id));
+    for entry in &entries {
+        assert_eq!(entry.ip_address.as_deref(), Some("127.0.0.1"));
+    }
+}
+
+#[tokio::test]
+async fn password_changes_are_recorded_without_the_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+    login(&app).await;
+
+    // Act
+    app.post_change_password(&json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    // Assert
+    let entries = audit_entries(&app).await;
+    let entry = entries
+        .iter()
+        .find(|e| e.action == "password.changed")
+        .unwrap();
+    assert_eq!(entry.user_id, Some(test_user_id(&app).await));
+    assert_eq!(entry.changes, json!({}));
+}
+
+#[tokio::test]
+async fn publishing_an_issue_is_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response: Value = app
+        .post_newsletters_json(json!({
+            "title": "Newsletter title",
+            "content": { "markdown": "Newsletter body" },
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "issue.published");
+    assert_eq!(entries[0].user_id, Some(test_user_id(&app).await));
+    assert_eq!(entries[0].target.as_deref(), response["issue_id"].as_str());
+    assert_eq!(
+        entries[0].changes,
+        json!({ "title": { "from": null, "to": "Newsletter title" } })
+    );
+}
+
+#[tokio::test]
+async fn an_issue_is_not_sent_if_its_publication_cannot_be_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("ALTER TABLE audit_log RENAME TO audit_log_sabotaged")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_newsletters_json(json!({
+            "title": "Newsletter title",
+            "content": { "markdown": "Newsletter body" },
+        }))
+        .await;
+
+    // Assert
+    // Failing before any email goes out, a retry can't send the issue twice
+    assert_eq!(response.status().as_u16(), 500);
+    let issues = sqlx::query!("SELECT id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn subscriber_changes_through_the_api_are_recorded_as_diffs() {
+    // Arrange
+    let app = spawn_app().await;
+    let created: Value = app
+        .api_v1(Method::POST, "/subscribers")
+        .json(&json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "le guin",
+            "status": "pending_confirmation",
+            "attributes": { "city": "Portland", "plan": "free" },
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let subscriber_id = created["id"].as_str().unwrap();
+
+    // Act
+    app.api_v1(Method::PATCH, &format!("/subscribers/{}", subscriber_id))
+        .json(&json!({
+            "status": "confirmed",
+            "name": "Ursula K. Le Guin",
+            "attributes": { "city": "Portland", "plan": "pro" },
+        }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.api_v1(Method::DELETE, &format!("/subscribers/{}", subscriber_id))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let entries = audit_entries(&app).await;
+    let actions: Vec<_> = entries.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(
+        actions,
+        [
+            "subscriber.created",
+            "subscriber.updated",
+            "subscriber.deleted"
+        ]
+    );
+    for entry in &entries {
+        assert_eq!(entry.target.as_deref(), Some(subscriber_id));
+        assert_eq!(entry.user_id, Some(test_user_id(&app).await));
+        // Nothing identifying the subscriber is kept, attributes only by name
+        assert!(!entry.changes.to_string().contains("guin"));
+        assert!(!entry.changes.to_string().contains("Portland"));
+    }
+    assert_eq!(entries[0].changes["attributes"], json!(["city", "plan"]));
+    assert_eq!(
+        entries[1].changes,
+        json!({
+            "status": { "from": "pending_confirmation", "to": "confirmed" },
+            "attributes": ["plan"],
+        })
+    );
+    assert_eq!(entries[2].changes["status"]["from"], "confirmed");
+    assert_eq!(entries[2].changes["status"]["to"], Value::Null);
+}
+
+#[tokio::test]
+async fn the_audit_log_is_append_only() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let update = sqlx::query!("UPDATE audit_log SET action = 'nothing.happened'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.db_pool)
+        .await;
+
+    // Assert
+    assert!(update.is_err());
+    assert!(delete.is_err());
+    assert_eq!(audit_entries(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn entries_past_the_retention_period_are_purged() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    sqlx::query!(
+        r#"
+      INSERT INTO audit_log (id, occurred_at, action, changes)
+      VALUES (gen_random_uuid(), now() - INTERVAL '400 days', 'logout', '{}')
+    "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let purged = purge_expired_audit_events(&app.db_pool, chrono::Duration::days(365))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(purged, 1);
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "login.succeeded");
+}
+
+#[tokio::test]
+async fn the_audit_log_page_can_be_filtered() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&json!({
+        "username": "mallory",
+        "password": "not-the-password",
+    }))
+    .await;
+    login(&app).await;
+
+    // Act
+    let all = app.get_audit_log("").await.text().await.unwrap();
+    let failures = app
+        .get_audit_log("action=login.failed")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let by_user = app
+        .get_audit_log(&format!("username={}", app.test_user.username))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(all.contains("<td>mallory</td>"));
+    assert!(all.contains("<td>login.succeeded</td>"));
+    assert!(failures.contains("<td>mallory</td>"));
+    assert!(!failures.contains("<td>login.succeeded</td>"));
+    assert!(by_user.contains("<td>login.succeeded</td>"));
+    assert!(!by_user.contains("<td>login.failed</td>"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_audit_log("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
@@ -140,7 +140,39 @@ async fn changing_password_works() {
     let app = spawn_app().await;
     let new_password = Uuid::new_v4().to_string();
 
-    // Act
+    // Act - p1 login
+    let response = app
+        .post_login(&serde_json::json!({
+          "username": &app.test_user.username,
+          "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
 
-    // Assert
+    // Act - p2 change password
+    let response = app
+        .post_change_password(&serde_json::json!({
+          "current_password": &app.test_user.password,
+          "new_password": &new_password,
+          "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    // Act - p3 follow the redirect
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+
+    // Act - p4 logout
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - p5 login using the new password
+    let response = app
+        .post_login(&serde_json::json!({
+          "username": &app.test_user.username,
+          "password": &new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
 }
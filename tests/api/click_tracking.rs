@@ -0,0 +1,205 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+struct PublishedIssue {
+    html: String,
+    text: String,
+}
+
+async fn publish_issue(app: &TestApp, track_clicks: bool) -> PublishedIssue {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_newsletters_json(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+          "markdown": "Read [the post](https://example.com/post?a=1&b=2) or [unsubscribe]({{ unsubscribe_url }}).",
+        },
+        "track_clicks": track_clicks,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+    PublishedIssue {
+        html: body["HtmlBody"].as_str().unwrap().to_owned(),
+        text: body["TextBody"].as_str().unwrap().to_owned(),
+    }
+}
+
+fn click_tracking_urls(app: &TestApp, s: &str) -> Vec<reqwest::Url> {
+    s.match_indices("http://localhost/t/c/")
+        .map(|(start, _)| {
+            let end = s[start..]
+                .find(|c: char| c == '"' || c.is_whitespace())
+                .map_or(s.len(), |end| start + end);
+
+            let mut url = reqwest::Url::parse(&s[start..end]).unwrap();
+            url.set_port(Some(app.port)).unwrap();
+            url
+        })
+        .collect()
+}
+
+async fn click(app: &TestApp, url: &reqwest::Url) -> reqwest::Response {
+    app.api_client
+        .get(url.clone())
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn links_in_both_parts_are_rewritten_for_tracked_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let issue = publish_issue(&app, true).await;
+
+    // Assert
+    assert!(!issue.html.contains("https://example.com/post"));
+    assert!(!issue.text.contains("https://example.com/post"));
+    assert_eq!(click_tracking_urls(&app, &issue.html).len(), 1);
+    assert_eq!(
+        click_tracking_urls(&app, &issue.html),
+        click_tracking_urls(&app, &issue.text)
+    );
+    // Unsubscribe links are personalised, and must keep working without the tracker
+    assert!(issue.html.contains("/subscriptions/unsubscribe?token="));
+}
+
+#[tokio::test]
+async fn links_are_not_rewritten_unless_requested() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let issue = publish_issue(&app, false).await;
+
+    // Assert
+    assert!(issue.html.contains("https://example.com/post"));
+    assert!(click_tracking_urls(&app, &issue.html).is_empty());
+}
+
+#[tokio::test]
+async fn clicking_a_link_records_the_click_and_redirects_to_the_original_url() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue = publish_issue(&app, true).await;
+    let link = &click_tracking_urls(&app, &issue.html)[0];
+
+    // Act
+    let response = click(&app, link).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/post?a=1&b=2"
+    );
+
+    let saved = sqlx::query!(
+        r#"
+          SELECT l.url, c.subscriber_id
+          FROM issue_clicks c
+          JOIN issue_links l USING (newsletter_issue_id, link_id)
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved click");
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(saved.url, "https://example.com/post?a=1&b=2");
+    assert_eq!(saved.subscriber_id, subscriber.id);
+}
+
+#[tokio::test]
+async fn tampered_tokens_are_rejected_with_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue = publish_issue(&app, true).await;
+    let mut link = click_tracking_urls(&app, &issue.html).remove(0);
+    let tampered_path = link.path().replacen("/t/c/", "/t/c/A", 1);
+    link.set_path(&tampered_path);
+
+    // Act
+    let response = click(&app, &link).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_click_counts() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_issue_clicks(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn click_counts_are_reported_per_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue = publish_issue(&app, true).await;
+    let link = &click_tracking_urls(&app, &issue.html)[0];
+    click(&app, link).await;
+    click(&app, link).await;
+
+    let issue_id = sqlx::query!("SELECT id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    app.post_login(&serde_json::json!({
+      "username": &app.test_user.username,
+      "password": &app.test_user.password,
+    }))
+    .await;
+
+    // Act
+    let clicks: serde_json::Value = app.get_issue_clicks(issue_id).await.json().await.unwrap();
+
+    // Assert
+    assert_eq!(
+        clicks,
+        serde_json::json!([{
+          "url": "https://example.com/post?a=1&b=2",
+          "clicks": 2,
+          "unique_clicks": 1,
+        }])
+    );
+}
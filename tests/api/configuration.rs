@@ -0,0 +1,106 @@
+use std::process::{Command, Output};
+
+fn check_config(env: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_zero-to-production"))
+        .arg("--check-config")
+        .envs(env.iter().copied())
+        .output()
+        .expect("Failed to run zero-to-production")
+}
+
+#[test]
+fn check_config_accepts_the_checked_in_configuration() {
+    // Act
+    let output = check_config(&[]);
+
+    // Assert
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "Configuration is valid"
+    );
+}
+
+#[test]
+fn check_config_reports_every_problem_with_its_source() {
+    // Act
+    let output = check_config(&[
+        ("APP_APPLICATION__BASE_URL", "localhost"),
+        ("APP_EMAIL_CLIENT__TIMEOUT_MILLISECONDS", "0"),
+    ]);
+
+    // Assert
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Found 2 problem(s)"), "{}", stderr);
+    assert!(stderr
+        .contains("application.base_url (from environment variable APP_APPLICATION__BASE_URL)"));
+    assert!(stderr.contains(
+        "email_client.timeout_milliseconds (from environment variable APP_EMAIL_CLIENT__TIMEOUT_MILLISECONDS)"
+    ));
+}
+
+#[test]
+fn production_refuses_to_start_with_the_development_secrets() {
+    // Arrange
+    let hmac_secret_file = std::env::temp_dir().join(format!("{}-hmac", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &hmac_secret_file,
+        "long-and-very-secret-random-key-needed-to-verify-message-integrity\n",
+    )
+    .unwrap();
+    let hmac_secret_file = hmac_secret_file.to_str().unwrap();
+
+    // Act
+    let output = check_config(&[
+        ("APP_ENVIRONMENT", "production"),
+        (
+            "APP_APPLICATION__BASE_URL",
+            "https://newsletter.example.com",
+        ),
+        ("APP_APPLICATION__HMAC_SECRET_FILE", hmac_secret_file),
+        ("APP_DATABASE__PASSWORD", "password"),
+        (
+            "APP_EMAIL_CLIENT__AUTHROISATATION_TOKEN",
+            "a-real-postmark-token",
+        ),
+        (
+            "APP_EMAIL_CLIENT__WEBHOOK_SECRET",
+            "a-real-postmark-webhook-secret-of-enough-length",
+        ),
+    ]);
+
+    // Assert
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Found 2 problem(s)"), "{}", stderr);
+    assert!(stderr.contains(&format!(
+        "application.hmac_secret (from {} via APP_APPLICATION__HMAC_SECRET_FILE)",
+        hmac_secret_file
+    )));
+    assert!(stderr.contains("database.password (from environment variable APP_DATABASE__PASSWORD)"));
+
+    std::fs::remove_file(hmac_secret_file).unwrap();
+}
+
+#[test]
+fn dumped_configuration_redacts_secrets() {
+    // Act
+    let output = Command::new(env!("CARGO_BIN_EXE_zero-to-production"))
+        .arg("--dump-config")
+        .env(
+            "APP_EMAIL_CLIENT__AUTHROISATATION_TOKEN",
+            "a-real-postmark-token",
+        )
+        .output()
+        .unwrap();
+
+    // Assert
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("authroisatation_token: Secret([REDACTED"));
+    assert!(!stdout.contains("a-real-postmark-token"));
+}
@@ -0,0 +1,159 @@
+use zero_to_production::configuration::CookieSameSite;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with_configuration, TestApp};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+fn new_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn logging_in_without_a_csrf_token_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    app.get_login_html().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn a_token_from_another_session_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // An attacker's own token, planted in a form on their site
+    let attacker = new_client();
+    let html = attacker
+        .get(format!("{}/login", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let (_, rest) = html.split_once(r#"name="csrf_token" value=""#).unwrap();
+    let attacker_token = rest.split('"').next().unwrap();
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .form(&[("csrf_token", attacker_token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn admin_form_posts_without_a_csrf_token_are_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    for path in ["/admin/logout", "/admin/password", "/admin/webhooks"] {
+        // Act
+        let response = app
+            .api_client
+            .post(format!("{}{}", &app.address, path))
+            .form(&[("csrf_token", "")])
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 403, "POST {}", path);
+    }
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn every_form_carries_the_session_token() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let token = app.csrf_token().await;
+    let input = format!(r#"name="csrf_token" value="{}""#, token);
+
+    // Act
+    let dashboard = app.get_admin_dashboard_html().await;
+    let password = app.get_change_password_html().await;
+    let webhooks = app.get_webhook_endpoints().await.text().await.unwrap();
+
+    // Assert
+    assert!(dashboard.contains(&input));
+    assert!(password.contains(&input));
+    assert!(webhooks.contains(&input));
+}
+
+#[tokio::test]
+async fn logging_out_starts_a_session_with_a_new_token() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let token = app.csrf_token().await;
+
+    // Act
+    app.post_logout().await;
+
+    // Assert
+    assert_ne!(app.csrf_token().await, token);
+}
+
+#[tokio::test]
+async fn the_session_cookie_follows_the_cookie_settings() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.cookies.same_site = CookieSameSite::Strict;
+        c.cookies.secure = true;
+    })
+    .await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/login", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let cookie = response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .find(|value| value.starts_with("id="))
+        .expect("No session cookie was set");
+    assert!(cookie.contains("SameSite=Strict"));
+    assert!(cookie.contains("Secure"));
+}
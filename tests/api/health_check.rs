@@ -1,4 +1,9 @@
-use crate::helpers::spawn_app;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, spawn_app_with_configuration};
 
 #[tokio::test]
 async fn health_check_coverage() {
@@ -12,3 +17,91 @@ async fn health_check_coverage() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length())
 }
+
+#[tokio::test]
+async fn liveness_returns_200() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_health_live().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn readiness_reports_every_dependency_as_up() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_health_ready().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "up");
+    for component in ["postgres", "redis"] {
+        assert_eq!(body["checks"][component]["status"], "up");
+        assert!(body["checks"][component]["latency_ms"].is_u64());
+    }
+    // The email provider is only probed when asked to
+    assert!(body["checks"].get("email_provider").is_none());
+}
+
+#[tokio::test]
+async fn readiness_fails_when_the_email_provider_rejects_us() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.health.check_email_provider = true).await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.get_health_ready().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["checks"]["postgres"]["status"], "up");
+    assert_eq!(body["checks"]["email_provider"]["status"], "down");
+    // The provider's error names its URL, it is logged rather than returned
+    assert_eq!(body["checks"]["email_provider"]["error"], "Check failed");
+}
+
+#[tokio::test]
+async fn readiness_fails_when_a_dependency_does_not_answer_in_time() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.health.check_email_provider = true;
+        c.health.timeout_milliseconds = 100;
+    })
+    .await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(2)))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.get_health_ready().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["email_provider"]["status"], "down");
+    assert_eq!(
+        body["checks"]["email_provider"]["error"],
+        "Timed out after 100ms"
+    );
+}
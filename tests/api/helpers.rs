@@ -1,12 +1,20 @@
 use argon2::password_hash::SaltString;
 use argon2::{Argon2, Params, PasswordHasher};
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero_to_production::configuration::{get_configuration, DatabaseSettings};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero_to_production::configuration::{
+    get_configuration, DatabaseSettings, Settings, WebhookSettings,
+};
+use zero_to_production::domain::UnsubscribeToken;
+use zero_to_production::shutdown::Shutdown;
 use zero_to_production::startup::{get_connection_pool, Application};
 use zero_to_production::telemetry::{get_subscriber, init_subscriber};
+use zero_to_production::webhook_delivery_worker::{try_execute_task, ExecutionOutcome};
 
 // Ensure that the `tracing` stack is only initialised once using `once_cell`
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -24,10 +32,15 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 pub struct TestApp {
     pub address: String,
     pub port: u16,
+    pub metrics_port: Option<u16>,
     pub db_pool: PgPool,
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub shutdown: Shutdown,
+    pub setup_token: Option<String>,
+    pub webhooks: WebhookSettings,
+    pub server: JoinHandle<Result<(), std::io::Error>>,
 }
 
 pub struct ConfirmationLinks {
@@ -44,9 +57,33 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_health_live(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/health/live", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_health_ready(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/health/ready", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/metrics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_newsletters_json(&self, body: serde_json::Value) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/newsletters", &self.address))
+            .post(format!("{}/newsletters", &self.address))
             .basic_auth(&self.test_user.username, Some(&self.test_user.password))
             .json(&body)
             .send()
@@ -56,7 +93,7 @@ impl TestApp {
 
     pub async fn post_newsletters_string(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/newsletters", &self.address))
+            .post(format!("{}/newsletters", &self.address))
             .basic_auth(&self.test_user.username, Some(&self.test_user.password))
             .json(&body)
             .send()
@@ -64,6 +101,15 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    ///
+    /// A request to `/api/v1`, authenticated as the test user
+    ///
+    pub fn api_v1(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.api_client
+            .request(method, format!("{}/api/v1{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(format!("{}/subscriptions", &self.address))
@@ -74,6 +120,15 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -92,35 +147,106 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_links(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_links(&body["TextBody"].as_str().unwrap());
+        let html = get_links(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_links(body["TextBody"].as_str().unwrap());
 
         ConfirmationLinks { html, plain_text }
     }
 
+    ///
+    /// The unsubscribe link an issue would carry for this subscriber
+    ///
+    pub fn unsubscribe_link(&self, subscriber_id: Uuid) -> String {
+        let hmac_secret = get_configuration().unwrap().application.hmac_secret;
+        format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            self.address,
+            UnsubscribeToken::new(subscriber_id).encode(&hmac_secret)
+        )
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
+        self.post_form("/login", body).await
+    }
+
+    ///
+    /// The CSRF token of the client's session, as rendered in the login form
+    ///
+    pub async fn csrf_token(&self) -> String {
+        let html = self.get_login_html().await;
+        let (_, rest) = html
+            .split_once(r#"name="csrf_token" value=""#)
+            .expect("The login form has no CSRF token");
+
+        rest.split('"').next().unwrap().to_string()
+    }
+
+    ///
+    /// Submit a form the way a browser would, with the session's CSRF token
+    ///
+    pub async fn post_form<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut form = serde_urlencoded::to_string(body).expect("Failed to encode the form");
+        if !form.is_empty() {
+            form.push('&');
+        }
+        form.push_str(
+            &serde_urlencoded::to_string([("csrf_token", self.csrf_token().await)]).unwrap(),
+        );
+
         self.api_client
-            .post(&format!("{}/login", &self.address))
-            .form(body)
+            .post(format!("{}{}", &self.address, path))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(form)
             .send()
             .await
             .expect("Failed to execute request")
     }
 
-    pub async fn post_logout(&self) -> reqwest::Response {
+    pub async fn get_home_html(&self) -> String {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .get(&self.address)
             .send()
             .await
             .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_setup(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/setup", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_setup<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/setup", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.post_form("/admin/logout", &()).await
     }
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -160,14 +286,129 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
+        self.post_form("/admin/password", body).await
+    }
+
+    pub async fn get_issue_clicks(&self, issue_id: Uuid) -> reqwest::Response {
         self.api_client
-            .post(format!("{}/admin/password", &self.address))
-            .form(body)
+            .get(format!(
+                "{}/admin/issues/{}/clicks",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_issue_delivery(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_audit_log(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_postmark_webhook(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                "postmark",
+                Some(
+                    get_configuration()
+                        .unwrap()
+                        .email_client
+                        .webhook_secret
+                        .expose_secret(),
+                ),
+            )
+            .json(&body)
             .send()
             .await
             .expect("Failed to execute request")
     }
 
+    pub async fn get_webhook_endpoints(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/webhooks", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webhook_endpoints<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form("/admin/webhooks", body).await
+    }
+
+    pub async fn get_webhook_endpoint(&self, endpoint_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/webhooks/{}", &self.address, endpoint_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    ///
+    /// Register an endpoint straight in the database, returning its id and signing secret
+    ///
+    pub async fn add_webhook_endpoint(&self, url: &str, events: &[&str]) -> (Uuid, String) {
+        let endpoint_id = Uuid::new_v4();
+        let secret = format!("whsec_{}", Uuid::new_v4().simple());
+        let events: Vec<String> = events.iter().map(|e| e.to_string()).collect();
+        sqlx::query!(
+            r#"
+          INSERT INTO webhook_endpoints (id, url, secret, events, enabled, created_at)
+          VALUES ($1, $2, $3, $4, true, now())
+        "#,
+            endpoint_id,
+            url,
+            secret,
+            &events
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+
+        (endpoint_id, secret)
+    }
+
+    ///
+    /// The background worker is off in tests, this works through every delivery that is due
+    ///
+    pub async fn dispatch_all_pending_webhooks(&self) {
+        let http_client = reqwest::Client::new();
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(&self.db_pool, &http_client, &self.webhooks)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
     pub async fn get_change_password_html(&self) -> String {
         self.get_change_password_form().await.text().await.unwrap()
     }
@@ -217,6 +458,13 @@ impl TestUser {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_configuration(|_| {}).await
+}
+
+///
+/// Spawn the app after applying test specific overrides on top of the randomised configuration
+///
+pub async fn spawn_app_with_configuration(customise: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -229,6 +477,15 @@ pub async fn spawn_app() -> TestApp {
         // Use a random OS port
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        // Every test app shares one Redis and one client IP, so keep their rate limits apart
+        c.signup.rate_limit_key_prefix = Uuid::new_v4().to_string();
+        // Tests post to /subscriptions directly rather than through the form on the home page
+        c.signup.min_fill_seconds = 0;
+        // Tests deliver webhooks with `dispatch_all_pending_webhooks` rather than polling
+        c.webhooks.worker_enabled = false;
+        // Tests purge with `purge_expired_audit_events` rather than waiting for the interval
+        c.audit.purge_enabled = false;
+        customise(&mut c);
         c
     };
 
@@ -241,7 +498,10 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application.");
     let address = format!("http://localhost:{}", application.port());
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    let metrics_port = application.metrics_port();
+    let shutdown = application.shutdown();
+    let setup_token = application.setup_token();
+    let server = tokio::spawn(application.run_until_stopped());
 
     let api_client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -252,14 +512,19 @@ pub async fn spawn_app() -> TestApp {
     let test_app = TestApp {
         address,
         port: application_port,
+        metrics_port,
         db_pool: get_connection_pool(&configuration.database),
         email_server,
         test_user: TestUser::generate(),
         api_client,
+        shutdown,
+        setup_token,
+        webhooks: configuration.webhooks.clone(),
+        server,
     };
     test_app.test_user.store(&test_app.db_pool).await;
 
-    return test_app;
+    test_app
 }
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
@@ -284,10 +549,10 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
     connection_pool
 }
 
-pub fn format_body<'a>(name: &str, email: &str) -> String {
+pub fn format_body(name: &str, email: &str) -> String {
     // Act
-    let _name = str::replace(&name, " ", "%20");
-    let _email = str::replace(&email, "@", "%40");
+    let _name = str::replace(name, " ", "%20");
+    let _email = str::replace(email, "@", "%40");
     format!("name={}&email={}", _name, _email)
 }
 
@@ -295,3 +560,51 @@ pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    create_unconfirmed_subscriber_with_email(app, "ursula_le_guin@gmail.com").await
+}
+
+pub async fn create_unconfirmed_subscriber_with_email(
+    app: &TestApp,
+    email: &str,
+) -> ConfirmationLinks {
+    let body = format_body("le guin", email);
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    create_confirmed_subscriber_with_email(app, "ursula_le_guin@gmail.com").await
+}
+
+pub async fn create_confirmed_subscriber_with_email(app: &TestApp, email: &str) {
+    let confirmation_links = create_unconfirmed_subscriber_with_email(app, email).await;
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
@@ -0,0 +1,272 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{body_partial_json, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, spawn_app, TestApp,
+};
+
+const DELIVERED_EMAIL: &str = "ursula_le_guin@gmail.com";
+const FAILED_EMAIL: &str = "octavia_butler@gmail.com";
+const MESSAGE_ID: &str = "b7bc2f4a-e38e-4336-af7d-e6c392c2f817";
+
+///
+/// Publish an issue to two subscribers, the first is accepted by the provider and the second fails
+///
+async fn publish_issue(app: &TestApp) -> (Uuid, serde_json::Value) {
+    create_confirmed_subscriber_with_email(app, DELIVERED_EMAIL).await;
+    create_confirmed_subscriber_with_email(app, FAILED_EMAIL).await;
+
+    let _delivered_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({ "To": DELIVERED_EMAIL }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+          "ErrorCode": 0,
+          "Message": "OK",
+          "MessageID": MESSAGE_ID,
+        })))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let _failed_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({ "To": FAILED_EMAIL })))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let response: serde_json::Value = app
+        .post_newsletters_json(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+              "text": "Newsletter body as plain text",
+              "html": "<p>Newsletter body as HTML</p>",
+            },
+        }))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let issue_id = response["issue_id"].as_str().unwrap().parse().unwrap();
+    (issue_id, response)
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+      "username": &app.test_user.username,
+      "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+fn assert_count(html: &str, name: &str, count: usize) {
+    assert!(
+        html.contains(&format!(r#"<dd id="{}">{}</dd>"#, name, count)),
+        "Expected {} to be {}",
+        name,
+        count
+    );
+}
+
+#[tokio::test]
+async fn failed_deliveries_do_not_stop_the_rest_of_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let (_, response) = publish_issue(&app).await;
+
+    // Assert
+    assert_eq!(response["warnings"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn every_send_is_written_to_the_delivery_log() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let (issue_id, _) = publish_issue(&app).await;
+
+    // Assert
+    let saved = sqlx::query!(
+        r#"
+          SELECT subscriber_email, status, message_id, error
+          FROM issue_delivery_log
+          WHERE newsletter_issue_id = $1
+          ORDER BY status
+        "#,
+        issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch delivery log");
+
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].subscriber_email, DELIVERED_EMAIL);
+    assert_eq!(saved[0].status, "delivered");
+    assert_eq!(saved[0].message_id.as_deref(), Some(MESSAGE_ID));
+    assert_eq!(saved[1].subscriber_email, FAILED_EMAIL);
+    assert_eq!(saved[1].status, "failed");
+    assert!(saved[1].error.is_some());
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_issue_delivery() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_issue_delivery(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn unknown_issues_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let response = app.get_issue_delivery(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issue_delivery_shows_counts_and_recipients() {
+    // Arrange
+    let app = spawn_app().await;
+    let (issue_id, _) = publish_issue(&app).await;
+    login(&app).await;
+
+    // Act
+    let html = app.get_issue_delivery(issue_id).await.text().await.unwrap();
+
+    // Assert
+    assert!(html.contains("<h1>Newsletter title</h1>"));
+    assert_count(&html, "targeted", 2);
+    assert_count(&html, "delivered", 1);
+    assert_count(&html, "failed", 1);
+    assert_count(&html, "bounced", 0);
+    assert_count(&html, "complained", 0);
+    assert_count(&html, "opened", 0);
+    assert_count(&html, "clicked", 0);
+    assert!(html.contains(DELIVERED_EMAIL));
+    assert!(html.contains(FAILED_EMAIL));
+    assert!(html.contains(MESSAGE_ID));
+}
+
+#[tokio::test]
+async fn webhooks_without_valid_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth(Uuid::new_v4().to_string(), Some(Uuid::new_v4().to_string()))
+        .json(&serde_json::json!({ "RecordType": "SpamComplaint", "MessageID": MESSAGE_ID }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        r#"Basic realm="webhooks""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn webhooks_with_the_admin_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "RecordType": "SpamComplaint", "MessageID": MESSAGE_ID }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn bounces_and_complaints_are_reported_on_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    let (issue_id, _) = publish_issue(&app).await;
+
+    // Act
+    app.post_postmark_webhook(serde_json::json!({
+      "RecordType": "Bounce",
+      "Type": "HardBounce",
+      "MessageID": MESSAGE_ID,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.post_postmark_webhook(serde_json::json!({
+      "RecordType": "SpamComplaint",
+      "MessageID": MESSAGE_ID,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    login(&app).await;
+    let html = app.get_issue_delivery(issue_id).await.text().await.unwrap();
+    assert_count(&html, "delivered", 0);
+    assert_count(&html, "bounced", 1);
+    assert_count(&html, "complained", 1);
+}
+
+#[tokio::test]
+async fn transient_bounces_and_unknown_messages_are_acknowledged_and_ignored() {
+    // Arrange
+    let app = spawn_app().await;
+    let (issue_id, _) = publish_issue(&app).await;
+
+    // Act
+    let transient = app
+        .post_postmark_webhook(serde_json::json!({
+          "RecordType": "Bounce",
+          "Type": "Transient",
+          "MessageID": MESSAGE_ID,
+        }))
+        .await;
+    let unknown = app
+        .post_postmark_webhook(serde_json::json!({
+          "RecordType": "SpamComplaint",
+          "MessageID": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(transient.status().as_u16(), 200);
+    assert_eq!(unknown.status().as_u16(), 200);
+
+    login(&app).await;
+    let html = app.get_issue_delivery(issue_id).await.text().await.unwrap();
+    assert_count(&html, "delivered", 1);
+    assert_count(&html, "bounced", 0);
+    assert_count(&html, "complained", 0);
+}
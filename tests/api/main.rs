@@ -1,8 +1,25 @@
+mod admin_cli;
 mod admin_dashboard;
+mod api_v1;
+mod audit_log;
 mod change_password;
+mod click_tracking;
+mod configuration;
+mod csrf;
 mod health_check;
 mod helpers;
+mod issue_delivery;
 mod login;
+mod metrics;
 mod newsletter;
+mod open_tracking;
+mod outbound_webhooks;
+mod personal_data;
+mod setup;
+mod shutdown;
+mod signup_protection;
+mod subscription_consent;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod telemetry;
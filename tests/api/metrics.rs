@@ -0,0 +1,106 @@
+use crate::helpers::{
+    create_confirmed_subscriber, spawn_app, spawn_app_with_configuration, TestApp,
+};
+
+async fn spawn_app_with_public_metrics() -> TestApp {
+    spawn_app_with_configuration(|c| c.application.public_metrics = true).await
+}
+
+#[tokio::test]
+async fn metrics_are_exposed_in_the_prometheus_text_format() {
+    // Arrange
+    let app = spawn_app_with_public_metrics().await;
+
+    // Act
+    let response = app.get_metrics().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+
+    let body = response.text().await.unwrap();
+    for family in [
+        "http_requests_total",
+        "http_request_duration_seconds",
+        "emails_sent_total",
+        "email_send_duration_seconds",
+        "db_pool_connections",
+    ] {
+        assert!(
+            body.contains(&format!("# TYPE {} ", family)),
+            "Missing {}",
+            family
+        );
+    }
+}
+
+#[tokio::test]
+async fn requests_are_labelled_by_route_pattern() {
+    // Arrange
+    let app = spawn_app_with_public_metrics().await;
+    app.get_health_check().await;
+    app.api_client
+        .get(format!(
+            "{}/admin/issues/{}",
+            app.address,
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let body = app.get_metrics().await.text().await.unwrap();
+
+    // Assert
+    assert!(
+        body.contains(r#"http_requests_total{method="GET",route="/health_check",status="200"}"#)
+    );
+    assert!(body.contains(r#"route="/admin/issues/{issue_id}""#));
+}
+
+#[tokio::test]
+async fn email_sends_and_subscriber_counts_are_recorded() {
+    // Arrange
+    let app = spawn_app_with_public_metrics().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let body = app.get_metrics().await.text().await.unwrap();
+
+    // Assert
+    assert!(body.contains(r#"emails_sent_total{outcome="success"}"#));
+    assert!(body.contains(r#"subscribers{status="confirmed"}"#));
+}
+
+#[tokio::test]
+async fn metrics_are_not_public_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_metrics().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn metrics_can_be_served_on_a_separate_port() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.application.metrics_port = Some(0)).await;
+    let metrics_port = app.metrics_port.unwrap();
+
+    // Act
+    let public_response = app.get_metrics().await;
+    let admin_response = reqwest::get(format!("http://localhost:{}/metrics", metrics_port))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(public_response.status().as_u16(), 404);
+    assert_eq!(admin_response.status().as_u16(), 200);
+}
@@ -5,7 +5,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::{spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
 
 #[tokio::test]
 async fn requests_missing_authorisation_are_rejected() {
@@ -14,7 +14,7 @@ async fn requests_missing_authorisation_are_rejected() {
 
     // Act
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .json(&serde_json::json!({
           "title": "Newsletter title",
           "content": {
@@ -115,6 +115,222 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     assert_eq!(response.status().as_u16(), 200);
 }
 
+#[tokio::test]
+async fn newsletters_are_personalised_for_each_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "content": {
+      "text": "Hi {{ subscriber.name }} from {{ attributes.company | default: \"nowhere\" }}. Leave: {{ unsubscribe_url }}",
+      "html": "<p>Hi {{ subscriber.name }}</p><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>",
+      }
+    });
+
+    let response = app.post_newsletters_json(newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+
+    assert!(text_body.starts_with("Hi le guin from nowhere. Leave: http://"));
+    assert!(text_body.contains("/subscriptions/unsubscribe?token="));
+    let headers = body["Headers"].as_array().unwrap();
+    assert_eq!(headers[0]["Name"], "List-Unsubscribe");
+    assert!(headers[0]["Value"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?token="));
+    assert_eq!(headers[1]["Name"], "List-Unsubscribe-Post");
+    assert_eq!(headers[1]["Value"], "List-Unsubscribe=One-Click");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Hi le guin</p>"));
+}
+
+#[tokio::test]
+async fn markdown_newsletters_are_delivered_as_html_and_text() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters_json(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "markdown": "# Hello {{ subscriber.name }}\n\nRead [the post](https://example.com/post).",
+          }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("Hello le guin</h1>"));
+    assert!(html_body.contains(r#"href="https://example.com/post""#));
+
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("# Hello le guin\n\n"));
+    assert!(text_body.contains("Read the post [1]."));
+    assert!(text_body.contains("[1] https://example.com/post"));
+}
+
+#[tokio::test]
+async fn explicit_parts_override_the_markdown_body() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters_json(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "markdown": "# Hello",
+            "text": "Hand written text",
+          }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+    assert_eq!(body["TextBody"].as_str().unwrap(), "Hand written text");
+    assert!(body["HtmlBody"].as_str().unwrap().contains("Hello</h1>"));
+}
+
+#[tokio::test]
+async fn newsletter_html_is_sanitised_and_styles_are_inlined() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters_json(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<style>p { color: red; }</style><p onclick=\"steal()\">Hi</p><script>alert(1)</script>",
+          }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(response_body["warnings"].as_array().unwrap().len(), 2);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["HtmlBody"].as_str().unwrap(),
+        r#"<p style="color: red;">Hi</p>"#
+    );
+}
+
+#[tokio::test]
+async fn newsletters_without_an_html_or_markdown_body_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_newsletters_json(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "text": "Newsletter body as plain text",
+          }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[rstest]
+#[case("{{ subscriber.password }}", "unknown variable")]
+#[case("{{ subscriber.name", "unclosed tag")]
+#[case("{{ subscriber.name | shout }}", "unknown filter")]
+#[tokio::test]
+async fn newsletters_with_invalid_templates_are_rejected(
+    #[case] text: String,
+    #[case] error_message: String,
+) {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters_json(serde_json::json!({
+          "title": "Newsletter title",
+          "content": {
+            "text": text,
+            "html": "<p>Newsletter body as HTML</p>",
+          }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(
+        400,
+        response.status().as_u16(),
+        "The API did not fail with a 400 Bad Request for an {}",
+        error_message
+    );
+}
+
 #[tokio::test]
 async fn non_existing_user_is_rejected() {
     // Arrange
@@ -124,7 +340,7 @@ async fn non_existing_user_is_rejected() {
     let password = Uuid::new_v4().to_string();
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
           "title": "Newsletter title",
@@ -180,40 +396,3 @@ async fn invalid_password_is_rejected() {
         response.headers()["WWW-Authenticate"]
     );
 }
-
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-
-    app.get_confirmation_links(&email_request)
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_links = create_unconfirmed_subscriber(app).await;
-
-    reqwest::get(confirmation_links.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
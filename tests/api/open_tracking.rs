@@ -0,0 +1,209 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use zero_to_production::configuration::get_configuration;
+use zero_to_production::domain::TrackingToken;
+
+use crate::helpers::{
+    create_confirmed_subscriber, spawn_app, spawn_app_with_configuration, TestApp,
+};
+
+const BROWSER_USER_AGENT: &str =
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko)";
+
+async fn publish_issue(app: &TestApp, track_opens: bool) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_newsletters_json(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+          "text": "Newsletter body as plain text",
+          "html": "<html><body><p>Newsletter body as HTML</p></body></html>",
+        },
+        "track_opens": track_opens,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+    body["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+fn tracking_pixel_url(app: &TestApp, html: &str) -> Option<reqwest::Url> {
+    let start = html.find("http://localhost/t/o/")?;
+    let end = start + html[start..].find('"')?;
+
+    let mut url = reqwest::Url::parse(&html[start..end]).unwrap();
+    url.set_port(Some(app.port)).unwrap();
+    Some(url)
+}
+
+async fn open(url: &reqwest::Url, user_agent: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(url.clone())
+        .header("User-Agent", user_agent)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn tracked_issues_embed_a_pixel_at_the_end_of_the_html() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let html = publish_issue(&app, true).await;
+
+    // Assert
+    assert!(tracking_pixel_url(&app, &html).is_some());
+    assert!(html.ends_with(r#"style="display: none;">"#));
+}
+
+#[tokio::test]
+async fn issues_do_not_embed_a_pixel_unless_requested() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let html = publish_issue(&app, false).await;
+
+    // Assert
+    assert!(tracking_pixel_url(&app, &html).is_none());
+}
+
+#[tokio::test]
+async fn issues_do_not_embed_a_pixel_when_tracking_is_disabled() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.tracking.enabled = false).await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let html = publish_issue(&app, true).await;
+
+    // Assert
+    assert!(tracking_pixel_url(&app, &html).is_none());
+}
+
+#[tokio::test]
+async fn opening_an_issue_serves_an_uncached_gif_and_records_the_open() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let html = publish_issue(&app, true).await;
+    let pixel_url = tracking_pixel_url(&app, &html).unwrap();
+
+    // Act
+    let response = open(&pixel_url, BROWSER_USER_AGENT).await;
+    open(&pixel_url, BROWSER_USER_AGENT).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    assert!(response.headers()["Cache-Control"]
+        .to_str()
+        .unwrap()
+        .contains("no-store"));
+
+    let saved = sqlx::query!(
+        "SELECT first_opened_at, last_opened_at, open_count, machine_open_count FROM issue_opens"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved open");
+
+    assert_eq!(saved.open_count, 2);
+    assert_eq!(saved.machine_open_count, 0);
+    assert!(saved.first_opened_at.unwrap() <= saved.last_opened_at.unwrap());
+}
+
+#[tokio::test]
+async fn proxy_and_prefetch_opens_are_counted_separately() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let html = publish_issue(&app, true).await;
+    let pixel_url = tracking_pixel_url(&app, &html).unwrap();
+
+    // Act
+    open(&pixel_url, "Mozilla/5.0").await;
+    open(&pixel_url, "Mozilla/5.0 (via ggpht.com GoogleImageProxy)").await;
+
+    // Assert
+    let saved =
+        sqlx::query!("SELECT first_opened_at, open_count, machine_open_count FROM issue_opens")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch saved open");
+
+    assert_eq!(saved.open_count, 0);
+    assert_eq!(saved.machine_open_count, 2);
+    assert!(saved.first_opened_at.is_none());
+}
+
+#[tokio::test]
+async fn opens_are_not_recorded_when_tracking_is_disabled() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.tracking.enabled = false).await;
+    create_confirmed_subscriber(&app).await;
+    publish_issue(&app, true).await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Simulate an issue which was published while tracking was still enabled
+    let issue = sqlx::query!("UPDATE newsletter_issues SET track_opens = true RETURNING id")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let hmac_secret = get_configuration().unwrap().application.hmac_secret;
+    let token = TrackingToken::new(issue.id, subscriber.id).encode(&hmac_secret);
+    let pixel_url = reqwest::Url::parse(&format!("{}/t/o/{}.gif", app.address, token)).unwrap();
+
+    // Act
+    let response = open(&pixel_url, BROWSER_USER_AGENT).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let opens = sqlx::query!("SELECT COUNT(*) AS count FROM issue_opens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(opens.count, Some(0));
+}
+
+#[tokio::test]
+async fn tampered_tokens_are_rejected_with_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let html = publish_issue(&app, true).await;
+    let mut pixel_url = tracking_pixel_url(&app, &html).unwrap();
+    let tampered_path = pixel_url.path().replacen("/t/o/", "/t/o/A", 1);
+    pixel_url.set_path(&tampered_path);
+
+    // Act
+    let response = open(&pixel_url, BROWSER_USER_AGENT).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,461 @@
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+use zero_to_production::webhook_events::sign_webhook;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    spawn_app_with_configuration, TestApp,
+};
+
+const ALL_EVENTS: [&str; 4] = [
+    "subscriber.subscribed",
+    "subscriber.confirmed",
+    "subscriber.unsubscribed",
+    "subscriber.bounced",
+];
+
+async fn webhook_receiver(status: u16) -> MockServer {
+    let receiver = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(status))
+        .mount(&receiver)
+        .await;
+    receiver
+}
+
+async fn received_events(receiver: &MockServer) -> Vec<serde_json::Value> {
+    receiver
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect()
+}
+
+fn event_types(events: &[serde_json::Value]) -> Vec<&str> {
+    events
+        .iter()
+        .map(|event| event["type"].as_str().unwrap())
+        .collect()
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+      "username": &app.test_user.username,
+      "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+#[tokio::test]
+async fn lifecycle_events_are_delivered_to_registered_endpoints() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = webhook_receiver(200).await;
+    app.add_webhook_endpoint(&format!("{}/hooks", receiver.uri()), &ALL_EVENTS)
+        .await;
+    let links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(links.html).await.unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    reqwest::Client::new()
+        .post(app.unsubscribe_link(subscriber_id))
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_webhooks().await;
+
+    // Assert
+    let events = received_events(&receiver).await;
+    assert_eq!(
+        event_types(&events),
+        [
+            "subscriber.subscribed",
+            "subscriber.confirmed",
+            "subscriber.unsubscribed"
+        ]
+    );
+    let subscriber = &events[0]["data"]["subscriber"];
+    assert_eq!(subscriber["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber["status"], "pending_confirmation");
+    assert_eq!(events[1]["data"]["subscriber"]["status"], "confirmed");
+    assert_eq!(events[2]["data"]["subscriber"]["status"], "unsubscribed");
+    assert_eq!(
+        events[0]["data"]["subscriber"]["id"],
+        events[2]["data"]["subscriber"]["id"]
+    );
+}
+
+#[tokio::test]
+async fn deliveries_are_signed_with_the_endpoint_secret() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = webhook_receiver(200).await;
+    let (_, secret) = app
+        .add_webhook_endpoint(&format!("{}/hooks", receiver.uri()), &ALL_EVENTS)
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    app.dispatch_all_pending_webhooks().await;
+
+    // Assert
+    let requests = receiver.received_requests().await.unwrap();
+    let request = &requests[0];
+    let header = |name: &str| {
+        request
+            .headers
+            .get(&name.parse().unwrap())
+            .unwrap()
+            .last()
+            .as_str()
+            .to_owned()
+    };
+
+    assert_eq!(header("Content-Type"), "application/json");
+    assert_eq!(header("Webhook-Event"), "subscriber.subscribed");
+    let delivery_id: Uuid = header("Webhook-Id").parse().unwrap();
+    let timestamp: i64 = header("Webhook-Timestamp").parse().unwrap();
+    let expected = sign_webhook(
+        &Secret::new(secret),
+        delivery_id,
+        timestamp,
+        std::str::from_utf8(&request.body).unwrap(),
+    );
+    assert_eq!(header("Webhook-Signature"), expected);
+}
+
+#[tokio::test]
+async fn endpoints_only_receive_the_events_they_registered_for() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = webhook_receiver(200).await;
+    app.add_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &["subscriber.confirmed"],
+    )
+    .await;
+
+    // Act
+    create_confirmed_subscriber(&app).await;
+    app.dispatch_all_pending_webhooks().await;
+
+    // Assert
+    let events = received_events(&receiver).await;
+    assert_eq!(event_types(&events), ["subscriber.confirmed"]);
+}
+
+#[tokio::test]
+async fn confirming_twice_only_announces_the_confirmation_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = webhook_receiver(200).await;
+    app.add_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &["subscriber.confirmed"],
+    )
+    .await;
+    let links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    reqwest::get(links.html.clone()).await.unwrap();
+    reqwest::get(links.html).await.unwrap();
+    app.dispatch_all_pending_webhooks().await;
+
+    // Assert
+    assert_eq!(received_events(&receiver).await.len(), 1);
+}
+
+#[tokio::test]
+async fn disabled_endpoints_are_not_sent_new_events() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = webhook_receiver(200).await;
+    let (endpoint_id, _) = app
+        .add_webhook_endpoint(&format!("{}/hooks", receiver.uri()), &ALL_EVENTS)
+        .await;
+    login(&app).await;
+    let response = app
+        .post_form(
+            &format!("/admin/webhooks/{}/enabled", endpoint_id),
+            &[("enabled", "false")],
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/webhooks/{}", endpoint_id));
+
+    // Act
+    create_unconfirmed_subscriber(&app).await;
+    app.dispatch_all_pending_webhooks().await;
+
+    // Assert
+    assert!(received_events(&receiver).await.is_empty());
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_later() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = MockServer::start().await;
+    app.add_webhook_endpoint(&format!("{}/hooks", receiver.uri()), &ALL_EVENTS)
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act - Part 1 - The endpoint is down
+    let failing = Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount_as_scoped(&receiver)
+        .await;
+    app.dispatch_all_pending_webhooks().await;
+    drop(failing);
+
+    // Assert - Part 1 - The delivery is left pending, and is not due again straight away
+    let delivery = sqlx::query!(
+        r#"
+      SELECT status, attempts, last_response_status, next_attempt_at > now() AS "backed_off!"
+      FROM webhook_deliveries
+    "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "pending");
+    assert_eq!(delivery.attempts, 1);
+    assert_eq!(delivery.last_response_status, Some(503));
+    assert!(delivery.backed_off);
+
+    // Act - Part 2 - The endpoint recovers once the delivery is due again
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+    sqlx::query!("UPDATE webhook_deliveries SET next_attempt_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_webhooks().await;
+
+    // Assert - Part 2
+    let delivery = sqlx::query!("SELECT status, attempts, delivered_at FROM webhook_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "delivered");
+    assert_eq!(delivery.attempts, 2);
+    assert!(delivery.delivered_at.is_some());
+}
+
+#[tokio::test]
+async fn deliveries_are_given_up_on_after_the_maximum_attempts() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.webhooks.max_attempts = 2).await;
+    let receiver = webhook_receiver(500).await;
+    app.add_webhook_endpoint(&format!("{}/hooks", receiver.uri()), &ALL_EVENTS)
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    app.dispatch_all_pending_webhooks().await;
+    sqlx::query!("UPDATE webhook_deliveries SET next_attempt_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_webhooks().await;
+
+    // Assert
+    let delivery = sqlx::query!("SELECT status, attempts, last_error FROM webhook_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.attempts, 2);
+    assert!(delivery.last_error.is_some());
+    assert_eq!(receiver.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn hard_bounces_are_announced() {
+    // Arrange
+    const MESSAGE_ID: &str = "6f2a8f4e-7d5c-4f39-9b0a-2b2f1f6e8c11";
+    let app = spawn_app().await;
+    let receiver = webhook_receiver(200).await;
+    app.add_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &["subscriber.bounced"],
+    )
+    .await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+          "ErrorCode": 0,
+          "Message": "OK",
+          "MessageID": MESSAGE_ID,
+        })))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters_json(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+          "text": "Newsletter body as plain text",
+          "html": "<p>Newsletter body as HTML</p>",
+        },
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    for _ in 0..2 {
+        app.post_postmark_webhook(serde_json::json!({
+          "RecordType": "Bounce",
+          "Type": "HardBounce",
+          "MessageID": MESSAGE_ID,
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+    app.dispatch_all_pending_webhooks().await;
+
+    // Assert
+    let events = received_events(&receiver).await;
+    assert_eq!(event_types(&events), ["subscriber.bounced"]);
+    assert_eq!(
+        events[0]["data"]["subscriber"]["email"],
+        "ursula_le_guin@gmail.com"
+    );
+}
+
+#[tokio::test]
+async fn the_background_worker_delivers_queued_events() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.webhooks.worker_enabled = true;
+        c.webhooks.poll_interval_milliseconds = 50;
+    })
+    .await;
+    let receiver = webhook_receiver(200).await;
+    app.add_webhook_endpoint(&format!("{}/hooks", receiver.uri()), &ALL_EVENTS)
+        .await;
+
+    // Act
+    create_unconfirmed_subscriber(&app).await;
+
+    // Assert
+    for _ in 0..100 {
+        if !receiver.received_requests().await.unwrap().is_empty() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("The worker did not deliver the webhook");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_webhooks() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let list = app.get_webhook_endpoints().await;
+    let create = app
+        .post_webhook_endpoints(&serde_json::json!({
+          "url": "https://example.com/hooks",
+          "subscriber.subscribed": "on",
+        }))
+        .await;
+    let detail = app.get_webhook_endpoint(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&list, "/login");
+    assert_is_redirect_to(&create, "/login");
+    assert_is_redirect_to(&detail, "/login");
+    let endpoints = sqlx::query!("SELECT id FROM webhook_endpoints")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(endpoints.is_empty());
+}
+
+#[tokio::test]
+async fn endpoints_added_from_the_dashboard_show_their_secret_and_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = webhook_receiver(200).await;
+    let url = format!("{}/hooks", receiver.uri());
+    login(&app).await;
+
+    // Act - Part 1 - Add the endpoint
+    let response = app
+        .post_webhook_endpoints(&serde_json::json!({
+          "url": &url,
+          "subscriber.subscribed": "on",
+        }))
+        .await;
+
+    // Assert - Part 1
+    let endpoint = sqlx::query!("SELECT id, secret, events FROM webhook_endpoints")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, &format!("/admin/webhooks/{}", endpoint.id));
+    assert_eq!(endpoint.events, ["subscriber.subscribed"]);
+    assert!(endpoint.secret.starts_with("whsec_"));
+
+    // Act - Part 2 - Deliver an event and look at the endpoint
+    create_unconfirmed_subscriber(&app).await;
+    app.dispatch_all_pending_webhooks().await;
+    let html = app
+        .get_webhook_endpoint(endpoint.id)
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert - Part 2
+    assert!(html.contains(&format!(r#"<code id="secret">{}</code>"#, endpoint.secret)));
+    assert!(html.contains("subscriber.subscribed"));
+    assert!(html.contains("delivered"));
+    let list = app.get_webhook_endpoints().await.text().await.unwrap();
+    assert!(list.contains(&url));
+}
+
+#[tokio::test]
+async fn endpoints_must_have_a_url_and_at_least_one_event() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let bad_url = app
+        .post_webhook_endpoints(&serde_json::json!({
+          "url": "ftp://example.com/hooks",
+          "subscriber.subscribed": "on",
+        }))
+        .await;
+    let no_events = app
+        .post_webhook_endpoints(&serde_json::json!({ "url": "https://example.com/hooks" }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&bad_url, "/admin/webhooks");
+    assert_is_redirect_to(&no_events, "/admin/webhooks");
+    let endpoints = sqlx::query!("SELECT id FROM webhook_endpoints")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(endpoints.is_empty());
+}
@@ -0,0 +1,266 @@
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+use zero_to_production::configuration::get_configuration;
+use zero_to_production::domain::DataExportToken;
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!("SELECT id FROM subscriptions WHERE email = $1", EMAIL)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+///
+/// Publish an issue carrying the self-service link to the confirmed subscriber, and return the
+/// link from the plain text part
+///
+async fn publish_issue_with_export_link(app: &TestApp) -> reqwest::Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+          "ErrorCode": 0,
+          "Message": "OK",
+          "MessageID": Uuid::new_v4().to_string(),
+        })))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters_json(json!({
+        "title": "Newsletter title",
+        "content": {
+          "text": "Your data: {{ data_export_url }}",
+          "html": "<p>Newsletter body as HTML</p>",
+        },
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = body["TextBody"].as_str().unwrap();
+    let link = linkify::LinkFinder::new()
+        .links(text)
+        .find(|l| l.as_str().contains("/subscriptions/export"))
+        .unwrap();
+
+    let mut link = reqwest::Url::parse(link.as_str()).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+fn export_link(app: &TestApp, token: &str) -> String {
+    format!("{}/subscriptions/export?token={}", app.address, token)
+}
+
+#[tokio::test]
+async fn the_export_includes_everything_held_on_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = MockServer::start().await;
+    app.add_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &["subscriber.confirmed"],
+    )
+    .await;
+    create_confirmed_subscriber(&app).await;
+    publish_issue_with_export_link(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    // Act
+    let response = app
+        .api_v1(
+            Method::GET,
+            &format!("/subscribers/{}/export", subscriber_id),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let export: Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+    assert_eq!(export["subscriber"]["status"], "confirmed");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(export["deliveries"][0]["issue_title"], "Newsletter title");
+    assert_eq!(export["deliveries"][0]["email"], EMAIL);
+    assert_eq!(export["webhook_events"][0]["type"], "subscriber.confirmed");
+    assert!(export["opens"].as_array().unwrap().is_empty());
+    assert!(export["clicks"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data_from_the_link_in_each_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = publish_issue_with_export_link(&app).await;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        r#"attachment; filename="subscriber-data.json""#
+    );
+    let export: Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+}
+
+#[tokio::test]
+async fn forged_and_expired_export_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    let hmac_secret = get_configuration().unwrap().application.hmac_secret;
+    let expired = DataExportToken::new(subscriber_id, chrono::Utc::now().timestamp() - 1)
+        .encode(&hmac_secret);
+    let forged = DataExportToken::new(subscriber_id, i64::MAX)
+        .encode(&secrecy::Secret::new("not-the-secret".to_string()));
+
+    for token in [expired.as_str(), forged.as_str(), "garbage"] {
+        // Act
+        let response = reqwest::get(export_link(&app, token)).await.unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401, "Accepted {}", token);
+    }
+}
+
+#[tokio::test]
+async fn erasure_removes_the_subscriber_from_every_table() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = MockServer::start().await;
+    app.add_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &["subscriber.subscribed", "subscriber.confirmed"],
+    )
+    .await;
+    create_confirmed_subscriber(&app).await;
+    let link = publish_issue_with_export_link(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    // Act
+    let response = app
+        .api_v1(
+            Method::POST,
+            &format!("/subscribers/{}/erase", subscriber_id),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let remaining = sqlx::query!(
+        r#"
+      SELECT
+        (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+        (SELECT COUNT(*) FROM subscription_tokens) AS "tokens!",
+        (SELECT COUNT(*) FROM issue_delivery_log) AS "deliveries!",
+        (SELECT COUNT(*) FROM webhook_deliveries) AS "webhooks!",
+        (SELECT COUNT(*) FROM suppressed_emails) AS "suppressed!"
+    "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining.subscriptions, 0);
+    assert_eq!(remaining.tokens, 0);
+    assert_eq!(remaining.deliveries, 0);
+    assert_eq!(remaining.webhooks, 0);
+    assert_eq!(remaining.suppressed, 1);
+
+    let suppressed = sqlx::query_scalar!("SELECT email_hash FROM suppressed_emails")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!suppressed.contains('@'));
+
+    // The old link finds nothing
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn erased_addresses_cannot_be_added_back_through_the_api() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    app.api_v1(
+        Method::POST,
+        &format!("/subscribers/{}/erase", subscriber_id),
+    )
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+
+    // Act - the same mailbox, written differently
+    let response = app
+        .api_v1(Method::POST, "/subscribers")
+        .json(&json!({ "email": "Ursula_Le_Guin@gmail.com", "name": "le guin" }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "conflict");
+}
+
+#[tokio::test]
+async fn erasing_an_unknown_subscriber_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let erase = app
+        .api_v1(
+            Method::POST,
+            &format!("/subscribers/{}/erase", Uuid::new_v4()),
+        )
+        .send()
+        .await
+        .unwrap();
+    let export = app
+        .api_v1(
+            Method::GET,
+            &format!("/subscribers/{}/export", Uuid::new_v4()),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(erase.status().as_u16(), 404);
+    assert_eq!(export.status().as_u16(), 404);
+    let suppressed = sqlx::query!("SELECT email_hash FROM suppressed_emails")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(suppressed.is_empty());
+}
@@ -0,0 +1,157 @@
+use reqwest::Url;
+use zero_to_production::bootstrap::bootstrap;
+use zero_to_production::configuration::Environment;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const PASSWORD: &str = "a-long-enough-password";
+const LEGACY_ADMIN_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$ZegXdWjxHc6jQE+2L+CtDw$84vf+M9sDkwsWLMegUsdwezd9qrt9OeGT1/ybKfxIYY";
+
+///
+/// Spawn an app and remove the test user, as if it had been deployed against an empty database
+///
+async fn spawn_app_without_users() -> (TestApp, String) {
+    let app = spawn_app().await;
+    sqlx::query!("DELETE FROM users")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let token = app
+        .setup_token
+        .clone()
+        .expect("A setup token is issued when there are no users");
+    (app, token)
+}
+
+fn setup_form(token: &str, password_check: &str) -> serde_json::Value {
+    serde_json::json!({
+      "token": token,
+      "username": "owner",
+      "password": PASSWORD,
+      "password_check": password_check,
+    })
+}
+
+#[tokio::test]
+async fn the_seeded_admin_user_is_removed_by_the_migrations() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let admin = sqlx::query!("SELECT user_id FROM users WHERE username = 'admin'")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(admin.is_none());
+}
+
+#[tokio::test]
+async fn setup_is_not_found_once_a_user_exists() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.setup_token.clone().unwrap();
+
+    // Act
+    let response = app.get_setup(&token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn setup_is_not_found_without_the_right_token() {
+    // Arrange
+    let (app, _) = spawn_app_without_users().await;
+
+    // Act
+    let response = app.get_setup("not-the-token").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_owner_account_can_be_created_with_the_setup_token() {
+    // Arrange
+    let (app, token) = spawn_app_without_users().await;
+    let form = app.get_setup(&token).await;
+    assert_eq!(form.status().as_u16(), 200);
+
+    // Act
+    let response = app.post_setup(&setup_form(&token, PASSWORD)).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("<p><i>Your account has been created, you can now log in</i></p>"));
+
+    let response = app
+        .post_login(&serde_json::json!({
+          "username": "owner",
+          "password": PASSWORD,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_setup_token_can_only_be_used_once() {
+    // Arrange
+    let (app, token) = spawn_app_without_users().await;
+    app.post_setup(&setup_form(&token, PASSWORD)).await;
+    // Even with the account gone again, the token must not be reusable
+    sqlx::query!("DELETE FROM users")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.post_setup(&setup_form(&token, PASSWORD)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn mismatched_passwords_are_rejected_without_using_the_token() {
+    // Arrange
+    let (app, token) = spawn_app_without_users().await;
+
+    // Act
+    let response = app
+        .post_setup(&setup_form(&token, "a-different-password"))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/setup?token={}", token));
+    let html = app.get_setup(&token).await.text().await.unwrap();
+    assert!(html.contains("You entered two different passwords"));
+}
+
+#[tokio::test]
+async fn production_refuses_to_start_while_the_legacy_admin_password_works() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        "INSERT INTO users (user_id, username, password_hash) VALUES ($1, 'admin', $2)",
+        uuid::Uuid::new_v4(),
+        LEGACY_ADMIN_HASH
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let base_url = Url::parse("http://localhost").unwrap();
+
+    // Act
+    let production = bootstrap(&app.db_pool, Environment::Production, &base_url).await;
+    let local = bootstrap(&app.db_pool, Environment::Local, &base_url).await;
+
+    // Assert
+    assert!(production.is_err());
+    assert!(local.is_ok());
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, spawn_app_with_configuration};
+
+#[tokio::test]
+async fn readiness_fails_as_soon_as_shutdown_begins() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.shutdown.drain_delay_seconds = 2).await;
+
+    // Act
+    app.shutdown.trigger();
+    let response = app.get_health_ready().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["shutting_down"], true);
+    // Liveness is unaffected, we are draining rather than broken
+    assert_eq!(app.get_health_live().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn in_flight_requests_are_allowed_to_finish() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let publish = app.post_newsletters_json(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+          "text": "Newsletter body as plain text",
+          "html": "<p>Newsletter body as HTML</p>",
+        },
+    }));
+    let shutdown = async {
+        // Let the publish request reach the email provider first
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        app.shutdown.trigger();
+    };
+
+    // Act
+    let (response, _) = tokio::join!(publish, shutdown);
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_server_stops_once_shutdown_completes() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.shutdown.trigger();
+    let outcome = tokio::time::timeout(Duration::from_secs(5), app.server)
+        .await
+        .expect("Server did not stop in time")
+        .unwrap();
+
+    // Assert
+    assert!(outcome.is_ok());
+    assert!(reqwest::get(format!("{}/health/live", &app.address))
+        .await
+        .is_err());
+}
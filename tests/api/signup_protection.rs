@@ -0,0 +1,274 @@
+use chrono::Utc;
+use secrecy::Secret;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero_to_production::configuration::get_configuration;
+use zero_to_production::domain::{verify_proof_of_work, SignupFormToken};
+
+use crate::helpers::{format_body, spawn_app, spawn_app_with_configuration, TestApp};
+
+/// The test apps sign with the secret from the checked in configuration
+fn hmac_secret() -> Secret<String> {
+    get_configuration().unwrap().application.hmac_secret
+}
+
+fn form_token(seconds_ago: i64) -> String {
+    SignupFormToken::new(Utc::now().timestamp() - seconds_ago).encode(&hmac_secret())
+}
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn signups_over_the_per_ip_limit_get_a_429_with_retry_after() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.signup.per_ip_limit = 2;
+        c.signup.per_ip_window_seconds = 60;
+    })
+    .await;
+    mock_email_server(&app).await;
+
+    // Act
+    for email in ["ursula_le_guin@gmail.com", "octavia_butler@gmail.com"] {
+        let response = app.post_subscriptions(format_body("le guin", email)).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app
+        .post_subscriptions(format_body("le guin", "n_k_jemisin@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+}
+
+#[tokio::test]
+async fn invalid_signups_count_towards_the_per_ip_limit() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.signup.per_ip_limit = 1).await;
+
+    // Act
+    let invalid = app.post_subscriptions("name=&email=".into()).await;
+    let valid = app
+        .post_subscriptions(format_body("le guin", "ursula_le_guin@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(valid.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn repeated_signups_for_one_address_are_limited_whatever_the_case() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.signup.per_email_limit = 1).await;
+    mock_email_server(&app).await;
+
+    // Act
+    let first = app
+        .post_subscriptions(format_body("le guin", "ursula_le_guin@gmail.com"))
+        .await;
+    let second = app
+        .post_subscriptions(format_body("le guin", "Ursula_Le_Guin@gmail.com"))
+        .await;
+    let another_address = app
+        .post_subscriptions(format_body("butler", "octavia_butler@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+    assert_eq!(another_address.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn signups_filling_in_the_honeypot_look_successful_but_do_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(format!(
+            "{}&website=http%3A%2F%2Fspam.example.com",
+            format_body("le guin", "ursula_le_guin@gmail.com")
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn signups_without_a_form_token_are_rejected() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.signup.min_fill_seconds = 3).await;
+
+    // Act
+    let response = app
+        .post_subscriptions(format_body("le guin", "ursula_le_guin@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn forms_submitted_too_quickly_are_rejected() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.signup.min_fill_seconds = 3).await;
+    mock_email_server(&app).await;
+    let body = format_body("le guin", "ursula_le_guin@gmail.com");
+
+    // Act
+    let too_quick = app
+        .post_subscriptions(format!("{}&form_token={}", body, form_token(0)))
+        .await;
+    let in_time = app
+        .post_subscriptions(format!("{}&form_token={}", body, form_token(10)))
+        .await;
+
+    // Assert
+    assert_eq!(too_quick.status().as_u16(), 400);
+    assert_eq!(in_time.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn forged_and_expired_form_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.signup.min_fill_seconds = 3).await;
+    let body = format_body("le guin", "ursula_le_guin@gmail.com");
+    let forged =
+        SignupFormToken::new(Utc::now().timestamp() - 10).encode(&Secret::new("forged".into()));
+
+    // Act
+    let forged = app
+        .post_subscriptions(format!("{}&form_token={}", body, forged))
+        .await;
+    let expired = app
+        .post_subscriptions(format!(
+            "{}&form_token={}",
+            body,
+            form_token(2 * 24 * 60 * 60)
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(forged.status().as_u16(), 400);
+    assert_eq!(expired.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_form_token_can_only_be_used_for_one_signup() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.signup.min_fill_seconds = 3).await;
+    mock_email_server(&app).await;
+    let token = form_token(10);
+
+    // Act
+    // A rejected address doesn't use the token up, so the subscriber can correct it
+    let invalid = app
+        .post_subscriptions(format!(
+            "{}&form_token={}",
+            format_body("le guin", "not-an-email"),
+            token
+        ))
+        .await;
+    let first = app
+        .post_subscriptions(format!(
+            "{}&form_token={}",
+            format_body("le guin", "ursula_le_guin@gmail.com"),
+            token
+        ))
+        .await;
+    let replayed = app
+        .post_subscriptions(format!(
+            "{}&form_token={}",
+            format_body("octavia butler", "octavia_butler@gmail.com"),
+            token
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(replayed.status().as_u16(), 400);
+
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+}
+
+#[tokio::test]
+async fn a_proof_of_work_is_required_when_enabled() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.signup.proof_of_work_difficulty = 8).await;
+    mock_email_server(&app).await;
+    let body = format_body("le guin", "ursula_le_guin@gmail.com");
+    let token = form_token(0);
+    let solution = (0..)
+        .map(|n: u32| n.to_string())
+        .find(|n| verify_proof_of_work(&token, n, 8))
+        .unwrap();
+
+    // Act
+    let unsolved = app
+        .post_subscriptions(format!("{}&form_token={}", body, token))
+        .await;
+    let solved = app
+        .post_subscriptions(format!(
+            "{}&form_token={}&proof_of_work={}",
+            body, token, solution
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(unsolved.status().as_u16(), 400);
+    assert_eq!(solved.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_home_page_renders_the_signup_form_with_a_valid_token() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.signup.proof_of_work_difficulty = 12).await;
+
+    // Act
+    let html = app.get_home_html().await;
+
+    // Assert
+    assert!(html.contains(r#"action="/subscriptions""#));
+    assert!(html.contains(r#"name="website""#));
+    assert!(html.contains(r#"data-proof-of-work-difficulty="12""#));
+
+    let token = html
+        .split(r#"name="form_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    let token = SignupFormToken::decode(token, &hmac_secret()).unwrap();
+    assert!(Utc::now().timestamp() - token.issued_at < 5);
+}
@@ -0,0 +1,350 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero_to_production::configuration::get_configuration;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_unconfirmed_subscriber, spawn_app, spawn_app_with_configuration,
+    TestApp,
+};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+struct ConsentRecord {
+    consent_text: String,
+    consent_version: String,
+    source: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+async fn consent_records(app: &TestApp) -> Vec<ConsentRecord> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+      SELECT consent_text, consent_version, source, ip_address, user_agent, confirmed_at
+      FROM subscription_consents
+      ORDER BY given_at
+    "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+fn signup_form(extra: &str) -> String {
+    format!("name=le%20guin&email=ursula_le_guin%40gmail.com{}", extra)
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+#[tokio::test]
+async fn the_home_page_shows_the_consent_being_asked_for() {
+    // Arrange
+    let app = spawn_app().await;
+    let signup = get_configuration().unwrap().signup;
+
+    // Act
+    let html = app.get_home_html().await;
+
+    // Assert
+    assert!(html.contains(&signup.consent_text));
+    assert!(html.contains(&format!(
+        r#"name="consent_version" value="{}""#,
+        signup.consent_version
+    )));
+}
+
+#[tokio::test]
+async fn subscribing_from_the_home_page_records_consent() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let signup = get_configuration().unwrap().signup;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "Mozilla/5.0 (X11; Linux x86_64)")
+        .body(signup_form(&format!(
+            "&consent_version={}&source=home",
+            signup.consent_version
+        )))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let records = consent_records(&app).await;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].consent_text, signup.consent_text);
+    assert_eq!(records[0].consent_version, signup.consent_version);
+    assert_eq!(records[0].source, "home");
+    assert_eq!(records[0].ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(
+        records[0].user_agent.as_deref(),
+        Some("Mozilla/5.0 (X11; Linux x86_64)")
+    );
+    assert!(records[0].confirmed_at.is_none());
+}
+
+#[tokio::test]
+async fn the_source_defaults_to_the_kind_of_request() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    // Act
+    app.post_subscriptions(signup_form(""))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_subscriptions_json(&serde_json::json!({
+        "name": "Octavia Butler",
+        "email": "octavia_butler@gmail.com",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let sources: Vec<_> = consent_records(&app)
+        .await
+        .into_iter()
+        .map(|r| r.source)
+        .collect();
+    assert_eq!(sources, ["form", "json"]);
+}
+
+#[tokio::test]
+async fn invalid_sources_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions(signup_form("&source=%3Cscript%3E"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(consent_records(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn signups_from_a_stale_form_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions(signup_form("&consent_version=2000-01-01"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn the_client_ip_is_taken_from_trusted_proxies() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.application.trusted_proxies = vec!["127.0.0.0/8".parse().unwrap()];
+    })
+    .await;
+    mock_email_server(&app).await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", "1.2.3.4, 198.51.100.7")
+        .body(signup_form(""))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let records = consent_records(&app).await;
+    assert_eq!(records[0].ip_address.as_deref(), Some("198.51.100.7"));
+}
+
+#[tokio::test]
+async fn forwarded_headers_are_ignored_without_trusted_proxies() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", "198.51.100.7")
+        .body(signup_form(""))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let records = consent_records(&app).await;
+    assert_eq!(records[0].ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn confirming_the_subscription_stamps_the_consent() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let records = consent_records(&app).await;
+    assert!(records[0].confirmed_at.is_some());
+}
+
+#[tokio::test]
+async fn consent_records_cannot_be_rewritten() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let rewrite = sqlx::query!("UPDATE subscription_consents SET consent_text = 'Something else'")
+        .execute(&app.db_pool)
+        .await;
+    let confirm = sqlx::query!("UPDATE subscription_consents SET confirmed_at = now()")
+        .execute(&app.db_pool)
+        .await;
+    let reconfirm = sqlx::query!("UPDATE subscription_consents SET confirmed_at = now()")
+        .execute(&app.db_pool)
+        .await;
+
+    // Assert
+    assert!(rewrite.is_err());
+    assert!(confirm.is_ok());
+    assert!(reconfirm.is_err());
+}
+
+#[tokio::test]
+async fn the_admin_subscriber_page_shows_the_consent_records() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions WHERE email = $1", EMAIL)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let signup = get_configuration().unwrap().signup;
+    login(&app).await;
+
+    // Act
+    let response = app.get_admin_subscriber(subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(EMAIL));
+    assert!(html.contains(&signup.consent_version));
+    assert!(html.contains("127.0.0.1"));
+
+    let response = app.get_admin_subscriber(Uuid::new_v4()).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_subscriber(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn consent_records_are_part_of_the_data_export() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions WHERE email = $1", EMAIL)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let export: serde_json::Value = app
+        .api_v1(
+            reqwest::Method::GET,
+            &format!("/subscribers/{}/export", subscriber_id),
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(export["consents"][0]["source"], "form");
+    assert_eq!(export["consents"][0]["ip_address"], "127.0.0.1");
+}
+
+#[tokio::test]
+async fn the_subscriber_search_is_escaped() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let html = app
+        .api_client
+        .get(format!("{}/admin/subscribers", &app.address))
+        .query(&[("email", r#""><script>alert(1)</script>"#)])
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(!html.contains("<script>"));
+    assert!(html.contains(r#"value="&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;""#));
+}
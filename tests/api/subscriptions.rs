@@ -3,7 +3,13 @@ use sqlx::query;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-use crate::helpers::{format_body, spawn_app};
+use uuid::Uuid;
+use zero_to_production::bootstrap::normalise_stored_emails;
+
+use crate::helpers::{
+    create_confirmed_subscriber_with_email, create_unconfirmed_subscriber_with_email, format_body,
+    spawn_app,
+};
 
 #[rstest]
 #[case("le guin", "guin@email.com")]
@@ -150,7 +156,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link(
 
     // Assert
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
@@ -173,3 +179,368 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[rstest]
+#[case("ursula@mailinator.com", "disposable")]
+#[case("postmaster@earthsea.org", "role account")]
+#[case("ursula@gmial.com", "did you mean ursula@gmail.com?")]
+#[tokio::test]
+async fn subscribe_returns_a_400_explaining_why_an_address_is_not_accepted(
+    #[case] email: String,
+    #[case] explanation: String,
+) {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_subscriptions(format_body("le guin", &email)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains(&explanation));
+}
+
+#[tokio::test]
+async fn a_suspected_typo_is_accepted_once_the_subscriber_confirms_it() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = format_body("le guin", "ursula@gmial.com");
+
+    // Act
+    let page = app.post_subscriptions(body.clone()).await;
+    let json = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula@gmial.com",
+        }))
+        .await;
+    let confirmed = app
+        .post_subscriptions(format!("{}&confirmed_domain=GMIAL.com", body))
+        .await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 400);
+    let html = page.text().await.unwrap();
+    assert!(html.contains(r#"name="confirmed_domain" value="gmial.com""#));
+    assert!(html.contains(r#"name="email" value="ursula@gmail.com""#));
+
+    assert_eq!(json.status().as_u16(), 400);
+    let json: serde_json::Value = json.json().await.unwrap();
+    assert_eq!(json["error"], "possible_typo");
+    assert!(json["fields"]["email"]
+        .as_str()
+        .unwrap()
+        .contains("did you mean ursula@gmail.com?"));
+
+    assert_eq!(confirmed.status().as_u16(), 200);
+    let saved = query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@gmial.com");
+}
+
+#[tokio::test]
+async fn addresses_stored_before_normalisation_are_normalised_like_new_signups() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // As left by the migration adding `normalised_email`, which could only lowercase
+    for (email, normalised) in [
+        ("Ursula.Le.Guin@gmail.com", Some("ursula.le.guin@gmail.com")),
+        ("octavia@example.com", Some("octavia@example.com")),
+        ("Octavia@example.com", None),
+    ] {
+        query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, normalised_email)
+            VALUES ($1, $2, 'someone', now(), 'pending_confirmation', $3)
+            "#,
+            Uuid::new_v4(),
+            email,
+            normalised,
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    query!("DELETE FROM email_normalisation")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    normalise_stored_emails(&app.db_pool, true).await.unwrap();
+    let again = app
+        .post_subscriptions(format_body("le guin", "UrsulaLe.Guin@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(again.status().as_u16(), 200);
+    let normalised: Vec<_> =
+        query!(r#"SELECT email, normalised_email FROM subscriptions ORDER BY email COLLATE "C""#)
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| (row.email, row.normalised_email))
+            .collect();
+    assert_eq!(
+        normalised,
+        [
+            ("Octavia@example.com".to_string(), None),
+            (
+                "Ursula.Le.Guin@gmail.com".to_string(),
+                Some("ursulaleguin@gmail.com".to_string())
+            ),
+            (
+                "octavia@example.com".to_string(),
+                Some("octavia@example.com".to_string())
+            ),
+        ]
+    );
+
+    // Turning folding off would now let every Gmail subscriber sign up a second time
+    assert!(normalise_stored_emails(&app.db_pool, false).await.is_err());
+    assert!(normalise_stored_emails(&app.db_pool, true).await.is_ok());
+}
+
+#[rstest]
+#[case("ursula_le_guin@gmail.com", "Ursula_Le_Guin@GMAIL.com")]
+#[case("ursulaleguin@gmail.com", "ursula.le.guin+news@googlemail.com")]
+#[tokio::test]
+async fn signing_up_the_same_mailbox_again_resends_the_confirmation(
+    #[case] first: String,
+    #[case] again: String,
+) {
+    // Arrange
+    let app = spawn_app().await;
+    let first_links = create_unconfirmed_subscriber_with_email(&app, &first).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = format!("name=le%20guin&email={}", urlencoding::encode(&again));
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subscribers = query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].email, first);
+
+    // Either link confirms the one subscription
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let second_links = app.get_confirmation_links(&email_request);
+    assert_ne!(first_links.html, second_links.html);
+    reqwest::get(second_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn signing_up_a_confirmed_mailbox_again_changes_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula_le_guin@gmail.com").await;
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(format_body("le guin", "Ursula_Le_Guin@gmail.com"))
+        .await;
+
+    // Assert
+    // The same answer as for a new address, so subscriptions can't be probed
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subscriber = query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.status, "confirmed");
+}
+
+#[tokio::test]
+async fn internationalised_addresses_are_stored_with_a_punycode_domain() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = format!(
+        "name=le%20guin&email={}",
+        urlencoding::encode("josé@Bücher.example")
+    );
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subscriber = query!("SELECT email, normalised_email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.email, "josé@xn--bcher-kva.example");
+    assert_eq!(
+        subscriber.normalised_email.as_deref(),
+        Some("josé@xn--bcher-kva.example")
+    );
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_and_answers_in_json() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+
+    let subscriber = query!("SELECT email, name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.email, "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber.name, "le guin");
+}
+
+#[tokio::test]
+async fn json_validation_errors_are_reported_per_field() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "",
+            "email": "definitely-not-an-email",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "invalid_fields");
+    assert!(body["fields"]["name"].is_string());
+    assert!(body["fields"]["email"]
+        .as_str()
+        .unwrap()
+        .contains("definitely-not-an-email"));
+}
+
+#[tokio::test]
+async fn missing_json_fields_are_reported_as_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({ "name": "le guin" }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let fields = body["fields"].as_object().unwrap();
+    assert_eq!(fields.keys().collect::<Vec<_>>(), vec!["email"]);
+}
+
+#[rstest]
+#[case("application/json", "application/json")]
+#[case("text/html", "text/html")]
+#[case("*/*", "text/html")]
+#[tokio::test]
+async fn form_submissions_answer_in_the_format_the_client_accepts(
+    #[case] accept: &str,
+    #[case] expected_content_type: &str,
+) {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", accept)
+        .body(format_body("le guin", "not-an-email"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let content_type = response.headers()["Content-Type"].to_str().unwrap();
+    assert!(content_type.starts_with(expected_content_type));
+}
+
+#[tokio::test]
+async fn json_submissions_can_ask_for_html() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept", "text/html")
+        .json(&serde_json::json!({ "name": "le guin", "email": "not-an-email" }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("not-an-email is not a valid a subscriber email."));
+}
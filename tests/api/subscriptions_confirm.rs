@@ -40,7 +40,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called(
 
     app.post_subscriptions(body).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     // Act
     let response = reqwest::get(confirmation_links.html).await.unwrap();
@@ -69,7 +69,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscription(
 
     app.post_subscriptions(body).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     // Act
     reqwest::get(confirmation_links.html)
@@ -93,6 +93,63 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscription(
 }
 
 // Not enough process to handle this test
+#[rstest]
+#[case(None, "text/html")]
+#[case(Some("text/html"), "text/html")]
+#[case(Some("application/json"), "application/json")]
+#[tokio::test]
+async fn confirmation_answers_in_the_format_the_client_accepts(
+    #[case] accept: Option<&str>,
+    #[case] expected_content_type: &str,
+) {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(format_body("le guin", "ursula_le_guin@gmail.com"))
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // Act
+    let mut request = app.api_client.get(confirmation_links.html);
+    if let Some(accept) = accept {
+        request = request.header("Accept", accept);
+    }
+    let response = request.send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let content_type = response.headers()["Content-Type"].to_str().unwrap();
+    assert!(content_type.starts_with(expected_content_type));
+}
+
+#[tokio::test]
+async fn unknown_tokens_are_reported_in_json_when_asked_for() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=unknown",
+            app.address
+        ))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "unknown_token");
+}
+
 // #[tokio::test]
 // async fn confirm_subscription_fails_if_there_is_a_fatal_database_error() {
 //     // Arrange
@@ -0,0 +1,164 @@
+use sqlx::query;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, format_body, spawn_app, TestApp};
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription")
+        .id
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription")
+        .status
+}
+
+#[tokio::test]
+async fn unsubscribing_without_a_token_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = format!("{}/subscriptions/unsubscribe", app.address);
+
+    // Act
+    let get = reqwest::get(&url).await.unwrap();
+    let post = reqwest::Client::new().post(&url).send().await.unwrap();
+
+    // Assert
+    assert_eq!(get.status().as_u16(), 400);
+    assert_eq!(post.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribing_with_an_invalid_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/unsubscribe?token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_asks_for_confirmation_without_unsubscribing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.unsubscribe_link(subscriber_id(&app).await);
+
+    // Act
+    let response = reqwest::get(&link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<form action="/subscriptions/unsubscribe" method="post">"#));
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn submitting_the_confirmation_form_unsubscribes() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = reqwest::Url::parse(&app.unsubscribe_link(subscriber_id(&app).await)).unwrap();
+    let token = link
+        .query_pairs()
+        .find(|(name, _)| name == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/unsubscribe", app.address))
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("You have been unsubscribed"));
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+
+    // The link now says so instead of asking again
+    let html = reqwest::get(link).await.unwrap().text().await.unwrap();
+    assert!(html.contains("You have been unsubscribed"));
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_posts_to_the_link_itself() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.unsubscribe_link(subscriber_id(&app).await);
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(&link)
+        .form(&[("List-Unsubscribe", "One-Click")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn the_confirmation_token_does_not_unsubscribe() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = format_body("le guin", "ursula_le_guin@gmail.com");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link.clone()).await.unwrap();
+    let subscription_token = confirmation_link
+        .query_pairs()
+        .find(|(name, _)| name == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/unsubscribe", app.address))
+        .form(&[("token", subscription_token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
@@ -0,0 +1,49 @@
+use wiremock::{
+    matchers::{header_regex, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
+
+#[tokio::test]
+async fn the_trace_context_of_a_publish_request_is_propagated_to_the_email_provider() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(header_regex(
+            "traceparent",
+            &format!("^00-{}-[0-9a-f]{{16}}-01$", trace_id),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header(
+            "traceparent",
+            format!("00-{}-00f067aa0ba902b7-01", trace_id),
+        )
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+              "text": "Newsletter body as plain text",
+              "html": "<p>Newsletter body as HTML</p>",
+            },
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    // Mock verifies on drop that the email was sent within the caller's trace
+}